@@ -0,0 +1,235 @@
+// Throughput of stanza parsing on the chariz and jammy fixtures
+// Run with `cargo bench`, which builds with optimizations
+//
+// "regex parse_kv" is the scanner parse_kv used before, and is kept here to
+// compare against. The jammy Packages fixture isn't part of the repository,
+// so jammy is covered by its Release file
+use apt_parser::{
+	case_map::CaseMap,
+	errors::{ErrorContext, KVError, Warning, WarningKind},
+	Deb822, PackageRef, Packages, Paragraph, Release,
+};
+use regex::Regex;
+use std::{
+	fs::read_to_string,
+	time::{Duration, Instant},
+};
+
+// Runs f until a second has passed and prints how fast data went through it
+fn bench<F: FnMut() -> usize>(name: &str, data: &str, mut f: F) {
+	// Warm up, and keep the results around so nothing is optimized out
+	let mut total = f();
+	let start = Instant::now();
+	let mut runs = 0;
+
+	while start.elapsed() < Duration::from_secs(1) {
+		total = total.wrapping_add(f());
+		runs += 1;
+	}
+
+	let elapsed = start.elapsed().as_secs_f64() / runs as f64;
+	let throughput = data.len() as f64 / elapsed / (1024.0 * 1024.0);
+	println!(
+		"{:<40} {:>10.1} µs/iter {:>10.1} MiB/s ({})",
+		name,
+		elapsed * 1_000_000.0,
+		throughput,
+		total % 10
+	);
+}
+
+// parse_kv as it was before the byte scanner, kept as close to the original as
+// the public API allows: the regex is compiled on every call, every line builds
+// an ErrorContext, and multiline values are rebuilt with format! per line
+fn regex_parse_kv(raw_apt_data: &str) -> Result<(CaseMap, Vec<Warning>), KVError> {
+	let binding = match raw_apt_data.contains(['\r', '\0']) {
+		true => raw_apt_data.replace("\r\n", "\n").replace('\0', ""),
+		false => raw_apt_data.to_string(),
+	};
+	let apt_data = binding.trim();
+
+	let mut offset = binding.len() - binding.trim_start().len();
+	let first_line = binding[..offset].matches('\n').count() + 1;
+
+	let mut fields = CaseMap::new();
+	let mut warnings = Vec::new();
+	let mut current_key = "";
+
+	let regex = match Regex::new(r"^(.*?):\s(.*)$") {
+		Ok(regex) => regex,
+		Err(_) => return Err(KVError::default()),
+	};
+
+	for (index, raw_line) in apt_data.split('\n').enumerate() {
+		let indent = raw_line.len() - raw_line.trim_start().len();
+		let context = ErrorContext {
+			offset: Some(offset + indent),
+			line: Some(first_line + index),
+			column: Some(indent + 1),
+			value: Some(raw_line.trim().to_string()),
+			..Default::default()
+		};
+
+		offset += raw_line.len() + 1;
+
+		let line = raw_line.trim();
+		let mut warn = |kind: WarningKind| warnings.push(Warning::new(kind, context.clone()));
+
+		if line.is_empty()
+			|| raw_line.ends_with(char::is_whitespace)
+			|| raw_line.contains(|x: char| x.is_whitespace() && x != ' ' && x != '\t')
+		{
+			warn(WarningKind::Whitespace);
+		}
+
+		if line.is_empty() {
+			continue;
+		}
+
+		if !regex.is_match(line) {
+			if line.ends_with(':') {
+				let mut chars = line.chars();
+				chars.next_back();
+
+				trim_description(&mut fields, current_key);
+				current_key = chars.as_str();
+				if fields.contains_key(current_key) {
+					warn(WarningKind::DuplicateKey);
+				}
+
+				fields.insert(current_key, "");
+				continue;
+			}
+
+			if !current_key.is_empty() {
+				if indent == 0 {
+					warn(WarningKind::StrayContinuation);
+				}
+
+				let existing_value = match fields.get(current_key) {
+					Some(value) => value,
+					None => "",
+				};
+
+				if line == "." {
+					let updated_key = format!("{existing_value}\n\n");
+					fields.insert(current_key, &updated_key);
+				} else {
+					let updated_key = if existing_value.ends_with('\n') {
+						format!("{existing_value}{line}")
+					} else {
+						format!("{existing_value} {line}")
+					};
+
+					fields.insert(current_key, &updated_key);
+				}
+
+				continue;
+			}
+		}
+
+		let captures = match regex.captures(line) {
+			Some(captures) => captures,
+			None => return Err(KVError::new(context)),
+		};
+
+		let (key, value) = match (captures.get(1), captures.get(2)) {
+			(Some(key), Some(value)) => (key.as_str(), value.as_str()),
+			_ => return Err(KVError::new(context)),
+		};
+
+		if fields.contains_key(key) {
+			warn(WarningKind::DuplicateKey);
+			continue;
+		}
+
+		if is_description(key) && !value.is_empty() {
+			let format = format!("{value}\n");
+			fields.insert(key, &format);
+		} else {
+			trim_description(&mut fields, current_key);
+			fields.insert(key, value);
+		}
+
+		current_key = key;
+	}
+
+	trim_description(&mut fields, current_key);
+	Ok((fields, warnings))
+}
+
+fn is_description(key: &str) -> bool {
+	let key = key.to_lowercase();
+	if key == "description" {
+		return true;
+	}
+
+	key.starts_with("description-") && key != "description-md5"
+}
+
+fn trim_description(fields: &mut CaseMap, key: &str) {
+	if !is_description(key) {
+		return;
+	}
+
+	let existing_value = match fields.get(key) {
+		Some(value) => value,
+		None => return,
+	};
+
+	if let Some(substring) = existing_value.strip_suffix('\n') {
+		let substring = substring.to_owned();
+		fields.insert(key, &substring);
+	}
+}
+
+fn stanzas(data: &str) -> Vec<&str> {
+	data.trim().split("\n\n").collect()
+}
+
+fn main() {
+	let chariz = read_to_string("./test/chariz.packages").unwrap();
+	let jammy = read_to_string("./test/jammy.release").unwrap();
+
+	let chariz_stanzas = stanzas(&chariz);
+	bench("chariz.packages regex parse_kv", &chariz, || {
+		chariz_stanzas
+			.iter()
+			.map(|x| regex_parse_kv(x).unwrap().0.len())
+			.sum()
+	});
+
+	bench("chariz.packages Paragraph::from", &chariz, || {
+		chariz_stanzas
+			.iter()
+			.map(|x| Paragraph::from(x).unwrap().fields.len())
+			.sum()
+	});
+
+	bench("chariz.packages Deb822::from", &chariz, || {
+		Deb822::from(&chariz).unwrap().len()
+	});
+
+	bench("chariz.packages Packages::from", &chariz, || {
+		Packages::from(&chariz).len()
+	});
+
+	bench("chariz.packages PackageRef::iter", &chariz, || {
+		PackageRef::iter(&chariz).filter(|x| x.is_ok()).count()
+	});
+
+	bench("jammy.release regex parse_kv", &jammy, || {
+		regex_parse_kv(&jammy).unwrap().0.len()
+	});
+
+	bench("jammy.release Paragraph::from", &jammy, || {
+		Paragraph::from(&jammy).unwrap().fields.len()
+	});
+
+	bench("jammy.release Release::from", &jammy, || {
+		Release::from(&jammy)
+			.unwrap()
+			.sha256sum
+			.map_or(0, |x| x.len())
+	});
+}
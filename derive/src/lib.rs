@@ -0,0 +1,171 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+	parse_macro_input, Data, DeriveInput, Error, Fields, GenericArgument, LitStr, Path,
+	PathArguments, Type,
+};
+
+// Implements apt_parser::FromStanza for a struct with named fields
+// Each field is read from the key named after it, e.g. installed_size reads
+// Installed-Size, and keys are looked up ignoring case
+//
+// #[stanza(rename = "SileoDepiction")] reads another key
+// #[stanza(with = "path::to::parser")] parses with a fn(&str) -> Result<T, E>
+// #[stanza(default)] uses Default::default() when the key is missing
+//
+// Option fields may be missing, and any other missing field is a
+// MissingKeyError
+#[proc_macro_derive(FromStanza, attributes(stanza))]
+pub fn derive_from_stanza(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	match expand(&input) {
+		Ok(tokens) => tokens.into(),
+		Err(err) => err.to_compile_error().into(),
+	}
+}
+
+struct Options {
+	key: String,
+	with: Option<Path>,
+	default: bool,
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2, Error> {
+	let fields = match &input.data {
+		Data::Struct(data) => match &data.fields {
+			Fields::Named(fields) => &fields.named,
+			_ => {
+				return Err(Error::new_spanned(
+					input,
+					"FromStanza needs a struct with named fields",
+				))
+			}
+		},
+		_ => {
+			return Err(Error::new_spanned(
+				input,
+				"FromStanza can only be derived for structs",
+			))
+		}
+	};
+
+	let mut values = Vec::new();
+	for field in fields {
+		let ident = match &field.ident {
+			Some(ident) => ident,
+			None => continue,
+		};
+
+		let options = options(field, &ident.to_string())?;
+		let key = &options.key;
+		let optional = option_type(&field.ty);
+		let inner = optional.unwrap_or(&field.ty);
+
+		let parser = match &options.with {
+			Some(path) => quote! { #path },
+			None => quote! { <#inner as ::apt_parser::FromField>::from_field },
+		};
+
+		let value = quote! { ::apt_parser::parse_field(paragraph, #key, #parser)? };
+		let value = match (optional.is_some(), options.default) {
+			(true, _) => value,
+			(false, true) => quote! { #value.unwrap_or_default() },
+			(false, false) => quote! {
+				match #value {
+					::core::option::Option::Some(value) => value,
+					::core::option::Option::None => {
+						return ::core::result::Result::Err(
+							::apt_parser::errors::APTError::MissingKeyError(
+								::apt_parser::errors::MissingKeyError::new(#key),
+							),
+						)
+					}
+				}
+			},
+		};
+
+		values.push(quote! { #ident: #value });
+	}
+
+	let name = &input.ident;
+	let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+	Ok(quote! {
+		impl #impl_generics ::apt_parser::FromStanza for #name #type_generics #where_clause {
+			fn from_paragraph(
+				paragraph: &::apt_parser::Paragraph,
+			) -> ::core::result::Result<Self, ::apt_parser::errors::APTError> {
+				::core::result::Result::Ok(#name { #(#values),* })
+			}
+		}
+	})
+}
+
+fn options(field: &syn::Field, name: &str) -> Result<Options, Error> {
+	let mut options = Options {
+		key: key_name(name),
+		with: None,
+		default: false,
+	};
+
+	for attr in &field.attrs {
+		if !attr.path().is_ident("stanza") {
+			continue;
+		}
+
+		attr.parse_nested_meta(|meta| {
+			if meta.path.is_ident("rename") {
+				options.key = meta.value()?.parse::<LitStr>()?.value();
+			} else if meta.path.is_ident("with") {
+				options.with = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+			} else if meta.path.is_ident("default") {
+				options.default = true;
+			} else {
+				return Err(meta.error("expected `rename`, `with` or `default`"));
+			}
+
+			Ok(())
+		})?;
+	}
+
+	Ok(options)
+}
+
+// installed_size becomes Installed-Size
+fn key_name(name: &str) -> String {
+	name.trim_start_matches("r#")
+		.split('_')
+		.filter(|word| !word.is_empty())
+		.map(|word| {
+			let mut chars = word.chars();
+			match chars.next() {
+				Some(first) => first.to_uppercase().chain(chars).collect(),
+				None => String::new(),
+			}
+		})
+		.collect::<Vec<String>>()
+		.join("-")
+}
+
+// The T of an Option<T>
+fn option_type(ty: &Type) -> Option<&Type> {
+	let segment = match ty {
+		Type::Path(path) if path.qself.is_none() => path.path.segments.last()?,
+		_ => return None,
+	};
+
+	if segment.ident != "Option" {
+		return None;
+	}
+
+	match &segment.arguments {
+		PathArguments::AngleBracketed(arguments) if arguments.args.len() == 1 => {
+			match &arguments.args[0] {
+				GenericArgument::Type(ty) => Some(ty),
+				_ => None,
+			}
+		}
+		_ => None,
+	}
+}
@@ -0,0 +1,517 @@
+use crate::{
+	lint::Severity,
+	packages::{Package, Packages},
+	path::SafePath,
+	release::{HashKind, Release},
+	repository::path_stem,
+};
+use rayon::prelude::*;
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	fmt::{Display, Formatter},
+	fs::read,
+	path::Path,
+};
+
+// Identifies a package stanza, printed like a .deb file name
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PackageId {
+	pub package: String,
+	pub version: String,
+	pub architecture: String,
+}
+
+impl PackageId {
+	pub fn of(package: &Package) -> PackageId {
+		PackageId {
+			package: package.package.clone(),
+			version: package.version.clone(),
+			architecture: package.architecture.clone(),
+		}
+	}
+}
+
+impl Display for PackageId {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(
+			formatter,
+			"{}_{}_{}",
+			self.package, self.version, self.architecture
+		)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditIssue {
+	// A file listed in the Release file that doesn't exist
+	// Release files often list uncompressed indexes that are never published,
+	// so another compression of the same file on disk is recorded alongside
+	MissingIndex {
+		filename: String,
+		alternative: Option<String>,
+	},
+	// A file listed in the Release file whose path could escape the repository
+	UnsafeIndex {
+		filename: String,
+	},
+	IndexSize {
+		filename: String,
+		expected: u64,
+		actual: u64,
+	},
+	IndexHash {
+		filename: String,
+		kind: HashKind,
+		expected: String,
+		actual: String,
+	},
+	// The same name, version and architecture in more than one stanza
+	DuplicatePackage {
+		id: PackageId,
+		count: usize,
+	},
+	MissingDeb {
+		id: PackageId,
+		filename: String,
+	},
+	// A Filename that could escape the archive root
+	UnsafeDeb {
+		id: PackageId,
+		filename: String,
+	},
+	DebSize {
+		id: PackageId,
+		filename: String,
+		expected: i64,
+		actual: u64,
+	},
+	DebHash {
+		id: PackageId,
+		filename: String,
+		kind: HashKind,
+		expected: String,
+		actual: String,
+	},
+	// A package with neither a SHA256 nor a SHA512, which apt won't trust
+	WeakHashes {
+		id: PackageId,
+	},
+}
+
+impl AuditIssue {
+	pub fn severity(&self) -> Severity {
+		match self {
+			AuditIssue::MissingIndex {
+				alternative: Some(_),
+				..
+			} => Severity::Info,
+			AuditIssue::WeakHashes { .. } => Severity::Warning,
+			_ => Severity::Error,
+		}
+	}
+}
+
+impl Display for AuditIssue {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(formatter, "{}: ", self.severity().letter())?;
+		match self {
+			AuditIssue::MissingIndex {
+				filename,
+				alternative: Some(alternative),
+			} => write!(formatter, "{filename} is missing, {alternative} exists"),
+			AuditIssue::MissingIndex { filename, .. } => write!(formatter, "{filename} is missing"),
+			AuditIssue::UnsafeIndex { filename } => write!(formatter, "{filename:?} is unsafe"),
+			AuditIssue::IndexSize {
+				filename,
+				expected,
+				actual,
+			} => write!(
+				formatter,
+				"{filename} is {actual} bytes, expected {expected}"
+			),
+			AuditIssue::IndexHash {
+				filename,
+				kind,
+				expected,
+				actual,
+			} => write!(
+				formatter,
+				"{filename} has {} {actual}, expected {expected}",
+				kind.field()
+			),
+			AuditIssue::DuplicatePackage { id, count } => {
+				write!(formatter, "{id} appears {count} times")
+			}
+			AuditIssue::MissingDeb { id, filename } => {
+				write!(formatter, "{id}: {filename} is missing")
+			}
+			AuditIssue::UnsafeDeb { id, filename } => {
+				write!(formatter, "{id}: {filename:?} is unsafe")
+			}
+			AuditIssue::DebSize {
+				id,
+				filename,
+				expected,
+				actual,
+			} => write!(
+				formatter,
+				"{id}: {filename} is {actual} bytes, expected {expected}"
+			),
+			AuditIssue::DebHash {
+				id,
+				filename,
+				kind,
+				expected,
+				actual,
+			} => write!(
+				formatter,
+				"{id}: {filename} has {} {actual}, expected {expected}",
+				kind.field()
+			),
+			AuditIssue::WeakHashes { id } => {
+				write!(formatter, "{id} has no SHA256 or SHA512")
+			}
+		}
+	}
+}
+
+// Checks a local copy of a repository before it's published
+pub struct AuditReport {
+	// Index files first, sorted by path, then packages in the order given
+	pub issues: Vec<AuditIssue>,
+}
+
+impl AuditReport {
+	// dist is the directory of the Release file, which its hashes are relative
+	// to, and root is the top of the archive, which Filename fields are
+	// relative to. Both are the same directory in a flat repository
+	pub fn new(release: &Release, dist: &Path, root: &Path, packages: &[&Packages]) -> AuditReport {
+		let mut issues = audit_indexes(release, dist);
+
+		let mut counts = HashMap::<PackageId, usize>::new();
+		let mut order = Vec::new();
+		for package in packages.iter().flat_map(|packages| packages.iter()) {
+			let id = PackageId::of(package);
+			let count = counts.entry(id.clone()).or_default();
+			if *count == 0 {
+				order.push(id);
+			}
+			*count += 1;
+		}
+
+		for id in order {
+			let count = counts[&id];
+			if count > 1 {
+				issues.push(AuditIssue::DuplicatePackage { id, count });
+			}
+		}
+
+		// Duplicate stanzas usually share a .deb, which only needs reading once
+		let mut seen = HashSet::new();
+		let unique = packages
+			.iter()
+			.flat_map(|packages| packages.iter())
+			.filter(|package| seen.insert((&package.filename, PackageId::of(package))))
+			.collect::<Vec<&Package>>();
+
+		issues.extend(
+			unique
+				.par_iter()
+				.flat_map_iter(|package| audit_deb(package, root))
+				.collect::<Vec<AuditIssue>>(),
+		);
+
+		AuditReport { issues }
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.issues.is_empty()
+	}
+
+	// Issues that should block a publish
+	pub fn errors(&self) -> impl Iterator<Item = &AuditIssue> {
+		self.issues
+			.iter()
+			.filter(|issue| issue.severity() == Severity::Error)
+	}
+}
+
+fn audit_indexes(release: &Release, dist: &Path) -> Vec<AuditIssue> {
+	let mut files = BTreeMap::new();
+	for kind in HashKind::ALL {
+		for entry in release.hashes(kind).into_iter().flatten() {
+			files
+				.entry(entry.filename.as_str())
+				.or_insert_with(Vec::new)
+				.push((kind, entry));
+		}
+	}
+
+	let mut issues = Vec::new();
+	for (filename, entries) in &files {
+		let path = match entries[0].1.safe_filename() {
+			Ok(path) => path,
+			Err(_) => {
+				issues.push(AuditIssue::UnsafeIndex {
+					filename: filename.to_string(),
+				});
+				continue;
+			}
+		};
+
+		let data = match read(dist.join(path)) {
+			Ok(data) => data,
+			Err(_) => {
+				let alternative = files
+					.keys()
+					.filter(|other| other != &filename && path_stem(other) == path_stem(filename))
+					.filter(|other| SafePath::from(other).is_ok())
+					.find(|other| dist.join(other).is_file())
+					.map(|other| other.to_string());
+
+				issues.push(AuditIssue::MissingIndex {
+					filename: filename.to_string(),
+					alternative,
+				});
+				continue;
+			}
+		};
+
+		// Every table should list the same size, so a wrong size is only
+		// reported once and the hashes aren't worth checking
+		let actual = data.len() as u64;
+		if let Some((_, entry)) = entries.iter().find(|(_, entry)| entry.size != actual) {
+			issues.push(AuditIssue::IndexSize {
+				filename: filename.to_string(),
+				expected: entry.size,
+				actual,
+			});
+			continue;
+		}
+
+		for (kind, entry) in entries {
+			let digest = kind.digest(&data);
+			if !digest.eq_ignore_ascii_case(&entry.hash) {
+				issues.push(AuditIssue::IndexHash {
+					filename: filename.to_string(),
+					kind: *kind,
+					expected: entry.hash.clone(),
+					actual: digest,
+				});
+			}
+		}
+	}
+
+	issues
+}
+
+fn audit_deb(package: &Package, root: &Path) -> Vec<AuditIssue> {
+	let id = PackageId::of(package);
+	let mut issues = Vec::new();
+
+	if package.sha256sum.is_none() && package.sha512sum.is_none() {
+		issues.push(AuditIssue::WeakHashes { id: id.clone() });
+	}
+
+	let path = match package.safe_filename() {
+		Ok(path) => path,
+		Err(_) => {
+			issues.push(AuditIssue::UnsafeDeb {
+				id,
+				filename: package.filename.clone(),
+			});
+			return issues;
+		}
+	};
+
+	let data = match read(root.join(path)) {
+		Ok(data) => data,
+		Err(_) => {
+			issues.push(AuditIssue::MissingDeb {
+				id,
+				filename: package.filename.clone(),
+			});
+			return issues;
+		}
+	};
+
+	// An invalid Size is parsed as -1, which the linter already reports
+	let actual = data.len() as u64;
+	if package.size >= 0 && package.size as u64 != actual {
+		issues.push(AuditIssue::DebSize {
+			id,
+			filename: package.filename.clone(),
+			expected: package.size,
+			actual,
+		});
+		return issues;
+	}
+
+	for kind in HashKind::ALL {
+		if let Some(expected) = package.hash(kind) {
+			let digest = kind.digest(&data);
+			if !digest.eq_ignore_ascii_case(expected) {
+				issues.push(AuditIssue::DebHash {
+					id: id.clone(),
+					filename: package.filename.clone(),
+					kind,
+					expected: expected.to_string(),
+					actual: digest,
+				});
+			}
+		}
+	}
+
+	issues
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{AuditIssue, AuditReport, PackageId};
+	use crate::{
+		lint::Severity,
+		packages::Packages,
+		release::{HashKind, Release},
+	};
+	use std::{
+		fs::{create_dir_all, read_to_string, remove_dir_all, write},
+		path::Path,
+	};
+
+	fn stanza(name: &str, data: &[u8], md5: &str, sha256: Option<&str>) -> String {
+		let mut stanza = format!(
+			"Package: {name}\nVersion: 1.0\nArchitecture: all\nFilename: debs/{name}.deb\nSize: {}\nMD5sum: {md5}\n",
+			data.len()
+		);
+
+		if let Some(sha256) = sha256 {
+			stanza.push_str(&format!("SHA256: {sha256}\n"));
+		}
+
+		stanza
+	}
+
+	#[test]
+	fn audit_repository() {
+		let root = std::env::temp_dir().join(format!("apt-parser-audit-{}", std::process::id()));
+		create_dir_all(root.join("debs")).unwrap();
+
+		let md5 = |data: &[u8]| HashKind::Md5.digest(data);
+		let sha256 = |data: &[u8]| HashKind::Sha256.digest(data);
+
+		write(root.join("debs/good.deb"), b"good").unwrap();
+		write(root.join("debs/weak.deb"), b"weak").unwrap();
+		write(root.join("debs/tampered.deb"), b"tampered").unwrap();
+		let packages = [
+			stanza("good", b"good", &md5(b"good"), Some(&sha256(b"good"))),
+			stanza("good", b"good", &md5(b"good"), Some(&sha256(b"good"))),
+			stanza("weak", b"weak", &md5(b"weak"), None),
+			stanza(
+				"tampered",
+				b"tampered",
+				&md5(b"tampered"),
+				Some(&sha256(b"other")),
+			),
+			stanza(
+				"missing",
+				b"missing",
+				&md5(b"missing"),
+				Some(&sha256(b"missing")),
+			),
+			"Package: escape\nVersion: 1.0\nArchitecture: all\nFilename: ../escape.deb\nSize: 0\nSHA256: 0000\n".to_string(),
+		]
+		.join("\n");
+
+		write(root.join("Packages.gz"), b"not really gzip").unwrap();
+		let release = Release::from(&format!(
+			"Architectures: all\nComponents: main\nSHA256:\n {} {} Packages\n {} 15 Packages.gz\n {} 3 Packages.xz\n",
+			sha256(packages.as_bytes()),
+			packages.len(),
+			sha256(b"tampered"),
+			sha256(b"xz"),
+		))
+		.unwrap();
+
+		let packages = Packages::from(&packages);
+		let report = AuditReport::new(&release, &root, &root, &[&packages]);
+		let id = |name: &str| PackageId {
+			package: name.to_string(),
+			version: "1.0".to_string(),
+			architecture: "all".to_string(),
+		};
+
+		assert_eq!(
+			report.issues,
+			vec![
+				AuditIssue::MissingIndex {
+					filename: "Packages".to_string(),
+					alternative: Some("Packages.gz".to_string()),
+				},
+				AuditIssue::IndexHash {
+					filename: "Packages.gz".to_string(),
+					kind: HashKind::Sha256,
+					expected: sha256(b"tampered"),
+					actual: sha256(b"not really gzip"),
+				},
+				AuditIssue::MissingIndex {
+					filename: "Packages.xz".to_string(),
+					alternative: Some("Packages.gz".to_string()),
+				},
+				AuditIssue::DuplicatePackage {
+					id: id("good"),
+					count: 2,
+				},
+				AuditIssue::WeakHashes { id: id("weak") },
+				AuditIssue::DebHash {
+					id: id("tampered"),
+					filename: "debs/tampered.deb".to_string(),
+					kind: HashKind::Sha256,
+					expected: sha256(b"other"),
+					actual: sha256(b"tampered"),
+				},
+				AuditIssue::MissingDeb {
+					id: id("missing"),
+					filename: "debs/missing.deb".to_string(),
+				},
+				AuditIssue::UnsafeDeb {
+					id: id("escape"),
+					filename: "../escape.deb".to_string(),
+				},
+			]
+		);
+
+		assert_eq!(report.errors().count(), 5);
+		assert_eq!(report.issues[0].severity(), Severity::Info);
+		assert_eq!(
+			report.issues[4].to_string(),
+			"W: weak_1.0_all has no SHA256 or SHA512"
+		);
+
+		remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn audit_chariz() {
+		let data = match read_to_string("./test/chariz.packages") {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		// Without the .debs every package is missing, but each one carries a
+		// SHA256 and appears once
+		let release = Release::from("Architectures: iphoneos-arm\nComponents: main\n").unwrap();
+		let packages = Packages::from(&data);
+		let report = AuditReport::new(
+			&release,
+			Path::new("/nonexistent"),
+			Path::new("/nonexistent"),
+			&[&packages],
+		);
+
+		assert_eq!(report.issues.len(), packages.len());
+		assert!(report
+			.issues
+			.iter()
+			.all(|issue| matches!(issue, AuditIssue::MissingDeb { .. })));
+	}
+}
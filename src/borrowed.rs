@@ -0,0 +1,362 @@
+use crate::{
+	deb822::{compat_value, lines},
+	errors::{APTError, ErrorContext, KVError, MissingKeyError, ParseError},
+	limits::Limits,
+	relation::{parse_field_relations, Relation, RelationKind},
+	split_stanzas,
+};
+use memchr::memchr;
+use std::{borrow::Cow, ops::Deref};
+
+// Splits a stanza into each field name and its raw value, as slices of the
+// data. Values still contain their continuation lines
+fn scan(data: &str) -> Result<Vec<(&str, &str)>, KVError> {
+	let mut fields: Vec<(&str, usize, usize)> = Vec::new();
+	let mut offset = 0;
+
+	for (index, raw_line) in lines(data).enumerate() {
+		let start = offset;
+		offset += raw_line.len() + 1;
+
+		// Also drops the "\r" of "\r\n" line endings
+		let line = raw_line.trim_end();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+
+		let indent = line.len() - line.trim_start().len();
+		let key = match indent {
+			0 => memchr(b':', line.as_bytes()).map(|colon| (&line[..colon], &line[colon + 1..])),
+			_ => None,
+		};
+
+		match key {
+			Some((name, value)) if !name.trim().is_empty() => {
+				let end = start + line.len();
+				fields.push((name.trim_end(), end - value.trim_start().len(), end));
+			}
+			_ => match fields.last_mut() {
+				Some(field) => field.2 = start + line.len(),
+				None => {
+					return Err(KVError::new(ErrorContext {
+						offset: Some(start + indent),
+						line: Some(index + 1),
+						column: Some(indent + 1),
+						value: Some(line.trim().to_string()),
+						..Default::default()
+					}))
+				}
+			},
+		}
+	}
+
+	Ok(fields
+		.into_iter()
+		.map(|(name, start, end)| (name, &data[start..end]))
+		.collect())
+}
+
+// A control stanza whose fields borrow from the data it was parsed from
+// Only values that span several lines, like a long Description, are copied
+// to be joined. Unlike Control there are no warnings, since this is meant
+// for scanning large indexes quickly
+#[derive(Debug, Clone)]
+pub struct ControlRef<'a> {
+	fields: Vec<(&'a str, &'a str)>,
+	// The whole stanza, which Control::from can parse when more is needed
+	pub raw: &'a str,
+	pub package: &'a str,
+	pub source: Option<&'a str>,
+	pub version: &'a str,
+	pub section: Option<&'a str>,
+	pub priority: Option<&'a str>,
+	pub architecture: &'a str,
+	pub is_essential: Option<bool>,
+	pub multi_arch: Option<&'a str>,
+	// Relation fields are kept as written, see relations(). Like Tag and
+	// Built-Using they may be folded over several lines, which are joined
+	pub depends: Option<Cow<'a, str>>,
+	pub pre_depends: Option<Cow<'a, str>>,
+	pub recommends: Option<Cow<'a, str>>,
+	pub suggests: Option<Cow<'a, str>>,
+	pub replaces: Option<Cow<'a, str>>,
+	pub enhances: Option<Cow<'a, str>>,
+	pub breaks: Option<Cow<'a, str>>,
+	pub conflicts: Option<Cow<'a, str>>,
+	pub provides: Option<Cow<'a, str>>,
+	pub installed_size: Option<i64>,
+	pub maintainer: Option<&'a str>,
+	pub description: Option<Cow<'a, str>>,
+	pub homepage: Option<&'a str>,
+	pub built_using: Option<Cow<'a, str>>,
+	pub package_type: Option<&'a str>,
+	pub tags: Option<Cow<'a, str>>,
+}
+
+impl<'a> ControlRef<'a> {
+	pub fn from(data: &'a str) -> Result<ControlRef<'a>, APTError> {
+		let fields = scan(data)?;
+		let single = |key: &str| single(&fields, data, key);
+		let folded = |key: &str| find(&fields, key).map(|value| joined(key, value));
+
+		let required = |key: &str| match single(key)? {
+			Some(value) => Ok(value),
+			None => Err(APTError::MissingKeyError(MissingKeyError::new(key))),
+		};
+
+		let installed_size = match single("Installed-Size")? {
+			Some(size) => Some(match size.parse::<i64>() {
+				Ok(size) => size,
+				Err(err) => {
+					return Err(APTError::ParseError(
+						ParseError::field("Invalid installed size", "Installed-Size", size)
+							.with_source(err)
+							.locate(data),
+					))
+				}
+			}),
+			None => None,
+		};
+
+		Ok(ControlRef {
+			raw: data,
+			package: required("Package")?,
+			source: single("Source")?,
+			version: required("Version")?,
+			section: single("Section")?,
+			priority: single("Priority")?,
+			architecture: required("Architecture")?,
+			is_essential: single("Essential")?.map(|x| x == "yes"),
+			multi_arch: single("Multi-Arch")?,
+			depends: folded("Depends"),
+			pre_depends: folded("Pre-Depends"),
+			recommends: folded("Recommends"),
+			suggests: folded("Suggests"),
+			replaces: folded("Replaces"),
+			enhances: folded("Enhances"),
+			breaks: folded("Breaks"),
+			conflicts: folded("Conflicts"),
+			provides: folded("Provides"),
+			installed_size,
+			maintainer: single("Maintainer")?,
+			description: find(&fields, "Description").map(|value| joined("Description", value)),
+			homepage: single("Homepage")?,
+			built_using: folded("Built-Using"),
+			package_type: single("Package-Type")?,
+			tags: folded("Tag"),
+			fields,
+		})
+	}
+
+	// Reads a field the same way Control::get does, case-insensitively
+	pub fn get(&self, key: &str) -> Option<Cow<'a, str>> {
+		find(&self.fields, key).map(|value| joined(key, value))
+	}
+
+	pub fn relations(&self, kind: RelationKind) -> Result<Vec<Relation>, APTError> {
+		let raw_data = self.get(kind.field()).map(Cow::into_owned);
+		parse_field_relations(kind, raw_data.as_ref())
+	}
+}
+
+fn find<'a>(fields: &[(&'a str, &'a str)], key: &str) -> Option<&'a str> {
+	fields
+		.iter()
+		.find(|(name, _)| name.eq_ignore_ascii_case(key))
+		.map(|(_, value)| *value)
+}
+
+// Single-valued fields like Package or Filename, which are always borrowed
+fn single<'a>(
+	fields: &[(&'a str, &'a str)],
+	data: &str,
+	key: &str,
+) -> Result<Option<&'a str>, APTError> {
+	match find(fields, key) {
+		Some(value) if value.contains('\n') => Err(APTError::ParseError(
+			ParseError::field("Field must be a single line", key, value).locate(data),
+		)),
+		value => Ok(value),
+	}
+}
+
+fn joined<'a>(name: &str, value: &'a str) -> Cow<'a, str> {
+	match value.contains('\n') {
+		true => Cow::Owned(compat_value(name, value)),
+		false => Cow::Borrowed(value),
+	}
+}
+
+// An entry of a Packages index whose fields borrow from the index
+// The fields of the control stanza are reachable through Deref
+#[derive(Debug, Clone)]
+pub struct PackageRef<'a> {
+	pub control: ControlRef<'a>,
+	pub filename: &'a str,
+	// -1 when the Size isn't a number, like Package
+	pub size: i64,
+	pub md5sum: Option<&'a str>,
+	pub sha1sum: Option<&'a str>,
+	pub sha256sum: Option<&'a str>,
+	pub sha512sum: Option<&'a str>,
+	pub description_md5sum: Option<&'a str>,
+}
+
+impl<'a> PackageRef<'a> {
+	pub fn from(data: &'a str) -> Result<PackageRef<'a>, APTError> {
+		let control = ControlRef::from(data)?;
+		let single = |key: &str| single(&control.fields, data, key);
+
+		let filename = match single("Filename")? {
+			Some(filename) => filename,
+			None => return Err(APTError::MissingKeyError(MissingKeyError::new("Filename"))),
+		};
+
+		let size = match single("Size")? {
+			Some(size) => size.parse::<i64>().unwrap_or(-1),
+			None => return Err(APTError::MissingKeyError(MissingKeyError::new("Size"))),
+		};
+
+		Ok(PackageRef {
+			filename,
+			size,
+			md5sum: single("MD5Sum")?,
+			sha1sum: single("SHA1")?,
+			sha256sum: single("SHA256")?,
+			sha512sum: single("SHA512")?,
+			description_md5sum: single("Description-md5")?,
+			control,
+		})
+	}
+
+	// Parses each stanza of a Packages index as it's reached, so nothing is
+	// kept around that the caller doesn't keep. The data must use "\n" line
+	// endings for stanzas to be split
+	pub fn iter(data: &'a str) -> impl Iterator<Item = Result<PackageRef<'a>, APTError>> {
+		PackageRef::iter_limited(data, &Limits::default())
+	}
+
+	// An input over the limits is a single error, and the first stanza past
+	// the stanza limit is an error that ends the iteration
+	pub fn iter_limited(
+		data: &'a str,
+		limits: &Limits,
+	) -> impl Iterator<Item = Result<PackageRef<'a>, APTError>> {
+		let limits = *limits;
+		let input = limits.check_input(data).err();
+		let valid = input.is_none();
+
+		input.map(Err).into_iter().chain(
+			split_stanzas(data)
+				.take_while(move |_| valid)
+				.take(limits.stanzas.saturating_add(1))
+				.enumerate()
+				.map(move |(index, (offset, line, stanza))| {
+					limits.check_stanzas(index + 1)?;
+					limits
+						.check_stanza(stanza)
+						.and_then(|_| PackageRef::from(stanza))
+						.map_err(|err| err.in_stanza(index, offset, line))
+				}),
+		)
+	}
+}
+
+impl<'a> Deref for PackageRef<'a> {
+	type Target = ControlRef<'a>;
+
+	fn deref(&self) -> &ControlRef<'a> {
+		&self.control
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ControlRef, PackageRef};
+	use crate::{control::Control, errors::APTError, packages::Packages, relation::RelationKind};
+	use std::{borrow::Cow, fs::read_to_string};
+
+	#[test]
+	fn borrowed_chariz() {
+		let data = match read_to_string("./test/chariz.packages") {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let borrowed = PackageRef::iter(&data)
+			.collect::<Result<Vec<PackageRef>, APTError>>()
+			.unwrap();
+		let packages = Packages::from(&data);
+		assert_eq!(borrowed.len(), packages.len());
+
+		// Everything reads the same as the owned parser
+		for (borrowed, package) in borrowed.iter().zip(packages.iter()) {
+			assert_eq!(borrowed.package, package.package);
+			assert_eq!(borrowed.version, package.version);
+			assert_eq!(borrowed.architecture, package.architecture);
+			assert_eq!(borrowed.filename, package.filename);
+			assert_eq!(borrowed.size, package.size);
+			assert_eq!(borrowed.sha256sum, package.sha256sum.as_deref());
+			assert_eq!(borrowed.installed_size, package.installed_size);
+			assert_eq!(
+				borrowed.description.as_deref(),
+				package.description.as_deref()
+			);
+			assert_eq!(
+				borrowed.get("Depiction").as_deref(),
+				package.get("Depiction")
+			);
+			assert_eq!(
+				borrowed.relations(RelationKind::Depends).unwrap(),
+				package.relations(RelationKind::Depends).unwrap()
+			);
+		}
+
+		// Single line values point into the data
+		let range = data.as_bytes().as_ptr_range();
+		let package = borrowed[0].package.as_bytes().as_ptr();
+		assert!(range.contains(&package));
+		assert!(matches!(
+			borrowed[0].get("SileoDepiction"),
+			Some(Cow::Borrowed(_))
+		));
+	}
+
+	#[test]
+	fn borrowed_control() {
+		let data = "Package: hello\nVersion: 2.10-2\nArchitecture: amd64\nDescription: example package\n Prints a greeting.\n .\n Used as an example.\nDepends: libc6 (>= 2.34)\n";
+
+		let control = ControlRef::from(data).unwrap();
+		assert_eq!(control.package, "hello");
+		assert_eq!(control.depends.as_deref(), Some("libc6 (>= 2.34)"));
+		assert!(matches!(control.depends, Some(Cow::Borrowed(_))));
+		assert_eq!(
+			control.description.as_deref(),
+			Some("example package\nPrints a greeting.\n\nUsed as an example.")
+		);
+		assert!(matches!(control.description, Some(Cow::Owned(_))));
+
+		// Folded relation fields are joined like Control does
+		let data = "Package: hello\nVersion: 2.10-2\nArchitecture: amd64\nDepends: libc6 (>= 2.34),\n libgcc-s1\nTag: role::program,\n interface::commandline\n";
+		let control = ControlRef::from(data).unwrap();
+		let owned = Control::from(data).unwrap();
+		assert!(matches!(control.depends, Some(Cow::Owned(_))));
+		assert_eq!(
+			control.relations(RelationKind::Depends).unwrap(),
+			owned.relations(RelationKind::Depends).unwrap()
+		);
+		assert_eq!(control.depends.as_deref(), owned.get("Depends"));
+		assert_eq!(control.tags.as_deref(), owned.get("Tag"));
+
+		// Single-valued fields can't span several lines
+		let err =
+			ControlRef::from("Package: hello\nVersion: 1\n 2\nArchitecture: all\n").unwrap_err();
+		assert_eq!(err.context().field.as_deref(), Some("Version"));
+		assert_eq!(err.context().line, Some(2));
+
+		assert!(matches!(
+			ControlRef::from("Package: hello\nVersion: 1\n"),
+			Err(APTError::MissingKeyError(_))
+		));
+	}
+}
@@ -1,6 +1,6 @@
 use std::collections::{hash_map::Iter, HashMap};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct CaseMap {
 	map: HashMap<String, String>,
 }
@@ -12,7 +12,7 @@ impl CaseMap {
 		}
 	}
 
-	fn get_proper_key<'a>(&'a self, key: &'a str) -> Option<&str> {
+	fn get_proper_key<'a>(&'a self, key: &'a str) -> Option<&'a str> {
 		if self.map.contains_key(key) {
 			return Some(key);
 		}
@@ -46,16 +46,17 @@ impl CaseMap {
 	}
 
 	pub fn contains_key(&self, key: &str) -> bool {
-		match self.get_proper_key(key) {
-			Some(_) => true,
-			None => false,
-		}
+		self.get_proper_key(key).is_some()
 	}
 
 	pub fn len(&self) -> usize {
 		self.map.len() / 2
 	}
 
+	pub fn is_empty(&self) -> bool {
+		self.map.is_empty()
+	}
+
 	pub fn iter(&self) -> Iter<'_, String, String> {
 		self.map.iter()
 	}
@@ -1,9 +1,12 @@
 use crate::{
 	case_map::CaseMap,
-	errors::{APTError, MissingKeyError, ParseError},
-	make_array, parse_kv,
+	deb822::Paragraph,
+	errors::{APTError, MissingKeyError, ParseError, ParseMode, Warning},
+	make_array,
+	relation::{parse_field_relations, Relation, RelationKind},
 };
 
+#[derive(Debug, Clone)]
 pub struct Control {
 	pub(crate) map: CaseMap,
 	pub package: String,
@@ -13,6 +16,7 @@ pub struct Control {
 	pub priority: Option<String>,
 	pub architecture: String,
 	pub is_essential: Option<bool>,
+	pub multi_arch: Option<String>,
 	pub depends: Option<Vec<String>>,
 	pub pre_depends: Option<Vec<String>>,
 	pub recommends: Option<Vec<String>>,
@@ -21,6 +25,7 @@ pub struct Control {
 	pub enhances: Option<Vec<String>>,
 	pub breaks: Option<Vec<String>>,
 	pub conflicts: Option<Vec<String>>,
+	pub provides: Option<Vec<String>>,
 	pub installed_size: Option<i64>,
 	pub maintainer: Option<String>,
 	pub description: Option<String>,
@@ -28,31 +33,28 @@ pub struct Control {
 	pub built_using: Option<String>,
 	pub package_type: Option<String>,
 	pub tags: Option<Vec<String>>,
+	// What the parser recovered from, always empty in strict mode
+	pub warnings: Vec<Warning>,
 }
 
 impl Control {
 	pub fn from(data: &str) -> Result<Control, APTError> {
-		let map = match parse_kv(data) {
-			Ok(map) => map,
-			Err(err) => return Err(APTError::KVError(err)),
-		};
+		Control::from_with(data, ParseMode::Lenient)
+	}
+
+	pub fn from_with(data: &str, mode: ParseMode) -> Result<Control, APTError> {
+		let paragraph = Paragraph::from_with(data, mode)?;
+		let map = paragraph.to_map();
+		let warnings = paragraph.warnings;
 
 		let package = match map.get("Package") {
 			Some(package) => package,
-			None => {
-				return Err(APTError::MissingKeyError(MissingKeyError::new(
-					"Package", data,
-				)))
-			}
+			None => return Err(APTError::MissingKeyError(MissingKeyError::new("Package"))),
 		};
 
 		let version = match map.get("Version") {
 			Some(version) => version,
-			None => {
-				return Err(APTError::MissingKeyError(MissingKeyError::new(
-					"Version", data,
-				)))
-			}
+			None => return Err(APTError::MissingKeyError(MissingKeyError::new("Version"))),
 		};
 
 		let architecture = match map.get("Architecture") {
@@ -60,7 +62,6 @@ impl Control {
 			None => {
 				return Err(APTError::MissingKeyError(MissingKeyError::new(
 					"Architecture",
-					data,
 				)))
 			}
 		};
@@ -68,13 +69,19 @@ impl Control {
 		let installed_size = match map.get("Installed-Size") {
 			Some(size) => Some(match size.parse::<i64>() {
 				Ok(size) => size,
-				Err(_) => return Err(APTError::ParseError(ParseError)),
+				Err(err) => {
+					return Err(APTError::ParseError(
+						ParseError::field("Invalid installed size", "Installed-Size", size)
+							.with_source(err)
+							.locate(data),
+					))
+				}
 			}),
 			None => None,
 		};
 
+		// The map moves in last, once every typed field has been read from it
 		Ok(Control {
-			map: map.clone(),
 			package: package.to_string(),
 			source: map.get("Source").cloned(),
 			version: version.to_string(),
@@ -82,6 +89,7 @@ impl Control {
 			priority: map.get("Priority").cloned(),
 			architecture: architecture.to_string(),
 			is_essential: map.get("Essential").map(|x| x == "yes"),
+			multi_arch: map.get("Multi-Arch").cloned(),
 			depends: make_array(map.get("Depends")),
 			pre_depends: make_array(map.get("Pre-Depends")),
 			recommends: make_array(map.get("Recommends")),
@@ -90,6 +98,7 @@ impl Control {
 			enhances: make_array(map.get("Enhances")),
 			breaks: make_array(map.get("Breaks")),
 			conflicts: make_array(map.get("Conflicts")),
+			provides: make_array(map.get("Provides")),
 			installed_size,
 			maintainer: map.get("Maintainer").cloned(),
 			description: map.get("Description").cloned(),
@@ -97,12 +106,18 @@ impl Control {
 			built_using: map.get("Built-Using").cloned(),
 			package_type: map.get("Package-Type").cloned(),
 			tags: make_array(map.get("Tag")),
+			warnings,
+			map,
 		})
 	}
 
 	pub fn get(&self, key: &str) -> Option<&str> {
 		self.map.get(key).map(|x| &**x)
 	}
+
+	pub fn relations(&self, kind: RelationKind) -> Result<Vec<Relation>, APTError> {
+		parse_field_relations(kind, self.map.get(kind.field()))
+	}
 }
 
 #[cfg(test)]
@@ -130,6 +145,7 @@ mod tests {
 		assert_eq!(control.priority, Some("optional".to_owned()));
 		assert_eq!(control.architecture, "amd64");
 		assert_eq!(control.is_essential, None);
+		assert_eq!(control.multi_arch, None);
 
 		assert_eq!(control.depends, Some(vec!["clang-13 (>= 13~)".to_owned()]));
 		assert_eq!(control.pre_depends, None);
@@ -156,6 +172,7 @@ mod tests {
 			])
 		);
 		assert_eq!(control.conflicts, None);
+		assert_eq!(control.provides, None);
 
 		assert_eq!(control.installed_size, Some(24));
 		assert_eq!(
@@ -194,6 +211,7 @@ mod tests {
 		assert_eq!(control.priority, None);
 		assert_eq!(control.architecture, "iphoneos-arm");
 		assert_eq!(control.is_essential, None);
+		assert_eq!(control.multi_arch, None);
 
 		assert_eq!(
 			control.depends,
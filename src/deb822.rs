@@ -0,0 +1,527 @@
+use crate::{
+	case_map::CaseMap,
+	errors::{APTError, ErrorContext, KVError, ParseMode, Warning, WarningKind},
+	is_description, normalize,
+};
+use memchr::{memchr, memchr3, memchr_iter};
+use std::fmt::{Display, Formatter};
+
+// How the value of a field is laid out, from Debian Policy 5.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+	// A single line, like Package or Version
+	Simple,
+	// A logical line that may be wrapped, like Depends or Uploaders
+	Folded,
+	// Lines that are significant, like Description or Files
+	Multiline,
+}
+
+impl FieldKind {
+	// Fields that aren't known to be folded or multiline are simple
+	pub fn of(name: &str) -> FieldKind {
+		let name = name.to_lowercase();
+		if is_description(&name) {
+			return FieldKind::Multiline;
+		}
+
+		match name.as_str() {
+			"changes" | "checksums-sha1" | "checksums-sha256" | "checksums-sha512"
+			| "conffiles" | "copyright" | "files" | "license" | "md5sum" | "package-list"
+			| "sha1" | "sha256" | "sha512" | "comment" | "disclaimer" => FieldKind::Multiline,
+			"architecture"
+			| "architectures"
+			| "binary"
+			| "breaks"
+			| "build-conflicts"
+			| "build-conflicts-arch"
+			| "build-conflicts-indep"
+			| "build-depends"
+			| "build-depends-arch"
+			| "build-depends-indep"
+			| "built-using"
+			| "components"
+			| "conflicts"
+			| "depends"
+			| "enhances"
+			| "pre-depends"
+			| "provides"
+			| "recommends"
+			| "replaces"
+			| "static-built-using"
+			| "suggests"
+			| "tag"
+			| "testsuite"
+			| "uploaders" => FieldKind::Folded,
+			_ => FieldKind::Simple,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+	pub name: String,
+	// Everything after the colon, without the whitespace that follows it
+	// Continuation lines are kept as they were written, indentation included,
+	// each one after a "\n"
+	pub value: String,
+	// Where the field starts in the input, lines start at 1
+	pub offset: usize,
+	pub line: usize,
+}
+
+impl Field {
+	pub fn kind(&self) -> FieldKind {
+		FieldKind::of(&self.name)
+	}
+
+	// Every line trimmed and joined with single spaces
+	pub fn folded(&self) -> String {
+		self.value
+			.split('\n')
+			.map(|line| line.trim())
+			.filter(|line| !line.is_empty())
+			.collect::<Vec<&str>>()
+			.join(" ")
+	}
+
+	// The first line, then each continuation line without its first space and
+	// with "." standing for an empty line
+	pub fn lines(&self) -> Vec<&str> {
+		let mut lines = self.value.split('\n');
+		let mut output = vec![lines.next().unwrap_or("").trim_end()];
+
+		for line in lines {
+			let line = line.strip_prefix([' ', '\t']).unwrap_or(line).trim_end();
+			output.push(match line {
+				"." => "",
+				line => line,
+			});
+		}
+
+		output
+	}
+
+	// The value as its kind says it should be read
+	pub fn text(&self) -> String {
+		match self.kind() {
+			FieldKind::Multiline => self.lines().join("\n"),
+			_ => self.folded(),
+		}
+	}
+
+	fn compat(&self) -> String {
+		compat_value(&self.name, &self.value)
+	}
+}
+
+// The value as parse_kv has always returned it: lines joined with spaces and
+// "." as a paragraph break, with the synopsis of a description on a line of
+// its own
+pub(crate) fn compat_value(name: &str, raw: &str) -> String {
+	let description = is_description(name);
+	let mut lines = raw.split('\n');
+
+	let first = lines.next().unwrap_or("").trim();
+	let mut value = match description && !first.is_empty() {
+		true => format!("{first}\n"),
+		false => first.to_string(),
+	};
+
+	for line in lines.map(|line| line.trim()) {
+		if line == "." {
+			value.push_str("\n\n");
+		} else {
+			if !value.ends_with('\n') {
+				value.push(' ');
+			}
+
+			value.push_str(line);
+		}
+	}
+
+	if description && value.ends_with('\n') {
+		value.pop();
+	}
+
+	value
+}
+
+impl Display for Field {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		match self.value.starts_with('\n') || self.value.is_empty() {
+			true => write!(formatter, "{}:{}", self.name, self.value),
+			false => write!(formatter, "{}: {}", self.name, self.value),
+		}
+	}
+}
+
+// A single stanza of fields, along with any comment lines in it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Paragraph {
+	pub fields: Vec<Field>,
+	// Lines starting with "#", without the "#"
+	pub comments: Vec<String>,
+	pub offset: usize,
+	pub line: usize,
+	// What the parser recovered from, always empty in strict mode
+	pub warnings: Vec<Warning>,
+}
+
+impl Paragraph {
+	pub fn from(data: &str) -> Result<Paragraph, APTError> {
+		Paragraph::from_with(data, ParseMode::Lenient)
+	}
+
+	// Reads the whole input as one paragraph, blank lines included
+	pub fn from_with(data: &str, mode: ParseMode) -> Result<Paragraph, APTError> {
+		let paragraph = Paragraph::parse(data)?;
+		mode.check(&paragraph.warnings)?;
+		Ok(paragraph)
+	}
+
+	pub(crate) fn parse(data: &str) -> Result<Paragraph, KVError> {
+		let data = normalize(data);
+		let offset = data.len() - data.trim_start().len();
+		let line = data[..offset].matches('\n').count() + 1;
+
+		let mut paragraphs = parse_lines(data.trim(), offset, line, false)?;
+		Ok(paragraphs.pop().unwrap_or(Paragraph {
+			offset,
+			line,
+			..Default::default()
+		}))
+	}
+
+	// The first field with the name, ignoring case
+	pub fn get(&self, name: &str) -> Option<&Field> {
+		self.fields
+			.iter()
+			.find(|field| field.name.eq_ignore_ascii_case(name))
+	}
+
+	pub fn text(&self, name: &str) -> Option<String> {
+		self.get(name).map(|field| field.text())
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.fields.is_empty()
+	}
+
+	// The fields as Control, Package and Release read them, where the first of
+	// any duplicate fields wins
+	pub(crate) fn to_map(&self) -> CaseMap {
+		let mut map = CaseMap::new();
+		for field in &self.fields {
+			if !map.contains_key(&field.name) {
+				map.insert(&field.name, &field.compat());
+			}
+		}
+
+		map
+	}
+}
+
+// Comments aren't written back, since their position among the fields
+// isn't kept
+impl Display for Paragraph {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		for field in &self.fields {
+			writeln!(formatter, "{field}")?;
+		}
+
+		Ok(())
+	}
+}
+
+// A deb822 file, like a Packages index, a .dsc, a .changes or a DEP-5
+// copyright file, made up of paragraphs separated by blank lines
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Deb822 {
+	pub paragraphs: Vec<Paragraph>,
+}
+
+impl Deb822 {
+	pub fn from(data: &str) -> Result<Deb822, APTError> {
+		Deb822::from_with(data, ParseMode::Lenient)
+	}
+
+	pub fn from_with(data: &str, mode: ParseMode) -> Result<Deb822, APTError> {
+		let data = normalize(data);
+		let paragraphs = parse_lines(&data, 0, 1, true)?;
+
+		for paragraph in &paragraphs {
+			mode.check(&paragraph.warnings)?;
+		}
+
+		Ok(Deb822 { paragraphs })
+	}
+
+	pub fn len(&self) -> usize {
+		self.paragraphs.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.paragraphs.is_empty()
+	}
+
+	pub fn iter(&self) -> std::slice::Iter<'_, Paragraph> {
+		self.paragraphs.iter()
+	}
+}
+
+impl Display for Deb822 {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		let paragraphs = self
+			.paragraphs
+			.iter()
+			.map(|paragraph| paragraph.to_string())
+			.collect::<Vec<String>>();
+
+		write!(formatter, "{}", paragraphs.join("\n"))
+	}
+}
+
+// Splits data into lines with memchr, which is much faster than
+// str::split on long inputs
+pub(crate) fn lines(data: &str) -> impl Iterator<Item = &str> {
+	let mut start = 0;
+	memchr_iter(b'\n', data.as_bytes())
+		.chain(std::iter::once(data.len()))
+		.map(move |end| {
+			let line = &data[start..end];
+			start = end + 1;
+			line
+		})
+}
+
+// Whitespace other than spaces and tabs, like "\r" or a form feed
+fn unexpected_whitespace(line: &str) -> bool {
+	match line.is_ascii() {
+		true => memchr3(b'\r', 0x0b, 0x0c, line.as_bytes()).is_some(),
+		false => line.contains(|x: char| x.is_whitespace() && x != ' ' && x != '\t'),
+	}
+}
+
+// Splits lines into fields, and into paragraphs at blank lines when document
+// is set. Otherwise blank lines are skipped with a warning, like parse_kv
+// always has. Offsets and lines are shifted by where the data starts
+fn parse_lines(
+	data: &str,
+	start: usize,
+	first_line: usize,
+	document: bool,
+) -> Result<Vec<Paragraph>, KVError> {
+	let mut paragraphs = Vec::new();
+	let mut paragraph: Option<Paragraph> = None;
+	// Warnings from before the paragraph they belong to has started
+	let mut pending = Vec::new();
+	let mut offset = start;
+
+	for (index, raw_line) in lines(data).enumerate() {
+		let line = raw_line.trim();
+		let indent = raw_line.len() - raw_line.trim_start().len();
+		let line_number = first_line + index;
+		let line_offset = offset;
+		offset += raw_line.len() + 1;
+
+		// Contexts are only built for the lines that need one
+		let stanza = paragraphs.len();
+		let context = || ErrorContext {
+			offset: Some(line_offset + indent),
+			line: Some(line_number),
+			column: Some(indent + 1),
+			stanza: match document {
+				true => Some(stanza),
+				false => None,
+			},
+			value: Some(line.to_string()),
+			..Default::default()
+		};
+
+		let warning = |kind: WarningKind| Warning::new(kind, context());
+
+		// Only empty lines are expected to separate paragraphs
+		if line.is_empty() {
+			if !document || !raw_line.is_empty() {
+				match &mut paragraph {
+					Some(current) => current.warnings.push(warning(WarningKind::Whitespace)),
+					None => pending.push(warning(WarningKind::Whitespace)),
+				}
+			}
+
+			if document {
+				paragraphs.extend(paragraph.take());
+			}
+
+			continue;
+		}
+
+		// Paragraphs start at their first line, comments included
+		let current = paragraph.get_or_insert_with(|| Paragraph {
+			offset: line_offset,
+			line: line_number,
+			warnings: std::mem::take(&mut pending),
+			..Default::default()
+		});
+
+		// Only spaces and tabs are expected, and never at the end of a line
+		if raw_line.ends_with(char::is_whitespace) || unexpected_whitespace(raw_line) {
+			current.warnings.push(warning(WarningKind::Whitespace));
+		}
+
+		if let Some(comment) = raw_line.strip_prefix('#') {
+			current.comments.push(comment.to_string());
+			continue;
+		}
+
+		let key = match indent {
+			0 => memchr(b':', raw_line.as_bytes())
+				.map(|colon| (&raw_line[..colon], &raw_line[colon + 1..])),
+			_ => None,
+		};
+
+		match key {
+			Some((name, value)) if !name.trim().is_empty() => {
+				let name = name.trim_end();
+				if current.get(name).is_some() {
+					current.warnings.push(warning(WarningKind::DuplicateKey));
+				}
+
+				current.fields.push(Field {
+					name: name.to_string(),
+					value: value.trim_start_matches([' ', '\t']).to_string(),
+					offset: line_offset,
+					line: line_number,
+				});
+			}
+			Some(_) => return Err(KVError::new(context())),
+			None => {
+				if indent == 0 {
+					current
+						.warnings
+						.push(warning(WarningKind::StrayContinuation));
+				}
+
+				match current.fields.last_mut() {
+					Some(field) => {
+						field.value.push('\n');
+						field.value.push_str(raw_line);
+					}
+					None => return Err(KVError::new(context())),
+				}
+			}
+		}
+	}
+
+	paragraphs.extend(paragraph);
+	if let Some(last) = paragraphs.last_mut() {
+		last.warnings.append(&mut pending);
+	}
+
+	Ok(paragraphs)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Deb822, FieldKind, Paragraph};
+	use crate::errors::{APTError, ParseMode, WarningKind};
+	use std::fs::read_to_string;
+
+	#[test]
+	fn deb822_document() {
+		let data = "# Generated by dpkg-source\nFormat: 3.0 (quilt)\nSource: hello\nBinary: hello,\n hello-doc\nUploaders: Jane Doe <jane@example.com>,\n  John Doe <john@example.com>\nFiles:\n 0123 100 hello_2.10.orig.tar.gz\n 4567 20 hello_2.10-2.debian.tar.xz\n\nPackage: hello\nDescription: example package\n Prints a greeting.\n .\n Used as an example. \n";
+
+		let document = Deb822::from(data).unwrap();
+		assert_eq!(document.len(), 2);
+
+		let source = &document.paragraphs[0];
+		assert_eq!(source.comments, vec![" Generated by dpkg-source"]);
+		assert_eq!((source.offset, source.line), (0, 1));
+		assert_eq!(source.text("binary").unwrap(), "hello, hello-doc");
+		assert_eq!(
+			source.text("Uploaders").unwrap(),
+			"Jane Doe <jane@example.com>, John Doe <john@example.com>"
+		);
+
+		let files = source.get("Files").unwrap();
+		assert_eq!(files.kind(), FieldKind::Multiline);
+		assert_eq!(
+			files.lines(),
+			vec![
+				"",
+				"0123 100 hello_2.10.orig.tar.gz",
+				"4567 20 hello_2.10-2.debian.tar.xz"
+			]
+		);
+
+		let binary = &document.paragraphs[1];
+		assert_eq!(binary.line, 12);
+		let description = binary.get("Description").unwrap();
+		assert_eq!(description.line, 13);
+		assert_eq!(
+			description.value,
+			"example package\n Prints a greeting.\n .\n Used as an example. "
+		);
+		assert_eq!(
+			description.text(),
+			"example package\nPrints a greeting.\n\nUsed as an example."
+		);
+		assert_eq!(
+			binary.to_map().get("Description").unwrap(),
+			"example package\nPrints a greeting.\n\nUsed as an example."
+		);
+
+		// The trailing space is kept in the value and reported
+		assert_eq!(binary.warnings.len(), 1);
+		assert_eq!(binary.warnings[0].kind, WarningKind::Whitespace);
+		assert_eq!(binary.warnings[0].context.line, Some(16));
+		assert!(matches!(
+			Deb822::from_with(data, ParseMode::Strict),
+			Err(APTError::Warning(_))
+		));
+
+		// Values are written back as they were read
+		let written = document.to_string();
+		let values = |paragraph: &Paragraph| {
+			paragraph
+				.fields
+				.iter()
+				.map(|field| (field.name.clone(), field.value.clone()))
+				.collect::<Vec<(String, String)>>()
+		};
+
+		let reread = Deb822::from(&written).unwrap();
+		assert_eq!(values(&reread.paragraphs[0]), values(source));
+		assert_eq!(values(&reread.paragraphs[1]), values(binary));
+		assert!(written.ends_with("Files:\n 0123 100 hello_2.10.orig.tar.gz\n 4567 20 hello_2.10-2.debian.tar.xz\n\nPackage: hello\nDescription: example package\n Prints a greeting.\n .\n Used as an example. \n"));
+	}
+
+	#[test]
+	fn deb822_chariz() {
+		let data = match read_to_string("./test/chariz.packages") {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let document = Deb822::from(&data).unwrap();
+		assert_eq!(document.len(), 415);
+		assert!(document
+			.iter()
+			.all(|paragraph| paragraph.warnings.is_empty()));
+
+		// Positions match where the stanzas start in the file
+		for paragraph in document.iter() {
+			assert!(data[paragraph.offset..].starts_with("Package:"));
+			for field in &paragraph.fields {
+				assert!(data[field.offset..].starts_with(&field.name));
+			}
+		}
+
+		// A single stanza reads the same on its own
+		let start = document.paragraphs[1].offset;
+		let end = document.paragraphs[2].offset;
+		let single = Paragraph::from(&data[start..end]).unwrap();
+		assert_eq!(single.to_map().len(), document.paragraphs[1].fields.len());
+	}
+}
@@ -0,0 +1,457 @@
+use crate::{
+	case_map::CaseMap,
+	packages::{address, Package, Packages},
+	release::{HashKind, Release, ReleaseHash},
+	version::compare_versions,
+};
+use std::{
+	cmp::Ordering,
+	collections::{HashMap, HashSet},
+};
+
+// A field whose value differs between two stanzas
+// None means the field is missing from that side
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+	pub field: String,
+	pub old: Option<String>,
+	pub new: Option<String>,
+}
+
+// The same (name, version, architecture) with different fields
+#[derive(Debug, Clone)]
+pub struct StanzaChange<'a> {
+	pub old: &'a Package,
+	pub new: &'a Package,
+	pub fields: Vec<FieldChange>,
+}
+
+// What changed between two snapshots of a Packages file
+// Packages are matched by name and architecture, and when the newest version
+// of one changes it's listed as an upgrade or downgrade from the old newest
+// version, instead of as a removal and an addition
+#[derive(Debug, Clone, Default)]
+pub struct PackagesDiff<'a> {
+	pub added: Vec<&'a Package>,
+	pub removed: Vec<&'a Package>,
+	pub upgraded: Vec<(&'a Package, &'a Package)>,
+	pub downgraded: Vec<(&'a Package, &'a Package)>,
+	pub changed: Vec<StanzaChange<'a>>,
+}
+
+impl<'a> PackagesDiff<'a> {
+	pub fn new(old: &'a Packages, new: &'a Packages) -> PackagesDiff<'a> {
+		let mut keys = Vec::<(&str, &str)>::new();
+		let mut groups = HashMap::<(&str, &str), (Vec<&Package>, Vec<&Package>)>::new();
+
+		for (side, packages) in [old, new].iter().enumerate() {
+			for package in packages.iter() {
+				let key = (package.package.as_str(), package.architecture.as_str());
+				let group = groups.entry(key).or_insert_with(|| {
+					keys.push(key);
+					(Vec::new(), Vec::new())
+				});
+
+				match side {
+					0 => group.0.push(package),
+					_ => group.1.push(package),
+				}
+			}
+		}
+
+		let mut diff = PackagesDiff::default();
+		for key in keys {
+			let (old, new) = &groups[&key];
+			let mut paired = HashSet::<usize>::new();
+
+			if let (Some(from), Some(to)) = (newest(old), newest(new)) {
+				let ordering = compare_versions(&to.version, &from.version);
+				match ordering {
+					Ordering::Greater => diff.upgraded.push((from, to)),
+					Ordering::Less => diff.downgraded.push((from, to)),
+					Ordering::Equal => {}
+				}
+
+				if ordering != Ordering::Equal {
+					paired.insert(address(from));
+					paired.insert(address(to));
+				}
+			}
+
+			for package in old {
+				match new.iter().find(|other| other.version == package.version) {
+					Some(other) => {
+						let fields = diff_fields(&package.map, &other.map);
+						if !fields.is_empty() {
+							diff.changed.push(StanzaChange {
+								old: package,
+								new: other,
+								fields,
+							});
+						}
+					}
+					None if !paired.contains(&address(package)) => diff.removed.push(package),
+					None => {}
+				}
+			}
+
+			for package in new {
+				if !paired.contains(&address(package))
+					&& !old.iter().any(|other| other.version == package.version)
+				{
+					diff.added.push(package);
+				}
+			}
+		}
+
+		diff
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.added.is_empty()
+			&& self.removed.is_empty()
+			&& self.upgraded.is_empty()
+			&& self.downgraded.is_empty()
+			&& self.changed.is_empty()
+	}
+}
+
+// An index file whose entry differs between two Release files
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileChange<'a> {
+	Added(&'a ReleaseHash),
+	Removed(&'a ReleaseHash),
+	Changed {
+		old: &'a ReleaseHash,
+		new: &'a ReleaseHash,
+	},
+}
+
+impl<'a> FileChange<'a> {
+	pub fn filename(&self) -> &'a str {
+		match self {
+			FileChange::Added(entry) | FileChange::Removed(entry) => &entry.filename,
+			FileChange::Changed { new, .. } => &new.filename,
+		}
+	}
+}
+
+// What changed between two Release files
+#[derive(Debug, Clone, Default)]
+pub struct ReleaseDiff<'a> {
+	// Every field except the hash tables, e.g. Date, Suite or Components
+	pub fields: Vec<FieldChange>,
+	// The changed entries of each hash table in either file, strongest first
+	pub files: Vec<(HashKind, Vec<FileChange<'a>>)>,
+}
+
+impl<'a> ReleaseDiff<'a> {
+	pub fn new(old: &'a Release, new: &'a Release) -> ReleaseDiff<'a> {
+		let fields = diff_fields(&old.map, &new.map)
+			.into_iter()
+			.filter(|change| {
+				!HashKind::ALL
+					.iter()
+					.any(|kind| kind.field().eq_ignore_ascii_case(&change.field))
+			})
+			.collect();
+
+		let mut files = Vec::new();
+		for kind in HashKind::ALL {
+			let (old, new) = match (old.hashes(kind), new.hashes(kind)) {
+				(None, None) => continue,
+				(old, new) => (
+					old.map(|x| x.as_slice()).unwrap_or_default(),
+					new.map(|x| x.as_slice()).unwrap_or_default(),
+				),
+			};
+
+			let mut changes = Vec::new();
+			for entry in old {
+				match new.iter().find(|other| other.filename == entry.filename) {
+					Some(other)
+						if other.size != entry.size
+							|| !other.hash.eq_ignore_ascii_case(&entry.hash) =>
+					{
+						changes.push(FileChange::Changed {
+							old: entry,
+							new: other,
+						})
+					}
+					Some(_) => {}
+					None => changes.push(FileChange::Removed(entry)),
+				}
+			}
+
+			for entry in new {
+				if !old.iter().any(|other| other.filename == entry.filename) {
+					changes.push(FileChange::Added(entry));
+				}
+			}
+
+			files.push((kind, changes));
+		}
+
+		ReleaseDiff { fields, files }
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.fields.is_empty() && self.files.iter().all(|(_, changes)| changes.is_empty())
+	}
+
+	pub fn field(&self, name: &str) -> Option<&FieldChange> {
+		self.fields
+			.iter()
+			.find(|change| change.field.eq_ignore_ascii_case(name))
+	}
+
+	pub fn changes(&self, kind: HashKind) -> Option<&Vec<FileChange<'a>>> {
+		self.files
+			.iter()
+			.find(|(other, _)| *other == kind)
+			.map(|(_, changes)| changes)
+	}
+
+	// The index files that were added or changed, and so need to be fetched
+	// again, judged by the strongest hash table in either file
+	pub fn refetch(&self) -> Vec<&'a str> {
+		let changes = match self.files.first() {
+			Some((_, changes)) => changes,
+			None => return Vec::new(),
+		};
+
+		let mut filenames = changes
+			.iter()
+			.filter(|change| !matches!(change, FileChange::Removed(_)))
+			.map(|change| change.filename())
+			.collect::<Vec<&str>>();
+
+		filenames.sort_unstable();
+		filenames
+	}
+}
+
+fn newest<'a>(packages: &[&'a Package]) -> Option<&'a Package> {
+	packages
+		.iter()
+		.copied()
+		.max_by(|a, b| compare_versions(&a.version, &b.version))
+}
+
+// Compares every field of two stanzas, ignoring the case of field names
+// Changes are sorted by field name
+pub(crate) fn diff_fields(old: &CaseMap, new: &CaseMap) -> Vec<FieldChange> {
+	let mut seen = HashSet::<String>::new();
+	let mut changes = Vec::new();
+
+	for map in [old, new] {
+		for key in map.iter().map(|(key, _)| key) {
+			if key.starts_with("__cased__") || !seen.insert(key.to_lowercase()) {
+				continue;
+			}
+
+			let (old, new) = (old.get(key), new.get(key));
+			if old != new {
+				changes.push(FieldChange {
+					field: key.to_string(),
+					old: old.cloned(),
+					new: new.cloned(),
+				});
+			}
+		}
+	}
+
+	changes.sort_by(|a, b| a.field.cmp(&b.field));
+	changes
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{FieldChange, FileChange, PackagesDiff, ReleaseDiff};
+	use crate::{
+		packages::Packages,
+		release::{HashKind, Release},
+	};
+	use std::fs::read_to_string;
+
+	#[test]
+	fn diff_packages() {
+		let data = match read_to_string("./test/chariz.packages") {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		// Drop every arpoison stanza, bump voltage, resize one stanza and add a
+		// new package
+		let mut stanzas = data
+			.trim()
+			.split("\n\n")
+			.filter(|stanza| !stanza.starts_with("Package: arpoison\n"))
+			.map(|stanza| {
+				if stanza.starts_with("Package: ca.kirara.voltage\n") {
+					stanza.replace("Version: 1.6.3\n", "Version: 1.6.10\n")
+				} else {
+					stanza.to_string()
+				}
+			})
+			.collect::<Vec<String>>();
+
+		let size = stanzas[1]
+			.lines()
+			.find(|line| line.starts_with("Size: "))
+			.unwrap()
+			.to_string();
+		stanzas[1] = stanzas[1].replace(&size, "Size: 1");
+		stanzas.push("Package: com.example.new\nVersion: 1.0\nArchitecture: iphoneos-arm\nFilename: debs/new.deb\nSize: 10".to_string());
+
+		let old = Packages::from(&data);
+		let new = Packages::from(&stanzas.join("\n\n"));
+		let diff = PackagesDiff::new(&old, &new);
+
+		assert_eq!(diff.removed.len(), 2);
+		assert!(diff
+			.removed
+			.iter()
+			.all(|package| package.package == "arpoison"));
+
+		assert_eq!(diff.added.len(), 1);
+		assert_eq!(diff.added[0].package, "com.example.new");
+
+		assert_eq!(diff.upgraded.len(), 1);
+		let (from, to) = diff.upgraded[0];
+		assert_eq!(
+			(from.version.as_str(), to.version.as_str()),
+			("1.6.3", "1.6.10")
+		);
+		assert!(diff.downgraded.is_empty());
+
+		assert_eq!(diff.changed.len(), 1);
+		assert_eq!(diff.changed[0].new.package, new[1].package);
+		assert_eq!(
+			diff.changed[0].fields,
+			vec![FieldChange {
+				field: "Size".to_string(),
+				old: Some(size.trim_start_matches("Size: ").to_string()),
+				new: Some("1".to_string()),
+			}]
+		);
+
+		// Swapping the sides turns the upgrade into a downgrade
+		let diff = PackagesDiff::new(&new, &old);
+		assert_eq!(diff.downgraded.len(), 1);
+		assert_eq!(diff.added.len(), 2);
+		assert!(PackagesDiff::new(&old, &old).is_empty());
+	}
+
+	#[test]
+	fn diff_packages_upgrade_changed() {
+		let old = Packages::from(
+			"Package: hello
+Architecture: amd64
+Version: 2.10-2
+Filename: pool/hello_2.10-2_amd64.deb
+Size: 100",
+		);
+
+		// 2.10-2 is still there, rebuilt with a new Size, next to 2.10-3
+		let new = Packages::from(
+			"Package: hello
+Architecture: amd64
+Version: 2.10-2
+Filename: pool/hello_2.10-2_amd64.deb
+Size: 200
+
+Package: hello
+Architecture: amd64
+Version: 2.10-3
+Filename: pool/hello_2.10-3_amd64.deb
+Size: 300",
+		);
+
+		// The rebuild is still reported next to the upgrade
+		let size = |old: &str, new: &str| {
+			vec![FieldChange {
+				field: "Size".to_string(),
+				old: Some(old.to_string()),
+				new: Some(new.to_string()),
+			}]
+		};
+
+		let diff = PackagesDiff::new(&old, &new);
+		assert_eq!(diff.upgraded.len(), 1);
+		assert_eq!(diff.changed.len(), 1);
+		assert_eq!(diff.changed[0].new.version, "2.10-2");
+		assert_eq!(diff.changed[0].fields, size("100", "200"));
+		assert!(diff.added.is_empty());
+		assert!(diff.removed.is_empty());
+
+		let diff = PackagesDiff::new(&new, &old);
+		assert_eq!(diff.downgraded.len(), 1);
+		assert_eq!(diff.changed.len(), 1);
+		assert_eq!(diff.changed[0].fields, size("200", "100"));
+		assert!(diff.added.is_empty());
+		assert!(diff.removed.is_empty());
+	}
+
+	#[test]
+	fn diff_release() {
+		let data = match read_to_string("./test/chariz.release") {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		// A newer Release without the lzma index, a changed xz index and a
+		// new component
+		let updated = data
+			.lines()
+			.filter(|line| !line.ends_with(" Packages.lzma"))
+			.map(|line| match line {
+				"Components: main" => "Components: main extra".to_string(),
+				line if line.starts_with("Date: ") => {
+					"Date: Fri, 14 Jan 2022 07:15:42 +0000".to_string()
+				}
+				line if line.ends_with(" Packages.xz") => line.replacen("39360", "39400", 1),
+				line => line.to_string(),
+			})
+			.chain([" 0123 10 extra/Packages".to_string()])
+			.collect::<Vec<String>>()
+			.join("\n");
+
+		let old = Release::from(&data).unwrap();
+		let new = Release::from(&updated).unwrap();
+		let diff = ReleaseDiff::new(&old, &new);
+
+		assert_eq!(
+			diff.fields
+				.iter()
+				.map(|x| x.field.as_str())
+				.collect::<Vec<&str>>(),
+			vec!["Components", "Date"]
+		);
+		assert_eq!(
+			diff.field("components").and_then(|x| x.new.as_deref()),
+			Some("main extra")
+		);
+
+		assert_eq!(diff.files.len(), 2);
+		assert_eq!(diff.files[0].0, HashKind::Sha512);
+		assert_eq!(diff.refetch(), vec!["Packages.xz", "extra/Packages"]);
+
+		// The extra line belongs to the last table in the file
+		let md5 = diff.changes(HashKind::Md5).unwrap();
+		assert_eq!(md5.len(), 2);
+		assert!(matches!(md5[0], FileChange::Removed(entry) if entry.filename == "Packages.lzma"));
+		assert!(
+			matches!(md5[1], FileChange::Changed { old, new } if old.size == 39360 && new.size == 39400)
+		);
+
+		let sha512 = diff.changes(HashKind::Sha512).unwrap();
+		assert_eq!(sha512.len(), 3);
+		assert!(
+			matches!(sha512[2], FileChange::Added(entry) if entry.filename == "extra/Packages")
+		);
+		assert!(diff.changes(HashKind::Sha256).is_none());
+
+		assert!(ReleaseDiff::new(&old, &old).is_empty());
+	}
+}
@@ -1,67 +1,376 @@
+use crate::limits::Limit;
 use std::{
 	error::Error,
 	fmt::{Display, Formatter, Result},
 };
 
-#[derive(Debug)]
-pub struct KVError;
+// Where an error happened, as far as the parser that raised it knows
+// Offsets and lines count from the start of the input (after "\r\n" line
+// endings are converted to "\n"), lines and columns start at 1 and stanzas
+// start at 0
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+	pub offset: Option<usize>,
+	pub line: Option<usize>,
+	pub column: Option<usize>,
+	pub stanza: Option<usize>,
+	pub field: Option<String>,
+	pub value: Option<String>,
+}
+
+impl ErrorContext {
+	pub fn field(field: &str, value: &str) -> ErrorContext {
+		ErrorContext {
+			field: Some(field.to_string()),
+			value: Some(value.to_string()),
+			..Default::default()
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		*self == ErrorContext::default()
+	}
+
+	// Fills in the position of the field from the data it was parsed from
+	pub(crate) fn locate(mut self, data: &str) -> ErrorContext {
+		let field = match &self.field {
+			Some(field) if self.line.is_none() => field,
+			_ => return self,
+		};
+
+		let mut offset = 0;
+		for (index, line) in data.split('\n').enumerate() {
+			let indent = line.len() - line.trim_start().len();
+			let key = line[indent..].split_once(':').map(|(key, _)| key);
+
+			if key.map_or(false, |key| key.eq_ignore_ascii_case(field)) {
+				self.offset = Some(offset + indent);
+				self.line = Some(index + 1);
+				self.column = Some(indent + 1);
+				break;
+			}
+
+			offset += line.len() + 1;
+		}
+
+		self
+	}
+
+	// Moves a position inside a stanza to a position inside the whole file
+	pub(crate) fn shift(&mut self, stanza: usize, offset: usize, line: usize) {
+		self.stanza = Some(stanza);
+		self.offset = self.offset.map(|x| x + offset);
+		self.line = self.line.map(|x| x + line - 1);
+	}
+}
+
+impl Display for ErrorContext {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
+		let mut parts = Vec::new();
+		if let Some(stanza) = self.stanza {
+			parts.push(format!("stanza {stanza}"));
+		}
+
+		if let Some(line) = self.line {
+			parts.push(format!("line {line}"));
+		}
+
+		if let Some(column) = self.column {
+			parts.push(format!("column {column}"));
+		}
+
+		if let Some(field) = &self.field {
+			parts.push(format!("field {field}"));
+		}
+
+		if let Some(value) = &self.value {
+			parts.push(format!("value {value:?}"));
+		}
+
+		write!(formatter, "{}", parts.join(", "))
+	}
+}
+
+// Writes a message followed by its context, e.g. "Invalid size (line 3)"
+fn write_message(formatter: &mut Formatter<'_>, message: &str, context: &ErrorContext) -> Result {
+	if context.is_empty() {
+		write!(formatter, "{}", message)
+	} else {
+		write!(formatter, "{} ({})", message, context)
+	}
+}
+
+// Every error boxes its context so that Results stay small
+#[derive(Debug, Default)]
+pub struct KVError {
+	pub context: Box<ErrorContext>,
+}
+
+impl KVError {
+	pub fn new(context: ErrorContext) -> KVError {
+		KVError {
+			context: Box::new(context),
+		}
+	}
+}
 
 impl Error for KVError {}
 
 impl Display for KVError {
 	fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
-		write!(formatter, "Failed to parse APT key-value data")
+		write_message(
+			formatter,
+			"Failed to parse APT key-value data",
+			&self.context,
+		)
 	}
 }
 
 #[derive(Debug)]
-pub struct ParseError;
+pub struct ParseError {
+	pub message: String,
+	pub context: Box<ErrorContext>,
+	source: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl ParseError {
+	pub fn new(message: &str) -> ParseError {
+		ParseError {
+			message: message.to_string(),
+			context: Box::default(),
+			source: None,
+		}
+	}
+
+	// A field whose value couldn't be parsed
+	pub fn field(message: &str, field: &str, value: &str) -> ParseError {
+		ParseError::new(message).with_context(ErrorContext::field(field, value))
+	}
+
+	// A value that isn't tied to a field, like a relation or a version
+	pub fn value(message: &str, value: &str) -> ParseError {
+		ParseError::new(message).with_context(ErrorContext {
+			value: Some(value.to_string()),
+			..Default::default()
+		})
+	}
+
+	pub fn with_context(mut self, context: ErrorContext) -> ParseError {
+		self.context = Box::new(context);
+		self
+	}
 
-impl Error for ParseError {}
+	// Fills in the position of the field from the stanza it was parsed from
+	pub(crate) fn locate(mut self, data: &str) -> ParseError {
+		*self.context = std::mem::take(&mut *self.context).locate(data);
+		self
+	}
+
+	pub fn with_source<E: Error + Send + Sync + 'static>(mut self, source: E) -> ParseError {
+		self.source = Some(Box::new(source));
+		self
+	}
+}
+
+impl Error for ParseError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		self.source
+			.as_ref()
+			.map(|source| &**source as &(dyn Error + 'static))
+	}
+}
 
 impl Display for ParseError {
 	fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
-		write!(formatter, "Failed to parse an APT value")
+		write_message(formatter, &self.message, &self.context)
 	}
 }
 
 #[derive(Debug)]
 pub struct MissingKeyError {
 	pub key: String,
-	pub data: String,
-	details: String,
+	pub context: Box<ErrorContext>,
 }
 
 impl MissingKeyError {
-	pub fn new(key: &str, data: &str) -> MissingKeyError {
+	pub fn new(key: &str) -> MissingKeyError {
 		MissingKeyError {
 			key: key.to_owned(),
-			data: data.to_owned(),
-			details: format!("Failed to find key {0}", key),
+			context: Box::default(),
 		}
 	}
 }
 
 impl Display for MissingKeyError {
 	fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
-		write!(formatter, "{}", self.details)
+		write_message(
+			formatter,
+			&format!("Failed to find key {}", self.key),
+			&self.context,
+		)
 	}
 }
 
-impl Error for MissingKeyError {
-	fn description(&self) -> &str {
-		&self.details
+impl Error for MissingKeyError {}
+
+// Input that's larger than the Limits the parser was given
+#[derive(Debug)]
+pub struct LimitError {
+	pub limit: Limit,
+	pub max: usize,
+	pub context: Box<ErrorContext>,
+}
+
+impl LimitError {
+	pub fn new(limit: Limit, max: usize, context: ErrorContext) -> LimitError {
+		LimitError {
+			limit,
+			max,
+			context: Box::new(context),
+		}
+	}
+}
+
+impl Display for LimitError {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
+		let unit = match self.limit {
+			Limit::Stanzas | Limit::Fields => "",
+			_ => " bytes",
+		};
+
+		write_message(
+			formatter,
+			&format!(
+				"Exceeded the maximum {} of {}{}",
+				self.limit, self.max, unit
+			),
+			&self.context,
+		)
+	}
+}
+
+impl Error for LimitError {}
+
+// How parsers treat input they can recover from, like duplicate fields
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+	// Recovers and records a Warning on the parsed item
+	#[default]
+	Lenient,
+	// Fails with the first Warning as an error
+	Strict,
+}
+
+impl ParseMode {
+	pub(crate) fn check(&self, warnings: &[Warning]) -> std::result::Result<(), APTError> {
+		match (self, warnings.first()) {
+			(ParseMode::Strict, Some(warning)) => Err(APTError::Warning(warning.clone())),
+			_ => Ok(()),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+	// A field that already appeared in the stanza, only the first is kept
+	DuplicateKey,
+	// A number that couldn't be parsed, e.g. a Size that becomes -1
+	InvalidNumber,
+	// A continuation line that isn't indented
+	StrayContinuation,
+	// Trailing whitespace, whitespace-only lines or whitespace other than
+	// spaces and tabs
+	Whitespace,
+}
+
+impl WarningKind {
+	pub fn message(&self) -> &'static str {
+		match self {
+			WarningKind::DuplicateKey => "Duplicate field",
+			WarningKind::InvalidNumber => "Invalid number",
+			WarningKind::StrayContinuation => "Continuation line isn't indented",
+			WarningKind::Whitespace => "Unexpected whitespace",
+		}
+	}
+}
+
+// Something a lenient parser recovered from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+	pub kind: WarningKind,
+	pub context: Box<ErrorContext>,
+}
+
+impl Warning {
+	pub fn new(kind: WarningKind, context: ErrorContext) -> Warning {
+		Warning {
+			kind,
+			context: Box::new(context),
+		}
+	}
+}
+
+impl Error for Warning {}
+
+impl Display for Warning {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
+		write_message(formatter, self.kind.message(), &self.context)
 	}
 }
 
+// New variants may be added, so matches need a wildcard arm
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum APTError {
 	KVError(KVError),
 	ParseError(ParseError),
 	MissingKeyError(MissingKeyError),
+	// A warning that strict mode turned into an error
+	Warning(Warning),
+	LimitError(LimitError),
+}
+
+impl APTError {
+	pub fn context(&self) -> &ErrorContext {
+		match self {
+			APTError::KVError(err) => &err.context,
+			APTError::ParseError(err) => &err.context,
+			APTError::MissingKeyError(err) => &err.context,
+			APTError::Warning(err) => &err.context,
+			APTError::LimitError(err) => &err.context,
+		}
+	}
+
+	pub fn context_mut(&mut self) -> &mut ErrorContext {
+		match self {
+			APTError::KVError(err) => &mut err.context,
+			APTError::ParseError(err) => &mut err.context,
+			APTError::MissingKeyError(err) => &mut err.context,
+			APTError::Warning(err) => &mut err.context,
+			APTError::LimitError(err) => &mut err.context,
+		}
+	}
+
+	// Marks an error from a single stanza with where that stanza starts
+	pub(crate) fn in_stanza(mut self, stanza: usize, offset: usize, line: usize) -> APTError {
+		self.context_mut().shift(stanza, offset, line);
+		self
+	}
 }
 
-impl Error for APTError {}
+// Display already includes the message of the wrapped error, so the source
+// is whatever caused that error, like a ParseIntError
+impl Error for APTError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			APTError::KVError(err) => err.source(),
+			APTError::ParseError(err) => err.source(),
+			APTError::MissingKeyError(err) => err.source(),
+			APTError::Warning(err) => err.source(),
+			APTError::LimitError(err) => err.source(),
+		}
+	}
+}
 
 impl Display for APTError {
 	fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
@@ -69,6 +378,8 @@ impl Display for APTError {
 			APTError::KVError(err) => write!(formatter, "{}", err),
 			APTError::ParseError(err) => write!(formatter, "{}", err),
 			APTError::MissingKeyError(err) => write!(formatter, "{}", err),
+			APTError::Warning(err) => write!(formatter, "{}", err),
+			APTError::LimitError(err) => write!(formatter, "{}", err),
 		}
 	}
 }
@@ -85,6 +396,18 @@ impl From<KVError> for APTError {
 	}
 }
 
+impl From<Warning> for APTError {
+	fn from(err: Warning) -> APTError {
+		APTError::Warning(err)
+	}
+}
+
+impl From<LimitError> for APTError {
+	fn from(err: LimitError) -> APTError {
+		APTError::LimitError(err)
+	}
+}
+
 impl From<MissingKeyError> for APTError {
 	fn from(err: MissingKeyError) -> APTError {
 		APTError::MissingKeyError(err)
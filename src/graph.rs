@@ -0,0 +1,406 @@
+use crate::{
+	errors::APTError,
+	index::PackageIndex,
+	packages::{address, Package, Packages},
+	relation::{Dependency, RelationKind},
+};
+use std::{
+	collections::{HashMap, HashSet},
+	fmt::Write,
+};
+
+// The kinds of relationships a DependencyGraph keeps track of
+pub const GRAPH_KINDS: [RelationKind; 7] = [
+	RelationKind::Depends,
+	RelationKind::Recommends,
+	RelationKind::Suggests,
+	RelationKind::Breaks,
+	RelationKind::Conflicts,
+	RelationKind::Replaces,
+	RelationKind::Enhances,
+];
+
+// One alternative of a relationship field, pointing from the package that
+// declares it to every package that can satisfy it
+#[derive(Debug, Clone)]
+pub struct Edge<'a> {
+	pub source: &'a Package,
+	pub kind: RelationKind,
+	pub dependency: Dependency,
+	pub targets: Vec<&'a Package>,
+	// Set when the dependency is part of an "a | b" group
+	pub alternative: bool,
+}
+
+pub struct DependencyGraph<'a> {
+	pub(crate) edges: Vec<Edge<'a>>,
+	// Package address to the edges it declares
+	forward: HashMap<usize, Vec<usize>>,
+	// Package or virtual package name to the edges pointing at it
+	reverse: HashMap<String, Vec<usize>>,
+	pub errors: Vec<APTError>,
+}
+
+impl<'a> DependencyGraph<'a> {
+	pub fn new(packages: &[&'a Packages]) -> DependencyGraph<'a> {
+		DependencyGraph::from_index(&PackageIndex::new(packages))
+	}
+
+	pub fn from_index(index: &PackageIndex<'a>) -> DependencyGraph<'a> {
+		let mut graph = DependencyGraph {
+			edges: Vec::new(),
+			forward: HashMap::new(),
+			reverse: HashMap::new(),
+			errors: Vec::new(),
+		};
+
+		for package in index.packages() {
+			for kind in GRAPH_KINDS {
+				let relations = match package.relations(kind) {
+					Ok(relations) => relations,
+					Err(err) => {
+						graph.errors.push(err);
+						continue;
+					}
+				};
+
+				for relation in relations {
+					let alternative = relation.alternatives.len() > 1;
+					for dependency in relation.alternatives {
+						let mut targets = Vec::<&'a Package>::new();
+						for provider in index.candidates(&dependency) {
+							if !targets.iter().any(|x| std::ptr::eq(*x, provider.package)) {
+								targets.push(provider.package);
+							}
+						}
+
+						graph.insert(Edge {
+							source: package,
+							kind,
+							dependency,
+							targets,
+							alternative,
+						});
+					}
+				}
+			}
+		}
+
+		graph
+	}
+
+	fn insert(&mut self, edge: Edge<'a>) {
+		let id = self.edges.len();
+		self.forward
+			.entry(address(edge.source))
+			.or_default()
+			.push(id);
+
+		// Edges are reachable from the name they were declared with and from
+		// the names of the packages that satisfy them
+		let mut names = vec![edge.dependency.name.as_str()];
+		for target in &edge.targets {
+			if !names.contains(&target.package.as_str()) {
+				names.push(&target.package);
+			}
+		}
+
+		for name in names {
+			self.reverse.entry(name.to_string()).or_default().push(id);
+		}
+
+		self.edges.push(edge);
+	}
+
+	pub fn len(&self) -> usize {
+		self.edges.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.edges.is_empty()
+	}
+
+	pub fn edges(&self) -> &[Edge<'a>] {
+		&self.edges
+	}
+
+	// The relationships a package declares, like apt-cache depends
+	pub fn depends(&self, package: &Package, kinds: &[RelationKind]) -> Vec<&Edge<'a>> {
+		match self.forward.get(&address(package)) {
+			Some(ids) => ids
+				.iter()
+				.map(|id| &self.edges[*id])
+				.filter(|edge| kinds.contains(&edge.kind))
+				.collect(),
+			None => Vec::new(),
+		}
+	}
+
+	// The relationships naming a package, like apt-cache rdepends
+	// Names of virtual packages and packages missing from the graph work too
+	pub fn rdepends(&self, name: &str, kinds: &[RelationKind]) -> Vec<&Edge<'a>> {
+		match self.reverse.get(name) {
+			Some(ids) => ids
+				.iter()
+				.map(|id| &self.edges[*id])
+				.filter(|edge| kinds.contains(&edge.kind))
+				.collect(),
+			None => Vec::new(),
+		}
+	}
+
+	// Every package with a relationship to the name, each listed once
+	pub fn dependents(&self, name: &str, kinds: &[RelationKind]) -> Vec<&'a Package> {
+		let mut seen = HashSet::new();
+		self.rdepends(name, kinds)
+			.into_iter()
+			.map(|edge| edge.source)
+			.filter(|package| seen.insert(address(package)))
+			.collect()
+	}
+
+	// Every package reachable from the package, not including itself
+	pub fn closure(&self, package: &'a Package, kinds: &[RelationKind]) -> Vec<&'a Package> {
+		let mut seen = HashSet::from([address(package)]);
+		let mut queue = vec![package];
+		let mut closure = Vec::new();
+
+		while let Some(current) = queue.pop() {
+			for edge in self.depends(current, kinds) {
+				for target in &edge.targets {
+					if seen.insert(address(target)) {
+						closure.push(*target);
+						queue.push(target);
+					}
+				}
+			}
+		}
+
+		closure
+	}
+
+	// Every package that reaches the name through the relationship kinds
+	pub fn reverse_closure(&self, name: &str, kinds: &[RelationKind]) -> Vec<&'a Package> {
+		let mut names = HashSet::from([name.to_string()]);
+		let mut seen = HashSet::new();
+		let mut queue = vec![name.to_string()];
+		let mut closure = Vec::new();
+
+		while let Some(current) = queue.pop() {
+			for package in self.dependents(&current, kinds) {
+				if !seen.insert(address(package)) {
+					continue;
+				}
+
+				closure.push(package);
+				if names.insert(package.package.clone()) {
+					queue.push(package.package.clone());
+				}
+			}
+		}
+
+		closure
+	}
+
+	// Renders the graph for Graphviz, with one node per package name
+	// Relationships nothing satisfies point at dashed nodes
+	pub fn to_dot(&self, kinds: &[RelationKind]) -> String {
+		let mut dot = String::from("digraph packages {\n");
+		let mut nodes = Vec::new();
+		let mut missing = Vec::new();
+		let mut edges = HashSet::new();
+		let mut lines = Vec::new();
+
+		for edge in &self.edges {
+			if !kinds.contains(&edge.kind) {
+				continue;
+			}
+
+			let source = edge.source.package.as_str();
+			if !nodes.contains(&source) {
+				nodes.push(source);
+			}
+
+			let mut targets = edge
+				.targets
+				.iter()
+				.map(|target| target.package.as_str())
+				.collect::<Vec<&str>>();
+
+			if targets.is_empty() {
+				targets.push(&edge.dependency.name);
+				if !missing.contains(&edge.dependency.name.as_str()) {
+					missing.push(&edge.dependency.name);
+				}
+			}
+
+			for target in targets {
+				if edges.insert((source, target, edge.kind)) {
+					lines.push((source, target, edge.kind));
+				}
+			}
+		}
+
+		for node in &nodes {
+			let _ = writeln!(dot, "\t\"{}\";", escape(node));
+		}
+
+		for node in &missing {
+			if !nodes.contains(node) {
+				let _ = writeln!(dot, "\t\"{}\" [style=dashed];", escape(node));
+			}
+		}
+
+		for (source, target, kind) in lines {
+			let style = match kind {
+				RelationKind::Breaks | RelationKind::Conflicts => ", color=red",
+				RelationKind::Recommends | RelationKind::Suggests | RelationKind::Enhances => {
+					", style=dashed"
+				}
+				_ => "",
+			};
+
+			let _ = writeln!(
+				dot,
+				"\t\"{}\" -> \"{}\" [label=\"{}\"{}];",
+				escape(source),
+				escape(target),
+				kind,
+				style
+			);
+		}
+
+		dot.push_str("}\n");
+		dot
+	}
+}
+
+fn escape(name: &str) -> String {
+	name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{DependencyGraph, GRAPH_KINDS};
+	use crate::{packages::Packages, relation::RelationKind};
+	use std::fs::read_to_string;
+
+	#[test]
+	fn graph_rdepends() {
+		let packages = Packages::from(
+			"Package: libc6
+Architecture: amd64
+Version: 2.35-0ubuntu3
+Breaks: nscd (<< 2.35)
+Filename: pool/libc6_2.35-0ubuntu3_amd64.deb
+Size: 100
+
+Package: nscd
+Architecture: amd64
+Version: 2.34-0ubuntu1
+Depends: libc6 (>= 2.34)
+Filename: pool/nscd_2.34-0ubuntu1_amd64.deb
+Size: 100
+
+Package: mawk
+Architecture: amd64
+Version: 1.3.4
+Depends: libc6 (>= 2.29)
+Provides: awk
+Filename: pool/mawk_1.3.4_amd64.deb
+Size: 100
+
+Package: app
+Architecture: amd64
+Version: 1.0
+Depends: awk | gawk, helper
+Recommends: libc6
+Filename: pool/app_1.0_amd64.deb
+Size: 100",
+		);
+
+		let graph = DependencyGraph::new(&[&packages]);
+		assert!(graph.errors.is_empty());
+		assert_eq!(graph.len(), 7);
+
+		let names = |packages: Vec<&crate::packages::Package>| {
+			let mut names = packages
+				.iter()
+				.map(|package| package.package.clone())
+				.collect::<Vec<String>>();
+			names.sort();
+			names
+		};
+
+		assert_eq!(
+			names(graph.dependents("libc6", &[RelationKind::Depends])),
+			vec!["mawk", "nscd"]
+		);
+
+		assert_eq!(
+			names(graph.dependents("libc6", &GRAPH_KINDS)),
+			vec!["app", "mawk", "nscd"]
+		);
+
+		assert_eq!(
+			names(graph.dependents("nscd", &[RelationKind::Breaks])),
+			vec!["libc6"]
+		);
+
+		// Virtual packages resolve to their providers in both directions
+		assert_eq!(
+			names(graph.dependents("mawk", &[RelationKind::Depends])),
+			vec!["app"]
+		);
+		assert_eq!(graph.rdepends("awk", &[RelationKind::Depends]).len(), 1);
+		assert!(graph.rdepends("gawk", &[RelationKind::Depends])[0].alternative);
+
+		let app = packages.get("app").unwrap();
+		assert_eq!(
+			names(graph.closure(app, &[RelationKind::Depends])),
+			vec!["libc6", "mawk"]
+		);
+
+		assert_eq!(
+			names(graph.reverse_closure("libc6", &[RelationKind::Depends])),
+			vec!["app", "mawk", "nscd"]
+		);
+
+		let dot = graph.to_dot(&[RelationKind::Depends, RelationKind::Breaks]);
+		assert!(dot.starts_with("digraph packages {\n"));
+		assert!(dot.contains("\t\"app\" -> \"mawk\" [label=\"Depends\"];\n"));
+		assert!(dot.contains("\t\"libc6\" -> \"nscd\" [label=\"Breaks\", color=red];\n"));
+		assert!(dot.contains("\t\"helper\" [style=dashed];\n"));
+		assert!(!dot.contains("Recommends"));
+	}
+
+	#[test]
+	fn graph_chariz() {
+		let file = "./test/chariz.packages";
+		let data = match read_to_string(file) {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let packages = Packages::from(&data);
+		let graph = DependencyGraph::new(&[&packages]);
+
+		// Every version of a package is its own node, so 20 packages depend on
+		// Cephei over 84 versions
+		let dependents = graph.dependents("ws.hbang.common", &[RelationKind::Depends]);
+		assert_eq!(dependents.len(), 84);
+
+		let mut names = dependents
+			.iter()
+			.map(|package| package.package.as_str())
+			.collect::<Vec<&str>>();
+		names.sort_unstable();
+		names.dedup();
+		assert_eq!(names.len(), 20);
+
+		let arpoison = &packages[0];
+		let closure = graph.closure(arpoison, &[RelationKind::Depends]);
+		assert!(!closure.is_empty());
+		assert!(closure.iter().all(|package| package.package == "libnet9"));
+	}
+}
@@ -0,0 +1,240 @@
+use crate::{
+	packages::{Package, Packages},
+	relation::{Dependency, Relation, RelationKind, VersionOperator},
+};
+use std::collections::HashMap;
+
+// A package that can be installed to fulfil a name, either directly or
+// through one of its Provides entries
+#[derive(Debug, Clone)]
+pub struct Provider<'a> {
+	pub package: &'a Package,
+	pub version: Option<String>,
+	pub is_virtual: bool,
+}
+
+impl<'a> Provider<'a> {
+	// Unversioned Provides never satisfy a versioned dependency
+	pub fn satisfies(&self, dependency: &Dependency) -> bool {
+		match (&dependency.constraint, &self.version) {
+			(None, _) => true,
+			(Some(constraint), Some(version)) => constraint.matches(version),
+			(Some(_), None) => false,
+		}
+	}
+}
+
+pub struct PackageIndex<'a> {
+	pub(crate) packages: Vec<&'a Package>,
+	pub(crate) providers: HashMap<String, Vec<Provider<'a>>>,
+}
+
+impl<'a> PackageIndex<'a> {
+	pub fn new(packages: &[&'a Packages]) -> PackageIndex<'a> {
+		let mut index = PackageIndex {
+			packages: Vec::new(),
+			providers: HashMap::new(),
+		};
+
+		for packages in packages {
+			index.extend(packages);
+		}
+
+		index
+	}
+
+	pub fn extend(&mut self, packages: &'a Packages) {
+		for package in packages.iter() {
+			self.insert(package);
+		}
+	}
+
+	pub fn insert(&mut self, package: &'a Package) {
+		self.packages.push(package);
+		self.providers
+			.entry(package.package.clone())
+			.or_default()
+			.push(Provider {
+				package,
+				version: Some(package.version.clone()),
+				is_virtual: false,
+			});
+
+		// Malformed Provides entries can't be matched against, so they're skipped
+		let provides = match package.relations(RelationKind::Provides) {
+			Ok(provides) => provides,
+			Err(_) => return,
+		};
+
+		for relation in provides {
+			for provided in relation.alternatives {
+				// Policy only allows "=" in Provides, other operators are ignored
+				let version = match provided.constraint {
+					Some(constraint) if constraint.operator == VersionOperator::Equal => {
+						Some(constraint.version)
+					}
+					Some(_) => continue,
+					None => None,
+				};
+
+				self.providers
+					.entry(provided.name)
+					.or_default()
+					.push(Provider {
+						package,
+						version,
+						is_virtual: true,
+					});
+			}
+		}
+	}
+
+	// Every indexed package, in insertion order
+	pub fn packages(&self) -> &[&'a Package] {
+		&self.packages
+	}
+
+	pub fn providers(&self, name: &str) -> &[Provider<'a>] {
+		match self.providers.get(name) {
+			Some(providers) => providers,
+			None => &[],
+		}
+	}
+
+	pub fn contains(&self, name: &str) -> bool {
+		self.providers.contains_key(name)
+	}
+
+	// A virtual package only exists through the Provides of other packages
+	pub fn is_virtual(&self, name: &str) -> bool {
+		let providers = self.providers(name);
+		!providers.is_empty() && providers.iter().all(|provider| provider.is_virtual)
+	}
+
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.providers.keys().map(|x| x.as_str())
+	}
+
+	pub fn candidates(&self, dependency: &Dependency) -> Vec<&Provider<'a>> {
+		self.providers(&dependency.name)
+			.iter()
+			.filter(|provider| provider.satisfies(dependency))
+			.collect()
+	}
+
+	// Candidates for every alternative, in the order APT would try them
+	pub fn candidates_for(&self, relation: &Relation) -> Vec<&Provider<'a>> {
+		relation
+			.alternatives
+			.iter()
+			.flat_map(|dependency| self.candidates(dependency))
+			.collect()
+	}
+
+	pub fn is_satisfiable(&self, relation: &Relation) -> bool {
+		relation
+			.alternatives
+			.iter()
+			.any(|dependency| !self.candidates(dependency).is_empty())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::PackageIndex;
+	use crate::{packages::Packages, relation::Relation};
+	use std::fs::read_to_string;
+
+	fn relation(data: &str) -> Relation {
+		match Relation::from(data) {
+			Ok(relation) => relation,
+			Err(err) => panic!("Failed to parse relation: {}", err),
+		}
+	}
+
+	#[test]
+	fn index_chariz() {
+		let file = "./test/chariz.packages";
+		let data = match read_to_string(file) {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let packages = Packages::from(&data);
+		let index = PackageIndex::new(&[&packages]);
+
+		assert_eq!(index.providers("arpoison").len(), 2);
+		assert!(!index.is_virtual("arpoison"));
+		assert!(index.is_virtual("me.nepeta.libcolorpicker"));
+		assert!(!index.contains("mobilesubstrate"));
+
+		assert!(index.is_satisfiable(&relation("arpoison (>= 0.7-1)")));
+		assert!(!index.is_satisfiable(&relation("arpoison (>> 0.7-1)")));
+		assert!(index.is_satisfiable(&relation("dev.theos.orion (= 1.0.0)")));
+		assert!(!index.is_satisfiable(&relation("dev.theos.orion (>= 2.0)")));
+		assert!(index.is_satisfiable(&relation("mobilesubstrate | arpoison")));
+		assert!(!index.is_satisfiable(&relation("mobilesubstrate")));
+
+		// Both versioned providers of libcolorpicker qualify
+		let candidates = index.candidates_for(&relation("me.nepeta.libcolorpicker (>= 1.6.2)"));
+		assert!(candidates.len() >= 2);
+		assert!(candidates.iter().all(|provider| provider.is_virtual));
+	}
+
+	#[test]
+	fn index_virtual_logind() {
+		let packages = Packages::from(
+			"Package: libpam-systemd
+Architecture: amd64
+Version: 249.11-0ubuntu3
+Provides: default-logind (= 249.11-0ubuntu3), logind (= 249.11-0ubuntu3)
+Filename: pool/main/s/systemd/libpam-systemd_249.11-0ubuntu3_amd64.deb
+Size: 202946
+
+Package: elogind
+Architecture: amd64
+Version: 246.10-3
+Provides: logind
+Filename: pool/universe/e/elogind/elogind_246.10-3_amd64.deb
+Size: 209540",
+		);
+
+		let index = PackageIndex::new(&[&packages]);
+		let recommends = relation("default-logind | logind");
+		assert!(index.is_satisfiable(&recommends));
+
+		let candidates = index
+			.candidates_for(&recommends)
+			.iter()
+			.map(|provider| provider.package.package.as_str())
+			.collect::<Vec<&str>>();
+
+		assert_eq!(
+			candidates,
+			vec!["libpam-systemd", "libpam-systemd", "elogind"]
+		);
+
+		// elogind's unversioned Provides can't satisfy a versioned dependency
+		let candidates = index.candidates_for(&relation("logind (>= 240)"));
+		assert_eq!(candidates.len(), 1);
+		assert_eq!(candidates[0].package.package, "libpam-systemd");
+	}
+
+	#[test]
+	fn index_provides_operator() {
+		let packages = Packages::from(
+			"Package: mawk
+Architecture: amd64
+Version: 1.3.4
+Provides: awk (= 1.3.4), gawk (>= 5.0)
+Filename: pool/main/m/mawk/mawk_1.3.4_amd64.deb
+Size: 72200",
+		);
+
+		// Only exact versions can be provided, so gawk isn't provided at all
+		let index = PackageIndex::new(&[&packages]);
+		assert!(index.is_satisfiable(&relation("awk (= 1.3.4)")));
+		assert!(!index.contains("gawk"));
+		assert!(!index.is_satisfiable(&relation("gawk (>= 5.0)")));
+	}
+}
@@ -0,0 +1,447 @@
+use crate::{
+	index::PackageIndex,
+	packages::Package,
+	relation::{Relation, RelationKind},
+	universe::Universe,
+	version::compare_versions,
+};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+// Searches that take longer than this are reported as Unknown
+const STEP_LIMIT: usize = 100_000;
+const REASON_LIMIT: usize = 8;
+
+#[derive(Debug, Clone)]
+pub enum Reason<'a> {
+	// Nothing in the universe satisfies a relation of the last package in the chain
+	Missing {
+		chain: Vec<&'a Package>,
+		relation: Relation,
+	},
+	// Every candidate for the relation clashes with a package already selected
+	// A kind of None means another version of the same package was selected
+	Conflict {
+		chain: Vec<&'a Package>,
+		relation: Relation,
+		candidate: &'a Package,
+		conflicting: &'a Package,
+		kind: Option<RelationKind>,
+	},
+	Malformed {
+		package: &'a Package,
+		kind: RelationKind,
+	},
+}
+
+#[derive(Debug, Clone)]
+pub enum InstallStatus<'a> {
+	// Holds one installation set that proves the package can be installed
+	Installable(Vec<&'a Package>),
+	Broken(Vec<Reason<'a>>),
+	Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct PackageReport<'a> {
+	pub package: &'a Package,
+	pub status: InstallStatus<'a>,
+}
+
+impl<'a> PackageReport<'a> {
+	pub fn is_installable(&self) -> bool {
+		matches!(self.status, InstallStatus::Installable(_))
+	}
+
+	pub fn is_broken(&self) -> bool {
+		matches!(self.status, InstallStatus::Broken(_))
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct InstallabilityReport<'a> {
+	pub packages: Vec<PackageReport<'a>>,
+}
+
+impl<'a> InstallabilityReport<'a> {
+	// Checks every package of the index on its own, like dose-distcheck
+	pub fn new(index: &PackageIndex<'a>, native_architecture: &str) -> InstallabilityReport<'a> {
+		let universe = Universe::new(
+			index,
+			native_architecture,
+			&[RelationKind::PreDepends, RelationKind::Depends],
+			&|a, b| compare_versions(&b.version, &a.version),
+		);
+
+		let packages = (0..universe.len())
+			.into_par_iter()
+			.map(|id| PackageReport {
+				package: universe.packages[id],
+				status: check(&universe, id),
+			})
+			.collect();
+
+		InstallabilityReport { packages }
+	}
+
+	pub fn get(&self, name: &str) -> Vec<&PackageReport<'a>> {
+		self.packages
+			.iter()
+			.filter(|report| report.package.package == name)
+			.collect()
+	}
+
+	pub fn installable(&self) -> impl Iterator<Item = &PackageReport<'a>> {
+		self.packages
+			.iter()
+			.filter(|report| report.is_installable())
+	}
+
+	pub fn broken(&self) -> impl Iterator<Item = &PackageReport<'a>> {
+		self.packages.iter().filter(|report| report.is_broken())
+	}
+
+	pub fn unknown(&self) -> impl Iterator<Item = &PackageReport<'a>> {
+		self.packages
+			.iter()
+			.filter(|report| matches!(report.status, InstallStatus::Unknown))
+	}
+}
+
+fn check<'a>(universe: &Universe<'a>, root: usize) -> InstallStatus<'a> {
+	if let Some(kind) = universe.malformed[root] {
+		return InstallStatus::Broken(vec![Reason::Malformed {
+			package: universe.packages[root],
+			kind,
+		}]);
+	}
+
+	let mut search = Search {
+		universe,
+		installed: vec![false; universe.len()],
+		selected: Vec::new(),
+		slots: HashMap::new(),
+		parents: vec![None; universe.len()],
+		steps: 0,
+		reasons: Vec::new(),
+		seen: HashSet::new(),
+	};
+
+	search.install(root, None);
+	let mut agenda = search.agenda_for(root);
+
+	match search.solve(&mut agenda, 0) {
+		Some(true) => InstallStatus::Installable(
+			search
+				.selected
+				.iter()
+				.map(|id| universe.packages[*id])
+				.collect(),
+		),
+		Some(false) => InstallStatus::Broken(search.reasons),
+		None => InstallStatus::Unknown,
+	}
+}
+
+struct Search<'u, 'a> {
+	universe: &'u Universe<'a>,
+	installed: Vec<bool>,
+	selected: Vec<usize>,
+	slots: HashMap<(&'a str, &'a str), usize>,
+	parents: Vec<Option<usize>>,
+	steps: usize,
+	reasons: Vec<Reason<'a>>,
+	seen: HashSet<String>,
+}
+
+impl<'u, 'a> Search<'u, 'a> {
+	fn agenda_for(&self, id: usize) -> Vec<(usize, usize)> {
+		(0..self.universe.requirements[id].len())
+			.map(|requirement| (id, requirement))
+			.collect()
+	}
+
+	fn install(&mut self, id: usize, parent: Option<usize>) {
+		self.installed[id] = true;
+		self.selected.push(id);
+		self.slots.insert(self.universe.slot(id), id);
+		self.parents[id] = parent;
+	}
+
+	fn uninstall(&mut self, id: usize) {
+		self.installed[id] = false;
+		self.selected.pop();
+		self.slots.remove(&self.universe.slot(id));
+		self.parents[id] = None;
+	}
+
+	fn chain(&self, id: usize) -> Vec<&'a Package> {
+		let mut chain = vec![self.universe.packages[id]];
+		let mut current = id;
+
+		while let Some(parent) = self.parents[current] {
+			chain.push(self.universe.packages[parent]);
+			current = parent;
+		}
+
+		chain.reverse();
+		chain
+	}
+
+	fn record(&mut self, reason: Reason<'a>) {
+		if self.reasons.len() >= REASON_LIMIT {
+			return;
+		}
+
+		let key = match &reason {
+			Reason::Missing { chain, relation } => {
+				format!("missing:{}:{}", chain.len(), relation)
+			}
+			Reason::Conflict {
+				relation,
+				candidate,
+				conflicting,
+				..
+			} => format!(
+				"conflict:{}:{}:{}:{}",
+				relation, candidate.package, candidate.version, conflicting.package
+			),
+			Reason::Malformed { package, kind } => {
+				format!("malformed:{}:{}", package.package, kind)
+			}
+		};
+
+		if self.seen.insert(key) {
+			self.reasons.push(reason);
+		}
+	}
+
+	// Finds a package already selected that rules out the candidate
+	fn blocker(&self, candidate: usize) -> Option<(usize, Option<RelationKind>)> {
+		if let Some(&existing) = self.slots.get(&self.universe.slot(candidate)) {
+			return Some((existing, None));
+		}
+
+		for &selected in &self.selected {
+			if let Some((_, restriction)) = self.universe.clash(candidate, selected) {
+				return Some((selected, Some(restriction.kind)));
+			}
+		}
+
+		None
+	}
+
+	// Returns None once the step limit is exhausted
+	fn solve(&mut self, agenda: &mut Vec<(usize, usize)>, position: usize) -> Option<bool> {
+		let mut position = position;
+		while position < agenda.len() {
+			let (owner, index) = agenda[position];
+			let requirement = &self.universe.requirements[owner][index];
+
+			if requirement.candidates.iter().any(|id| self.installed[*id]) {
+				position += 1;
+				continue;
+			}
+
+			if requirement.candidates.is_empty() {
+				let reason = Reason::Missing {
+					chain: self.chain(owner),
+					relation: requirement.relation.clone(),
+				};
+
+				self.record(reason);
+				return Some(false);
+			}
+
+			for &candidate in &requirement.candidates {
+				self.steps += 1;
+				if self.steps > STEP_LIMIT {
+					return None;
+				}
+
+				if self.universe.malformed[candidate].is_some() {
+					continue;
+				}
+
+				if let Some((conflicting, kind)) = self.blocker(candidate) {
+					let reason = Reason::Conflict {
+						chain: self.chain(owner),
+						relation: requirement.relation.clone(),
+						candidate: self.universe.packages[candidate],
+						conflicting: self.universe.packages[conflicting],
+						kind,
+					};
+
+					self.record(reason);
+					continue;
+				}
+
+				self.install(candidate, Some(owner));
+				let mark = agenda.len();
+				agenda.extend(self.agenda_for(candidate));
+
+				match self.solve(agenda, position + 1) {
+					Some(true) => return Some(true),
+					Some(false) => {}
+					None => return None,
+				}
+
+				agenda.truncate(mark);
+				self.uninstall(candidate);
+			}
+
+			return Some(false);
+		}
+
+		Some(true)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{InstallStatus, InstallabilityReport, Reason};
+	use crate::{index::PackageIndex, packages::Packages, relation::RelationKind};
+	use std::fs::read_to_string;
+
+	#[test]
+	fn installability_conflicts() {
+		let packages = Packages::from(
+			"Package: app
+Architecture: amd64
+Version: 1.0
+Depends: libfoo (>= 2.0) | libfoo-compat, helper
+Filename: pool/app_1.0_amd64.deb
+Size: 100
+
+Package: libfoo
+Architecture: amd64
+Version: 2.1
+Filename: pool/libfoo_2.1_amd64.deb
+Size: 100
+
+Package: helper
+Architecture: all
+Version: 1.0
+Depends: libfoo (<< 2.0)
+Filename: pool/helper_1.0_all.deb
+Size: 100
+
+Package: libfoo
+Architecture: amd64
+Version: 1.5
+Filename: pool/libfoo_1.5_amd64.deb
+Size: 100
+
+Package: libfoo-compat
+Architecture: amd64
+Version: 1.0
+Breaks: helper (<< 2.0)
+Filename: pool/libfoo-compat_1.0_amd64.deb
+Size: 100
+
+Package: orphan
+Architecture: amd64
+Version: 1.0
+Depends: helper, missing-library
+Filename: pool/orphan_1.0_amd64.deb
+Size: 100",
+		);
+
+		let index = PackageIndex::new(&[&packages]);
+		let report = InstallabilityReport::new(&index, "amd64");
+
+		assert_eq!(report.packages.len(), 6);
+		assert_eq!(report.installable().count(), 4);
+
+		// app needs libfoo >= 2.0 or libfoo-compat, but helper needs an older
+		// libfoo and libfoo-compat breaks helper
+		let app = report.get("app")[0];
+		let reasons = match &app.status {
+			InstallStatus::Broken(reasons) => reasons,
+			status => panic!("Expected app to be broken: {:?}", status),
+		};
+
+		assert!(reasons.iter().any(|reason| match reason {
+			Reason::Conflict {
+				candidate,
+				conflicting,
+				kind,
+				..
+			} => {
+				candidate.package == "helper"
+					&& conflicting.package == "libfoo-compat"
+					&& *kind == Some(RelationKind::Breaks)
+			}
+			_ => false,
+		}));
+
+		assert!(reasons.iter().any(|reason| match reason {
+			Reason::Conflict {
+				candidate,
+				conflicting,
+				kind,
+				..
+			} => {
+				candidate.package == "libfoo" && conflicting.package == "libfoo" && kind.is_none()
+			}
+			_ => false,
+		}));
+
+		let orphan = report.get("orphan")[0];
+		match &orphan.status {
+			InstallStatus::Broken(reasons) => match &reasons[0] {
+				Reason::Missing { chain, relation } => {
+					assert_eq!(chain.len(), 1);
+					assert_eq!(chain[0].package, "orphan");
+					assert_eq!(relation.to_string(), "missing-library");
+				}
+				reason => panic!("Expected a missing dependency: {:?}", reason),
+			},
+			status => panic!("Expected orphan to be broken: {:?}", status),
+		}
+
+		let helper = report.get("helper")[0];
+		match &helper.status {
+			InstallStatus::Installable(set) => {
+				let names = set
+					.iter()
+					.map(|package| format!("{} {}", package.package, package.version))
+					.collect::<Vec<String>>();
+				assert_eq!(names, vec!["helper 1.0", "libfoo 1.5"]);
+			}
+			status => panic!("Expected helper to be installable: {:?}", status),
+		}
+	}
+
+	#[test]
+	fn installability_chariz() {
+		let file = "./test/chariz.packages";
+		let data = match read_to_string(file) {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let packages = Packages::from(&data);
+		let index = PackageIndex::new(&[&packages]);
+		let report = InstallabilityReport::new(&index, "iphoneos-arm");
+
+		assert_eq!(report.packages.len(), packages.len());
+		assert_eq!(report.unknown().count(), 0);
+
+		// arpoison only needs libnet9, which chariz ships itself
+		assert!(report.get("arpoison").iter().all(|x| x.is_installable()));
+
+		// Tweaks need the jailbreak bootstrap, which isn't part of the repo
+		let voltage = report.get("ca.kirara.voltage")[0];
+		match &voltage.status {
+			InstallStatus::Broken(reasons) => {
+				assert!(reasons.iter().any(|reason| match reason {
+					Reason::Missing { relation, .. } =>
+						relation.to_string().starts_with("firmware"),
+					_ => false,
+				}));
+			}
+			status => panic!("Expected voltage to be broken: {:?}", status),
+		}
+	}
+}
@@ -1,16 +1,73 @@
+pub mod audit;
+pub mod borrowed;
 pub mod case_map;
 pub mod control;
+pub mod deb822;
+pub mod diff;
 pub mod errors;
+pub mod graph;
+pub mod index;
+pub mod installability;
+pub mod limits;
+pub mod lint;
+pub mod loader;
+pub mod ordering;
 pub mod packages;
+pub mod path;
+pub mod pdiff;
+pub mod preferences;
+pub mod relation;
 pub mod release;
-
+pub mod repository;
+pub mod satisfy;
+pub mod solver;
+pub mod sources;
+pub mod stanza;
+pub mod status;
+pub mod translations;
+pub mod transport;
+mod universe;
+pub mod version;
+
+pub use audit::*;
+pub use borrowed::*;
 pub use control::*;
+pub use deb822::*;
+pub use diff::*;
+pub use graph::*;
+pub use index::*;
+pub use installability::*;
+pub use limits::*;
+pub use lint::*;
+pub use loader::*;
+pub use ordering::*;
 pub use packages::*;
+pub use path::*;
+pub use pdiff::*;
+pub use preferences::*;
+pub use relation::*;
 pub use release::*;
+pub use repository::*;
+pub use satisfy::*;
+pub use solver::*;
+pub use sources::*;
+pub use stanza::*;
+pub use status::*;
+pub use translations::*;
+pub use transport::*;
+pub use version::*;
+
+#[cfg(feature = "derive")]
+pub use apt_parser_derive::FromStanza;
+
+// Lets the code generated by apt-parser-derive name this crate from inside
+// it, which the tests rely on
+extern crate self as apt_parser;
 
 use case_map::CaseMap;
-use errors::KVError;
-use regex::Regex;
+use errors::{APTError, KVError, ParseMode, Warning};
+use memchr::{memchr_iter, memmem};
+use std::borrow::Cow;
 
 #[warn(clippy::all)]
 #[warn(clippy::correctness)]
@@ -19,113 +76,102 @@ use regex::Regex;
 #[warn(clippy::style)]
 #[warn(clippy::complexity)]
 #[warn(clippy::perf)]
-
 // HashMap<String, String>
+/// # Errors
+///
+/// Returns a `KVError` if a line cannot be split into a key and a value
 pub fn parse_kv(raw_apt_data: &str) -> Result<CaseMap, KVError> {
-	// clean the string
-	let binding = raw_apt_data.replace("\r\n", "\n").replace('\0', "");
-	let apt_data = binding.trim().split('\n');
-
-	let mut fields = CaseMap::new();
-	let mut current_key = "";
-
-	// Compile our regex before-hand
-	let regex = match Regex::new(r"^(.*?):\s(.*)$") {
-		Ok(regex) => regex,
-		Err(_) => return Err(KVError),
-	};
+	parse_kv_warnings(raw_apt_data).map(|(fields, _)| fields)
+}
 
-	for line in apt_data {
-		let line = line.trim();
+// Like parse_kv, but also returns what it had to recover from
+// In strict mode the first warning is returned as an error instead
+pub fn parse_kv_with(
+	raw_apt_data: &str,
+	mode: ParseMode,
+) -> Result<(CaseMap, Vec<Warning>), APTError> {
+	let (fields, warnings) = parse_kv_warnings(raw_apt_data)?;
+	mode.check(&warnings)?;
+	Ok((fields, warnings))
+}
 
-		if line.is_empty() {
-			continue;
-		}
+pub(crate) fn parse_kv_warnings(raw_apt_data: &str) -> Result<(CaseMap, Vec<Warning>), KVError> {
+	let paragraph = Paragraph::parse(raw_apt_data)?;
+	Ok((paragraph.to_map(), paragraph.warnings))
+}
 
-		if !regex.is_match(line) {
-			if line.ends_with(':') {
-				let mut chars = line.chars();
-				chars.next_back(); // Pop the last character off
+// Description and its localized Description-<lang> variants are multiline
+// Description-md5 is a plain value even though it shares the prefix
+pub(crate) fn is_description(key: &str) -> bool {
+	let key = key.to_lowercase();
+	if key == "description" {
+		return true;
+	}
 
-				current_key = chars.as_str();
-				fields.insert(current_key, "");
-				continue;
-			}
+	key.starts_with("description-") && key != "description-md5"
+}
 
-			if !current_key.is_empty() {
-				let existing_value = match fields.get(current_key) {
-					Some(value) => value,
-					None => "",
-				};
-
-				// On multiline descriptions, the '.' signifies a newline (blank)
-				if line == "." {
-					let updated_key = format!("{existing_value}\n\n");
-					fields.insert(current_key, &updated_key);
-				} else {
-					let updated_key = match existing_value.ends_with('\n') {
-						true => format!("{existing_value}{line}"),
-						false => format!("{existing_value} {line}"),
-					};
-
-					fields.insert(current_key, &updated_key);
-				}
-
-				continue;
-			}
-		}
+// Converts "\r\n" line endings to "\n" and drops NUL bytes in a single pass,
+// only copying the data when it contains either
+pub(crate) fn normalize(data: &str) -> Cow<'_, str> {
+	if !data.contains(['\r', '\0']) {
+		return Cow::Borrowed(data);
+	}
 
-		let captures = regex.captures(line);
-		if captures.is_none() {
-			return Err(KVError);
+	let mut normalized = String::with_capacity(data.len());
+	let mut chars = data.chars().peekable();
+	while let Some(x) = chars.next() {
+		match x {
+			'\0' => {}
+			'\r' if chars.peek() == Some(&'\n') => {}
+			_ => normalized.push(x),
 		}
+	}
 
-		let captures = captures.unwrap();
-		let key = captures.get(1);
-		let value = captures.get(2);
-
-		if key.is_none() || value.is_none() {
-			return Err(KVError);
-		};
-
-		let key = key.unwrap().as_str();
-		let value = value.unwrap().as_str();
-
-		if fields.contains_key(key) {
-			continue;
-		}
+	Cow::Owned(normalized)
+}
 
-		if key.to_lowercase() == "description" && !value.is_empty() {
-			let format = format!("{value}\n");
-			fields.insert(key, &format);
-		} else {
-			if current_key.to_lowercase() == "description" {
-				let existing_value = match fields.get(current_key) {
-					Some(value) => value,
-					None => "",
-				};
-
-				if existing_value.ends_with('\n') {
-					let substring = existing_value
-						.chars()
-						.take(existing_value.len() - 1)
-						.collect::<String>();
-
-					fields.insert(current_key, &substring);
-				}
+// Replaces lines commented out with "#" by spaces, for files that allow
+// comments between fields. They're blanked out rather than removed so errors
+// keep pointing at the right line
+pub(crate) fn blank_comments(data: &str) -> String {
+	data.replace("\r\n", "\n")
+		.split('\n')
+		.map(|line| {
+			if line.trim_start().starts_with('#') {
+				" ".repeat(line.len())
+			} else {
+				line.to_string()
 			}
+		})
+		.collect::<Vec<String>>()
+		.join("\n")
+}
 
-			fields.insert(key, value);
-		}
-
-		current_key = key;
-	}
-
-	Ok(fields)
+// Splits data into stanzas separated by blank lines, along with the byte
+// offset and line number each one starts at
+// Stanzas are produced lazily so callers can stop at a limit
+pub(crate) fn split_stanzas(data: &str) -> impl Iterator<Item = (usize, usize, &str)> {
+	let mut offset = data.len() - data.trim_start().len();
+	let mut line = data[..offset].matches('\n').count() + 1;
+
+	let trimmed = data.trim();
+	// Matches don't overlap, so "\n\n\n" splits the same as str::split
+	memmem::find_iter(trimmed.as_bytes(), b"\n\n")
+		.chain(std::iter::once(trimmed.len()))
+		.scan(0, move |start, end| {
+			let stanza = &trimmed[*start..end];
+			*start = end + 2;
+
+			let position = (offset, line, stanza);
+			offset += stanza.len() + 2;
+			line += memchr_iter(b'\n', stanza.as_bytes()).count() + 2;
+			Some(position)
+		})
 }
 
 pub fn make_array(raw_data: Option<&String>) -> Option<Vec<String>> {
-	return match raw_data {
+	match raw_data {
 		Some(raw_data) => {
 			let mut data = Vec::new();
 			for line in raw_data.split(',') {
@@ -135,5 +181,5 @@ pub fn make_array(raw_data: Option<&String>) -> Option<Vec<String>> {
 			Some(data)
 		}
 		None => None,
-	};
+	}
 }
@@ -0,0 +1,320 @@
+use crate::errors::{APTError, ErrorContext, LimitError};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+	// Bytes in the whole input
+	InputSize,
+	// Bytes in a single stanza
+	StanzaSize,
+	// Stanzas in the whole input
+	Stanzas,
+	// Bytes in a single field, including its continuation lines
+	FieldLength,
+	// Fields in a single stanza
+	Fields,
+}
+
+impl Display for Limit {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		let name = match self {
+			Limit::InputSize => "input size",
+			Limit::StanzaSize => "stanza size",
+			Limit::Stanzas => "number of stanzas",
+			Limit::FieldLength => "field length",
+			Limit::Fields => "number of fields",
+		};
+
+		write!(formatter, "{name}")
+	}
+}
+
+// Caps on how much untrusted input a parser accepts before it gives up
+// The defaults are well above what the Debian and Ubuntu archives need, e.g. a
+// Release file's SHA256 field or an Ubuntu universe Packages file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+	pub input_size: usize,
+	pub stanza_size: usize,
+	pub stanzas: usize,
+	pub field_length: usize,
+	pub fields: usize,
+}
+
+impl Default for Limits {
+	fn default() -> Limits {
+		Limits {
+			input_size: 1 << 30,
+			stanza_size: 16 << 20,
+			stanzas: 1_000_000,
+			field_length: 8 << 20,
+			fields: 1000,
+		}
+	}
+}
+
+impl Limits {
+	pub const UNLIMITED: Limits = Limits {
+		input_size: usize::MAX,
+		stanza_size: usize::MAX,
+		stanzas: usize::MAX,
+		field_length: usize::MAX,
+		fields: usize::MAX,
+	};
+
+	fn error(limit: Limit, max: usize, context: ErrorContext) -> APTError {
+		APTError::LimitError(LimitError::new(limit, max, context))
+	}
+
+	pub(crate) fn check_input(&self, data: &str) -> Result<(), APTError> {
+		self.check_size(data.len()).map_err(APTError::LimitError)
+	}
+
+	// Also used for input that isn't text yet, like a decompressed index
+	pub(crate) fn check_size(&self, size: usize) -> Result<(), LimitError> {
+		match size > self.input_size {
+			true => Err(LimitError::new(
+				Limit::InputSize,
+				self.input_size,
+				ErrorContext::default(),
+			)),
+			false => Ok(()),
+		}
+	}
+
+	pub(crate) fn check_stanzas(&self, count: usize) -> Result<(), APTError> {
+		match count > self.stanzas {
+			true => Err(Limits::error(
+				Limit::Stanzas,
+				self.stanzas,
+				ErrorContext {
+					stanza: Some(self.stanzas),
+					..Default::default()
+				},
+			)),
+			false => Ok(()),
+		}
+	}
+
+	// Scans a stanza without allocating, so that parse_kv only ever sees
+	// stanzas within the limits
+	pub(crate) fn check_stanza(&self, stanza: &str) -> Result<(), APTError> {
+		if stanza.len() > self.stanza_size {
+			return Err(Limits::error(
+				Limit::StanzaSize,
+				self.stanza_size,
+				ErrorContext {
+					offset: Some(0),
+					line: Some(1),
+					column: Some(1),
+					..Default::default()
+				},
+			));
+		}
+
+		let mut fields = 0;
+		let mut length = 0;
+		let mut field = (0, 0, "");
+		let mut offset = 0;
+
+		for (index, line) in stanza.split('\n').enumerate() {
+			let key = match line.starts_with(char::is_whitespace) {
+				true => None,
+				false => line.split_once(':').map(|(key, _)| key),
+			};
+
+			match key {
+				Some(key) => {
+					fields += 1;
+					length = line.len() - key.len() - 1;
+					field = (offset, index + 1, key);
+				}
+				None => length += line.len() + 1,
+			}
+
+			offset += line.len() + 1;
+
+			let context = ErrorContext {
+				offset: Some(field.0),
+				line: Some(field.1),
+				column: Some(1),
+				field: Some(field.2.to_string()),
+				..Default::default()
+			};
+
+			if fields > self.fields {
+				return Err(Limits::error(Limit::Fields, self.fields, context));
+			}
+
+			if length > self.field_length {
+				return Err(Limits::error(
+					Limit::FieldLength,
+					self.field_length,
+					context,
+				));
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Limit, Limits};
+	use crate::{
+		borrowed::PackageRef, errors::APTError, packages::Packages, release::Release,
+		status::DpkgStatus, translations::Translations,
+	};
+	use std::fs::read_to_string;
+
+	fn limit(err: &APTError) -> Option<Limit> {
+		match err {
+			APTError::LimitError(err) => Some(err.limit),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn limits_packages() {
+		let data = match read_to_string("./test/chariz.packages") {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let packages = Packages::from_limited(&data, Default::default(), &Limits::default());
+		assert_eq!(packages.len(), 415);
+		assert!(packages.errors.is_empty());
+
+		let check = |limits: Limits| {
+			let packages = Packages::from_limited(&data, Default::default(), &limits);
+			(packages.len(), packages.errors)
+		};
+
+		let (count, errors) = check(Limits {
+			input_size: 1000,
+			..Default::default()
+		});
+		assert_eq!(count, 0);
+		assert_eq!(limit(&errors[0]), Some(Limit::InputSize));
+
+		let (count, errors) = check(Limits {
+			stanzas: 100,
+			..Default::default()
+		});
+		assert_eq!(count, 0);
+		assert_eq!(limit(&errors[0]), Some(Limit::Stanzas));
+
+		// Per-stanza limits only drop the stanzas that break them
+		let (count, errors) = check(Limits {
+			fields: 15,
+			..Default::default()
+		});
+		assert!(count > 0 && !errors.is_empty());
+		assert_eq!(count + errors.len(), 415);
+		assert!(errors.iter().all(|err| limit(err) == Some(Limit::Fields)));
+
+		let (_, errors) = check(Limits {
+			field_length: 200,
+			..Default::default()
+		});
+		let err = &errors[0];
+		assert_eq!(limit(err), Some(Limit::FieldLength));
+
+		// The error points at the start of the field that was too long
+		let field = err.context().field.clone().unwrap();
+		let offset = err.context().offset.unwrap();
+		assert!(data[offset..].starts_with(&format!("{field}:")));
+	}
+
+	#[test]
+	fn limits_other_parsers() {
+		let data = match read_to_string("./test/chariz.packages") {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		// PackageRef stops at the first stanza past the limit
+		let limits = Limits {
+			stanzas: 100,
+			..Default::default()
+		};
+		let results = PackageRef::iter_limited(&data, &limits).collect::<Vec<_>>();
+		assert_eq!(results.len(), 101);
+		assert!(results[..100].iter().all(Result::is_ok));
+		assert_eq!(
+			limit(results[100].as_ref().unwrap_err()),
+			Some(Limit::Stanzas)
+		);
+
+		let limits = Limits {
+			input_size: 1000,
+			..Default::default()
+		};
+		let results = PackageRef::iter_limited(&data, &limits).collect::<Vec<_>>();
+		assert_eq!(results.len(), 1);
+		assert_eq!(
+			limit(results[0].as_ref().unwrap_err()),
+			Some(Limit::InputSize)
+		);
+
+		let data = match read_to_string("./test/jammy.translation-en") {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let limits = Limits {
+			stanzas: 1,
+			..Default::default()
+		};
+		let translations = Translations::from_limited(&data, &limits);
+		assert!(translations.is_empty());
+		assert_eq!(limit(&translations.errors[0]), Some(Limit::Stanzas));
+
+		let status = DpkgStatus::from_limited(
+			"Package: hello\nStatus: install ok installed\nVersion: 2.10-2\nArchitecture: amd64\nDescription: example package\n",
+			&Limits {
+				fields: 4,
+				..Default::default()
+			},
+		);
+		assert!(status.is_empty());
+		assert_eq!(limit(&status.errors[0]), Some(Limit::Fields));
+	}
+
+	#[test]
+	fn limits_release() {
+		let data = match read_to_string("./test/jammy.release") {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		assert!(Release::from(&data).is_ok());
+
+		let check = |limits: Limits| match Release::from_limited(&data, Default::default(), &limits)
+		{
+			Ok(_) => panic!("Expected {:?} to be exceeded", limits),
+			Err(err) => err,
+		};
+
+		let err = check(Limits {
+			field_length: 4096,
+			..Default::default()
+		});
+		assert_eq!(limit(&err), Some(Limit::FieldLength));
+		assert_eq!(err.context().field.as_deref(), Some("MD5Sum"));
+		assert_eq!(
+			err.to_string(),
+			format!(
+				"Exceeded the maximum field length of 4096 bytes (line {}, column 1, field MD5Sum)",
+				data.lines().position(|x| x == "MD5Sum:").unwrap() + 1
+			)
+		);
+
+		let err = check(Limits {
+			stanza_size: 1000,
+			..Default::default()
+		});
+		assert_eq!(limit(&err), Some(Limit::StanzaSize));
+	}
+}
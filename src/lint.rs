@@ -0,0 +1,629 @@
+use crate::{
+	case_map::CaseMap,
+	control::Control,
+	errors::{APTError, Warning, WarningKind},
+	make_array,
+	packages::Package,
+	parse_kv_warnings,
+	relation::{parse_relations, RelationKind},
+	version::Version,
+};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+	Info,
+	Warning,
+	Error,
+}
+
+impl Severity {
+	// The single letter lintian prints before each tag
+	pub fn letter(&self) -> char {
+		match self {
+			Severity::Info => 'I',
+			Severity::Warning => 'W',
+			Severity::Error => 'E',
+		}
+	}
+}
+
+// The kind of stanza being linted, which decides the required fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StanzaKind {
+	// DEBIAN/control of a binary package
+	Control,
+	// An entry of a Packages index
+	Package,
+	// A .dsc file or an entry of a Sources index
+	Source,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+	pub tag: &'static str,
+	pub severity: Severity,
+	pub field: Option<String>,
+	pub value: Option<String>,
+}
+
+impl Finding {
+	fn new(tag: &'static str, severity: Severity, field: &str, value: Option<&str>) -> Finding {
+		Finding {
+			tag,
+			severity,
+			field: Some(field.to_string()),
+			value: value.map(|x| x.to_string()),
+		}
+	}
+}
+
+impl Display for Finding {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(formatter, "{}: {}", self.severity.letter(), self.tag)?;
+		match (&self.field, &self.value) {
+			(Some(field), Some(value)) => write!(formatter, " {}: {}", field, value),
+			(Some(field), None) => write!(formatter, " {}", field),
+			_ => Ok(()),
+		}
+	}
+}
+
+// Sections from Debian Policy 2.4, which may be prefixed with an archive area
+const SECTIONS: [&str; 58] = [
+	"admin",
+	"cli-mono",
+	"comm",
+	"database",
+	"debian-installer",
+	"debug",
+	"devel",
+	"doc",
+	"editors",
+	"education",
+	"electronics",
+	"embedded",
+	"fonts",
+	"games",
+	"gnome",
+	"gnu-r",
+	"gnustep",
+	"graphics",
+	"hamradio",
+	"haskell",
+	"httpd",
+	"interpreters",
+	"introspection",
+	"java",
+	"javascript",
+	"kde",
+	"kernel",
+	"libdevel",
+	"libs",
+	"lisp",
+	"localization",
+	"mail",
+	"math",
+	"metapackages",
+	"misc",
+	"net",
+	"news",
+	"ocaml",
+	"oldlibs",
+	"otherosfs",
+	"perl",
+	"php",
+	"python",
+	"ruby",
+	"rust",
+	"science",
+	"shells",
+	"sound",
+	"tasks",
+	"tex",
+	"text",
+	"utils",
+	"vcs",
+	"video",
+	"web",
+	"x11",
+	"xfce",
+	"zope",
+];
+
+const AREAS: [&str; 5] = [
+	"main",
+	"contrib",
+	"non-free",
+	"non-free-firmware",
+	"restricted",
+];
+
+const PRIORITIES: [&str; 5] = ["required", "important", "standard", "optional", "extra"];
+
+// Fields of binary packages, both in DEBIAN/control and Packages indexes
+const BINARY_FIELDS: [&str; 30] = [
+	"Package",
+	"Source",
+	"Version",
+	"Section",
+	"Priority",
+	"Architecture",
+	"Essential",
+	"Protected",
+	"Build-Essential",
+	"Important",
+	"Depends",
+	"Pre-Depends",
+	"Recommends",
+	"Suggests",
+	"Breaks",
+	"Conflicts",
+	"Provides",
+	"Replaces",
+	"Enhances",
+	"Installed-Size",
+	"Maintainer",
+	"Original-Maintainer",
+	"Description",
+	"Homepage",
+	"Built-Using",
+	"Static-Built-Using",
+	"Package-Type",
+	"Multi-Arch",
+	"Tag",
+	"Bugs",
+];
+
+// Fields that only appear in Packages indexes
+const INDEX_FIELDS: [&str; 11] = [
+	"Filename",
+	"Size",
+	"MD5sum",
+	"SHA1",
+	"SHA256",
+	"SHA512",
+	"Description-md5",
+	"Origin",
+	"Task",
+	"Supported",
+	"Phased-Update-Percentage",
+];
+
+const SOURCE_FIELDS: [&str; 34] = [
+	"Package",
+	"Source",
+	"Binary",
+	"Version",
+	"Maintainer",
+	"Uploaders",
+	"Section",
+	"Priority",
+	"Standards-Version",
+	"Format",
+	"Architecture",
+	"Homepage",
+	"Build-Depends",
+	"Build-Depends-Indep",
+	"Build-Depends-Arch",
+	"Build-Conflicts",
+	"Build-Conflicts-Indep",
+	"Build-Conflicts-Arch",
+	"Testsuite",
+	"Testsuite-Triggers",
+	"Dgit",
+	"Files",
+	"Checksums-Sha1",
+	"Checksums-Sha256",
+	"Checksums-Sha512",
+	"Directory",
+	"Package-List",
+	"Rules-Requires-Root",
+	"Original-Maintainer",
+	"Extra-Source-Only",
+	"Build-Indep-Architecture",
+	"Vcs-Browser",
+	"Vcs-Git",
+	"Bugs",
+];
+
+const SOURCE_RELATIONS: [&str; 6] = [
+	"Build-Depends",
+	"Build-Depends-Indep",
+	"Build-Depends-Arch",
+	"Build-Conflicts",
+	"Build-Conflicts-Indep",
+	"Build-Conflicts-Arch",
+];
+
+impl Control {
+	pub fn lint(&self) -> Vec<Finding> {
+		let mut findings = lint_warnings(&self.warnings);
+		findings.extend(lint_map(&self.map, StanzaKind::Control));
+		findings
+	}
+}
+
+impl Package {
+	pub fn lint(&self) -> Vec<Finding> {
+		let mut findings = lint_warnings(&self.warnings);
+		findings.extend(lint_map(&self.map, StanzaKind::Package));
+		findings
+	}
+}
+
+// Lints any stanza, including source stanzas which have no parsed type
+pub fn lint_stanza(data: &str, kind: StanzaKind) -> Result<Vec<Finding>, APTError> {
+	let (map, warnings) = parse_kv_warnings(data)?;
+	let mut findings = lint_warnings(&warnings);
+	findings.extend(lint_map(&map, kind));
+	Ok(findings)
+}
+
+fn lint_warnings(warnings: &[Warning]) -> Vec<Finding> {
+	warnings
+		.iter()
+		.map(|warning| {
+			let tag = match warning.kind {
+				WarningKind::DuplicateKey => "duplicate-field",
+				WarningKind::InvalidNumber => "invalid-number",
+				WarningKind::StrayContinuation => "unindented-continuation-line",
+				WarningKind::Whitespace => "unexpected-whitespace",
+			};
+
+			Finding {
+				tag,
+				severity: Severity::Warning,
+				field: warning.context.field.clone(),
+				value: warning.context.value.clone(),
+			}
+		})
+		.collect()
+}
+
+fn lint_map(map: &CaseMap, kind: StanzaKind) -> Vec<Finding> {
+	let mut findings = Vec::new();
+
+	let (name_field, required, recommended): (&str, &[&str], &[&str]) = match kind {
+		StanzaKind::Control => (
+			"Package",
+			&[
+				"Package",
+				"Version",
+				"Architecture",
+				"Maintainer",
+				"Description",
+			],
+			&["Section", "Priority"],
+		),
+		StanzaKind::Package => (
+			"Package",
+			&[
+				"Package",
+				"Version",
+				"Architecture",
+				"Maintainer",
+				"Filename",
+				"Size",
+			],
+			&["Section", "Priority"],
+		),
+		// .dsc files name the package in Source, Sources indexes in Package
+		StanzaKind::Source => (
+			if map.contains_key("Package") {
+				"Package"
+			} else {
+				"Source"
+			},
+			&["Version", "Maintainer", "Binary", "Architecture", "Format"],
+			&["Section", "Priority", "Standards-Version"],
+		),
+	};
+
+	if kind == StanzaKind::Source && !map.contains_key(name_field) {
+		findings.push(Finding::new(
+			"missing-required-field",
+			Severity::Error,
+			"Source",
+			None,
+		));
+	}
+
+	for field in required {
+		if !map.contains_key(field) {
+			findings.push(Finding::new(
+				"missing-required-field",
+				Severity::Error,
+				field,
+				None,
+			));
+		}
+	}
+
+	// Packages indexes may only carry the md5 of a translated description
+	if kind == StanzaKind::Package
+		&& !map.contains_key("Description")
+		&& !map.contains_key("Description-md5")
+	{
+		findings.push(Finding::new(
+			"missing-required-field",
+			Severity::Error,
+			"Description",
+			None,
+		));
+	}
+
+	if kind == StanzaKind::Source
+		&& !map.contains_key("Files")
+		&& !map.contains_key("Checksums-Sha256")
+	{
+		findings.push(Finding::new(
+			"missing-required-field",
+			Severity::Error,
+			"Checksums-Sha256",
+			None,
+		));
+	}
+
+	for field in recommended {
+		if !map.contains_key(field) {
+			findings.push(Finding::new(
+				"missing-recommended-field",
+				Severity::Warning,
+				field,
+				None,
+			));
+		}
+	}
+
+	let mut names = map.get(name_field).into_iter().collect::<Vec<&String>>();
+	let binaries = match map.get("Binary") {
+		Some(binaries) if kind == StanzaKind::Source => binaries
+			.split(',')
+			.map(|x| x.trim().to_string())
+			.collect::<Vec<String>>(),
+		_ => Vec::new(),
+	};
+	names.extend(binaries.iter());
+
+	for name in names {
+		if !is_valid_name(name) {
+			findings.push(Finding::new(
+				"invalid-package-name",
+				Severity::Error,
+				name_field,
+				Some(name),
+			));
+		}
+	}
+
+	if let Some(version) = map.get("Version") {
+		if Version::from(version).is_err() {
+			findings.push(Finding::new(
+				"invalid-version",
+				Severity::Error,
+				"Version",
+				Some(version),
+			));
+		}
+	}
+
+	if let Some(priority) = map.get("Priority") {
+		match priority.as_str() {
+			"extra" => findings.push(Finding::new(
+				"priority-extra-is-replaced-by-priority-optional",
+				Severity::Warning,
+				"Priority",
+				Some(priority),
+			)),
+			value if !PRIORITIES.contains(&value) => findings.push(Finding::new(
+				"unknown-priority",
+				Severity::Warning,
+				"Priority",
+				Some(priority),
+			)),
+			_ => {}
+		}
+	}
+
+	if let Some(section) = map.get("Section") {
+		let (area, name) = match section.split_once('/') {
+			Some((area, name)) => (Some(area), name),
+			None => (None, section.as_str()),
+		};
+
+		if area.map_or(false, |area| !AREAS.contains(&area)) || !SECTIONS.contains(&name) {
+			findings.push(Finding::new(
+				"unknown-section",
+				Severity::Warning,
+				"Section",
+				Some(section),
+			));
+		}
+	}
+
+	if kind != StanzaKind::Source {
+		if let Some(description) = map.get("Description") {
+			let name = map.get("Package").map(|x| x.as_str()).unwrap_or_default();
+			lint_synopsis(&mut findings, name, description);
+		}
+	}
+
+	let relations = match kind {
+		StanzaKind::Source => SOURCE_RELATIONS.to_vec(),
+		_ => RelationKind::ALL
+			.iter()
+			.map(|kind| kind.field())
+			.chain(["Built-Using", "Static-Built-Using"])
+			.collect(),
+	};
+
+	for field in relations {
+		let value = match map.get(field) {
+			Some(value) => value,
+			None => continue,
+		};
+
+		if parse_relations(make_array(Some(value)).as_ref()).is_err() {
+			findings.push(Finding::new(
+				"malformed-relation",
+				Severity::Error,
+				field,
+				Some(value),
+			));
+		}
+	}
+
+	let mut unknown = map
+		.iter()
+		.map(|(key, _)| key.as_str())
+		.filter(|key| !key.starts_with("__cased__") && !is_known_field(key, kind))
+		.collect::<Vec<&str>>();
+	unknown.sort_unstable();
+
+	for field in unknown {
+		findings.push(Finding::new("unknown-field", Severity::Info, field, None));
+	}
+
+	findings
+}
+
+// Checks the first line of a Description against Policy 3.4.1
+fn lint_synopsis(findings: &mut Vec<Finding>, name: &str, description: &str) {
+	let synopsis = description.lines().next().unwrap_or_default().trim();
+	let mut finding = |tag: &'static str, severity: Severity| {
+		findings.push(Finding::new(tag, severity, "Description", Some(synopsis)));
+	};
+
+	if synopsis.is_empty() {
+		finding("description-synopsis-is-empty", Severity::Error);
+		return;
+	}
+
+	if synopsis.chars().count() > 80 {
+		finding("description-synopsis-is-too-long", Severity::Warning);
+	}
+
+	let first = synopsis.split_whitespace().next().unwrap_or_default();
+	if !name.is_empty() && first.eq_ignore_ascii_case(name) {
+		finding("description-starts-with-package-name", Severity::Warning);
+	}
+
+	if ["a", "an", "the"].contains(&first.to_lowercase().as_str()) {
+		finding("description-synopsis-starts-with-article", Severity::Info);
+	}
+
+	if synopsis.ends_with('.') && !synopsis.ends_with("...") {
+		finding("synopsis-is-a-sentence", Severity::Info);
+	}
+}
+
+// Policy 5.6.1: lowercase letters, digits, "+", "-" and "." only, at least
+// two characters long and starting with an alphanumeric character
+fn is_valid_name(name: &str) -> bool {
+	name.len() >= 2
+		&& name.starts_with(|x: char| x.is_ascii_lowercase() || x.is_ascii_digit())
+		&& name
+			.chars()
+			.all(|x| x.is_ascii_lowercase() || x.is_ascii_digit() || "+-.".contains(x))
+}
+
+fn is_known_field(key: &str, kind: StanzaKind) -> bool {
+	// X[BCS]- fields are user-defined, as are Vcs-* fields in source stanzas
+	if let Some((prefix, _)) = key.split_once('-') {
+		let prefix = prefix.to_ascii_uppercase();
+		if prefix.starts_with('X') && prefix[1..].chars().all(|x| "BCS".contains(x)) {
+			return true;
+		}
+
+		if kind == StanzaKind::Source && prefix == "VCS" {
+			return true;
+		}
+	}
+
+	let fields: Vec<&str> = match kind {
+		StanzaKind::Control => BINARY_FIELDS.to_vec(),
+		StanzaKind::Package => BINARY_FIELDS
+			.iter()
+			.chain(INDEX_FIELDS.iter())
+			.copied()
+			.collect(),
+		StanzaKind::Source => SOURCE_FIELDS.to_vec(),
+	};
+
+	fields.iter().any(|field| field.eq_ignore_ascii_case(key))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{lint_stanza, Severity, StanzaKind};
+	use crate::{control::Control, packages::Packages};
+	use std::{collections::HashMap, fs::read_to_string};
+
+	#[test]
+	fn lint_chariz() {
+		let data = match read_to_string("./test/chariz.packages") {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		// Jailbreak repos use their own sections and fields, but nothing in
+		// chariz breaks policy outright
+		let packages = Packages::from(&data);
+		let mut tags = HashMap::<&str, usize>::new();
+		for package in packages.iter() {
+			for finding in package.lint() {
+				assert_ne!(finding.severity, Severity::Error, "{}", finding);
+				*tags.entry(finding.tag).or_default() += 1;
+			}
+		}
+
+		assert_eq!(tags.get("unknown-section"), Some(&415));
+		assert_eq!(tags.get("description-synopsis-is-too-long"), Some(&33));
+		assert!(packages[0]
+			.lint()
+			.iter()
+			.any(|finding| finding.tag == "unknown-field"
+				&& finding.field.as_deref() == Some("Depiction")));
+	}
+
+	#[test]
+	fn lint_findings() {
+		let control = Control::from(
+			"Package: Bad_Name\nVersion: 1.0-\nArchitecture: all\nPriority: extra\nSection: contrib/games\nDepends: libc6 (>= 2.34), foo (~ 1)\nDescription: The bad_name package.\nX-Custom: yes\nColor: blue",
+		)
+		.unwrap();
+
+		let findings = control
+			.lint()
+			.iter()
+			.map(|finding| finding.to_string())
+			.collect::<Vec<String>>();
+
+		assert_eq!(
+			findings,
+			vec![
+				"E: missing-required-field Maintainer",
+				"E: invalid-package-name Package: Bad_Name",
+				"E: invalid-version Version: 1.0-",
+				"W: priority-extra-is-replaced-by-priority-optional Priority: extra",
+				"I: description-synopsis-starts-with-article Description: The bad_name package.",
+				"I: synopsis-is-a-sentence Description: The bad_name package.",
+				"E: malformed-relation Depends: libc6 (>= 2.34), foo (~ 1)",
+				"I: unknown-field Color",
+			]
+		);
+
+		let findings = lint_stanza(
+			"Source: hello\nBinary: hello, Hello-Doc\nVersion: 2.10-3\nMaintainer: Santiago Vila <sanvila@debian.org>\nArchitecture: any\nFormat: 3.0 (quilt)\nStandards-Version: 4.6.2\nBuild-Depends: debhelper-compat (= 13) [linux-any] <!nocheck>\nVcs-Git: https://salsa.debian.org/sanvila/hello.git\nSection: devel \nPriority: optional\nDirectory: pool/main/h/hello\nChecksums-Sha256:\n 0123 1 hello_2.10.orig.tar.gz",
+			StanzaKind::Source,
+		)
+		.unwrap();
+
+		assert_eq!(findings.len(), 2);
+		assert_eq!(findings[0].tag, "unexpected-whitespace");
+		assert_eq!(findings[0].severity, Severity::Warning);
+		assert_eq!(findings[1].tag, "invalid-package-name");
+		assert_eq!(findings[1].value.as_deref(), Some("Hello-Doc"));
+	}
+}
@@ -0,0 +1,590 @@
+use crate::{
+	errors::{APTError, ParseMode},
+	limits::Limits,
+	packages::Packages,
+	path::SafePath,
+	pdiff::{apply_ed, DiffIndex},
+	release::{HashKind, Release},
+	repository::{Compression, IndexFile, IndexKind, Repository},
+	transport::{FetchError, Transport},
+};
+use flate2::read::GzDecoder;
+use std::io::Read;
+use xz2::read::XzDecoder;
+
+// The compressions the loader can decompress, best first
+pub const SUPPORTED_COMPRESSIONS: [Compression; 3] =
+	[Compression::Xz, Compression::Gzip, Compression::None];
+
+pub struct LoadedRepository {
+	pub repository: Repository,
+	pub release: Release,
+	// One entry for each Packages file, e.g. one per component and architecture
+	pub packages: Vec<(IndexFile, Packages)>,
+}
+
+impl LoadedRepository {
+	pub fn packages(&self) -> Vec<&Packages> {
+		self.packages.iter().map(|(_, packages)| packages).collect()
+	}
+}
+
+// Downloads a repository through a Transport, verifying every index file
+// against the hashes and sizes in its Release file
+// Signatures aren't checked, so the Release file has to be trusted, e.g. by
+// fetching it from a trusted mirror or verifying the InRelease signature first
+pub struct RepositoryLoader<'t> {
+	transport: &'t dyn Transport,
+	limits: Limits,
+}
+
+impl<'t> RepositoryLoader<'t> {
+	pub fn new(transport: &'t dyn Transport) -> RepositoryLoader<'t> {
+		RepositoryLoader::new_limited(transport, Limits::default())
+	}
+
+	// The input size limit also caps how far index files are decompressed
+	pub fn new_limited(transport: &'t dyn Transport, limits: Limits) -> RepositoryLoader<'t> {
+		RepositoryLoader { transport, limits }
+	}
+
+	// Fetches InRelease, falling back to Release
+	pub fn fetch_release(&self, repository: &Repository) -> Result<Release, FetchError> {
+		let urls = repository.release_urls();
+		let (url, data) = match self.transport.fetch(&urls[0])? {
+			Some(data) => (&urls[0], clearsigned_message(&decode(&urls[0], data)?)),
+			None => match self.transport.fetch(&urls[1])? {
+				Some(data) => (&urls[1], decode(&urls[1], data)?),
+				None => return Err(FetchError::NotFound(urls[1].clone())),
+			},
+		};
+
+		match Release::from_limited(&data, ParseMode::Lenient, &self.limits) {
+			Ok(release) => Ok(release),
+			Err(err) => Err(FetchError::Parse {
+				url: url.clone(),
+				message: err.to_string(),
+			}),
+		}
+	}
+
+	// Fetches an index file, verifies it and returns it decompressed
+	// by-hash URLs that are missing fall back to the plain path
+	pub fn fetch_index(
+		&self,
+		repository: &Repository,
+		file: &IndexFile,
+	) -> Result<Vec<u8>, FetchError> {
+		safe_path(&file.path)?;
+
+		let mut url = file.url.clone();
+		let mut data = self.transport.fetch(&url)?;
+
+		let plain = repository.url(&file.path);
+		if data.is_none() && plain != url {
+			url = plain;
+			data = self.transport.fetch(&url)?;
+		}
+
+		let data = match data {
+			Some(data) => data,
+			None => return Err(FetchError::NotFound(url)),
+		};
+
+		verify(&url, file.hash_kind, &file.hash.hash, file.hash.size, &data)?;
+		let data = decompress(&url, file.compression, data, &self.limits)?;
+
+		if let Some(uncompressed) = &file.uncompressed {
+			verify(
+				&url,
+				file.hash_kind,
+				&uncompressed.hash,
+				uncompressed.size,
+				&data,
+			)?;
+		}
+
+		Ok(data)
+	}
+
+	// Updates a local copy of an index file using the ed patches listed in its
+	// PDiff Index, checking the hash of every patch and intermediate file
+	// Returns None when the repository has no PDiffs or the local copy isn't in
+	// the history, in which case the index has to be fetched in full
+	pub fn update_index(
+		&self,
+		repository: &Repository,
+		release: &Release,
+		file: &IndexFile,
+		local: &[u8],
+	) -> Result<Option<Vec<u8>>, FetchError> {
+		safe_path(&file.path)?;
+
+		let path = file.diff_path("Index");
+		let (kind, expected) = match release.find(&path) {
+			Some(entry) => entry,
+			None => return Ok(None),
+		};
+
+		let url = repository.url(&path);
+		let data = match self.transport.fetch(&url)? {
+			Some(data) => data,
+			None => return Ok(None),
+		};
+
+		verify(&url, kind, &expected.hash, expected.size, &data)?;
+		let index = match DiffIndex::from(&decode(&url, data)?) {
+			Ok(index) => index,
+			Err(err) => {
+				return Err(FetchError::Parse {
+					url,
+					message: err.to_string(),
+				})
+			}
+		};
+
+		let steps = match index.chain(&index.hash_kind.digest(local)) {
+			Some(steps) => steps,
+			None => return Ok(None),
+		};
+
+		let mut current = decode(&file.url, local.to_vec())?;
+		for step in steps {
+			safe_path(step.name)?;
+			let url = repository.url(&file.diff_path(&format!("{}.gz", step.name)));
+			let data = match self.transport.fetch(&url)? {
+				Some(data) => data,
+				None => return Err(FetchError::NotFound(url)),
+			};
+
+			if let Some(download) = step.download {
+				verify(&url, index.hash_kind, &download.hash, download.size, &data)?;
+			}
+
+			let patch = decompress(&url, Compression::Gzip, data, &self.limits)?;
+			verify(
+				&url,
+				index.hash_kind,
+				&step.patch.hash,
+				step.patch.size,
+				&patch,
+			)?;
+
+			current = match apply_ed(&current, &decode(&url, patch)?) {
+				Ok(current) => current,
+				Err(err) => {
+					return Err(FetchError::Parse {
+						url,
+						message: err.to_string(),
+					})
+				}
+			};
+
+			verify(
+				&url,
+				index.hash_kind,
+				step.result_hash,
+				step.result_size,
+				current.as_bytes(),
+			)?;
+		}
+
+		Ok(Some(current.into_bytes()))
+	}
+
+	// Fetches the Release file and every Packages file it lists
+	pub fn load(&self, repository: &Repository) -> Result<LoadedRepository, FetchError> {
+		let release = self.fetch_release(repository)?;
+		let files = repository
+			.index_files_with(&release, &SUPPORTED_COMPRESSIONS)
+			.into_iter()
+			.filter(|file| file.kind == IndexKind::Packages)
+			.collect::<Vec<IndexFile>>();
+
+		let mut packages = Vec::new();
+		for file in files {
+			let data = self.fetch_index(repository, &file)?;
+			let data = decode(&file.url, data)?;
+			let parsed = Packages::from_limited(&data, ParseMode::Lenient, &self.limits);
+			packages.push((file, parsed));
+		}
+
+		Ok(LoadedRepository {
+			repository: repository.clone(),
+			release,
+			packages,
+		})
+	}
+}
+
+// Index paths and patch names end up in URLs, which a LocalTransport maps
+// onto the filesystem
+fn safe_path(path: &str) -> Result<SafePath, FetchError> {
+	SafePath::from(path).map_err(|err| FetchError::UnsafePath {
+		path: path.to_string(),
+		message: match err {
+			APTError::ParseError(err) => err.message,
+			err => err.to_string(),
+		},
+	})
+}
+
+fn verify(url: &str, kind: HashKind, hash: &str, size: u64, data: &[u8]) -> Result<(), FetchError> {
+	let actual_size = data.len() as u64;
+	if actual_size != size {
+		return Err(FetchError::SizeMismatch {
+			url: url.to_string(),
+			expected: size,
+			actual: actual_size,
+		});
+	}
+
+	let actual = kind.digest(data);
+	if !actual.eq_ignore_ascii_case(hash) {
+		return Err(FetchError::HashMismatch {
+			url: url.to_string(),
+			kind,
+			expected: hash.to_string(),
+			actual,
+		});
+	}
+
+	Ok(())
+}
+
+// Stops reading one byte past the input size limit, so a small file that
+// decompresses to gigabytes fails without being inflated
+fn decompress(
+	url: &str,
+	compression: Compression,
+	data: Vec<u8>,
+	limits: &Limits,
+) -> Result<Vec<u8>, FetchError> {
+	let max = (limits.input_size as u64).saturating_add(1);
+	let mut output = Vec::new();
+	let result = match compression {
+		Compression::None => {
+			output = data;
+			Ok(output.len())
+		}
+		Compression::Gzip => GzDecoder::new(&data[..]).take(max).read_to_end(&mut output),
+		Compression::Xz => XzDecoder::new(&data[..]).take(max).read_to_end(&mut output),
+		_ => {
+			return Err(FetchError::Decompress {
+				url: url.to_string(),
+				message: format!("{compression:?} is not supported"),
+			})
+		}
+	};
+
+	if let Err(error) = limits.check_size(output.len()) {
+		return Err(FetchError::Limit {
+			url: url.to_string(),
+			error,
+		});
+	}
+
+	match result {
+		Ok(_) => Ok(output),
+		Err(err) => Err(FetchError::Decompress {
+			url: url.to_string(),
+			message: err.to_string(),
+		}),
+	}
+}
+
+fn decode(url: &str, data: Vec<u8>) -> Result<String, FetchError> {
+	match String::from_utf8(data) {
+		Ok(data) => Ok(data),
+		Err(_) => Err(FetchError::Encoding(url.to_string())),
+	}
+}
+
+// Extracts the message of an OpenPGP clearsigned document like InRelease,
+// returning the data unchanged when it isn't signed
+pub fn clearsigned_message(data: &str) -> String {
+	let data = data.replace("\r\n", "\n");
+	let body = match data.split_once("-----BEGIN PGP SIGNED MESSAGE-----\n") {
+		Some((_, body)) => body,
+		None => return data,
+	};
+
+	// Armor headers like "Hash: SHA512" end at the first empty line
+	let body = match body.split_once("\n\n") {
+		Some((_, body)) => body,
+		None => body,
+	};
+
+	let body = match body.split_once("\n-----BEGIN PGP SIGNATURE-----") {
+		Some((body, _)) => body,
+		None => body,
+	};
+
+	body.lines()
+		.map(|line| line.strip_prefix("- ").unwrap_or(line))
+		.collect::<Vec<&str>>()
+		.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{clearsigned_message, RepositoryLoader};
+	use crate::{
+		limits::{Limit, Limits},
+		release::HashKind,
+		sources::SourceEntry,
+		transport::{FetchError, LocalTransport},
+	};
+	use flate2::{write::GzEncoder, Compression};
+	use std::{
+		fs::{create_dir_all, read_to_string, remove_dir_all, write},
+		io::Write,
+		path::PathBuf,
+	};
+	use xz2::write::XzEncoder;
+
+	// Builds a mirror of the chariz Packages file with every compression
+	fn mirror(name: &str) -> PathBuf {
+		let packages = match read_to_string("./test/chariz.packages") {
+			Ok(data) => data.into_bytes(),
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+		gzip.write_all(&packages).unwrap();
+		let gzip = gzip.finish().unwrap();
+
+		let mut xz = XzEncoder::new(Vec::new(), 6);
+		xz.write_all(&packages).unwrap();
+		let xz = xz.finish().unwrap();
+
+		let root = std::env::temp_dir().join(format!("apt-parser-{}-{}", name, std::process::id()));
+		let dists = root.join("repo.example.com/debian/dists/stable");
+		create_dir_all(dists.join("main/binary-iphoneos-arm/by-hash/SHA256")).unwrap();
+
+		let mut hashes = String::from("SHA256:\n");
+		for (extension, data) in [("", &packages), (".gz", &gzip), (".xz", &xz)] {
+			let path = format!("main/binary-iphoneos-arm/Packages{extension}");
+			let hash = HashKind::Sha256.digest(data);
+			hashes.push_str(&format!(" {} {} {}\n", hash, data.len(), path));
+			write(dists.join(&path), data).unwrap();
+
+			if extension == ".xz" {
+				let by_hash = format!("main/binary-iphoneos-arm/by-hash/SHA256/{hash}");
+				write(dists.join(by_hash), data).unwrap();
+			}
+		}
+
+		let release = format!(
+			"Origin: Example\nSuite: stable\nArchitectures: iphoneos-arm\nComponents: main\nAcquire-By-Hash: yes\n{hashes}"
+		);
+
+		let signed = format!(
+			"-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA512\n\n{release}-----BEGIN PGP SIGNATURE-----\n\niQIzBAEBCgAdFiEE\n-----END PGP SIGNATURE-----\n"
+		);
+
+		write(dists.join("InRelease"), signed).unwrap();
+		root
+	}
+
+	#[test]
+	fn loader_mirror() {
+		let root = mirror("mirror");
+		let transport = LocalTransport::mirror(&root);
+		let loader = RepositoryLoader::new(&transport);
+
+		let entry =
+			SourceEntry::from_line("deb http://repo.example.com/debian stable main").unwrap();
+		let repository = &entry.repositories("iphoneos-arm")[0];
+
+		let loaded = match loader.load(repository) {
+			Ok(loaded) => loaded,
+			Err(err) => panic!("Failed to load repository: {}", err),
+		};
+
+		assert_eq!(loaded.release.origin.as_deref(), Some("Example"));
+		assert_eq!(loaded.packages.len(), 1);
+
+		let (file, packages) = &loaded.packages[0];
+		assert_eq!(file.path, "main/binary-iphoneos-arm/Packages.xz");
+		assert!(file.url.contains("/by-hash/SHA256/"));
+		assert_eq!(packages.len(), 415);
+		assert!(packages.errors.is_empty());
+
+		// A tampered file is caught even when the size matches
+		let by_hash =
+			root.join("repo.example.com/debian/dists/stable/main/binary-iphoneos-arm/by-hash");
+		remove_dir_all(&by_hash).unwrap();
+
+		let path =
+			root.join("repo.example.com/debian/dists/stable/main/binary-iphoneos-arm/Packages.xz");
+		let mut data = std::fs::read(&path).unwrap();
+		let last = data.len() - 1;
+		data[last] ^= 0xff;
+		write(&path, data).unwrap();
+
+		match loader.load(repository) {
+			Err(FetchError::HashMismatch { url, kind, .. }) => {
+				assert_eq!(kind, HashKind::Sha256);
+				assert!(url.ends_with("/dists/stable/main/binary-iphoneos-arm/Packages.xz"));
+			}
+			result => panic!("Expected a hash mismatch: {:?}", result.err()),
+		}
+
+		remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn loader_limits() {
+		let root = mirror("limits");
+		let transport = LocalTransport::mirror(&root);
+		let limits = Limits {
+			input_size: 100_000,
+			..Default::default()
+		};
+		let loader = RepositoryLoader::new_limited(&transport, limits);
+
+		let entry =
+			SourceEntry::from_line("deb http://repo.example.com/debian stable main").unwrap();
+		let repository = &entry.repositories("iphoneos-arm")[0];
+
+		// The compressed index fits, but it's only inflated up to the limit
+		match loader.load(repository) {
+			Err(FetchError::Limit { url, error }) => {
+				assert!(url.contains("/by-hash/SHA256/"));
+				assert_eq!(error.limit, Limit::InputSize);
+			}
+			result => panic!("Expected the input size limit: {:?}", result.err()),
+		}
+
+		remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn loader_file_urls() {
+		let root = mirror("file");
+		let transport = LocalTransport::new();
+		let loader = RepositoryLoader::new(&transport);
+
+		let line = format!(
+			"deb file://{}/repo.example.com/debian stable main",
+			root.display()
+		);
+		let entry = SourceEntry::from_line(&line).unwrap();
+		let repository = &entry.repositories("iphoneos-arm")[0];
+
+		let loaded = match loader.load(repository) {
+			Ok(loaded) => loaded,
+			Err(err) => panic!("Failed to load repository: {}", err),
+		};
+		assert_eq!(loaded.packages()[0].len(), 415);
+
+		// Other schemes need a mirror directory
+		let entry =
+			SourceEntry::from_line("deb http://repo.example.com/debian stable main").unwrap();
+		let repository = &entry.repositories("iphoneos-arm")[0];
+		assert!(matches!(
+			loader.fetch_release(repository),
+			Err(FetchError::Transport { .. })
+		));
+
+		assert_eq!(
+			clearsigned_message("-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA256\n\nOrigin: Example\n- -----Dashed\n-----BEGIN PGP SIGNATURE-----\n"),
+			"Origin: Example\n-----Dashed"
+		);
+
+		remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn loader_pdiff() {
+		let versions = [
+			"Package: a\nVersion: 1\n\nPackage: b\nVersion: 1\n",
+			"Package: a\nVersion: 2\n\nPackage: b\nVersion: 1\n",
+			"Package: a\nVersion: 2\n",
+		];
+		let patches = ["2c\nVersion: 2\n.\n", "3,5d\n"];
+		let sha256 = |data: &str| HashKind::Sha256.digest(data.as_bytes());
+
+		let root = std::env::temp_dir().join(format!("apt-parser-pdiff-{}", std::process::id()));
+		let dists = root.join("dists/stable");
+		let diffs = dists.join("main/binary-all/Packages.diff");
+		create_dir_all(&diffs).unwrap();
+
+		let mut history = String::new();
+		let mut listed = String::new();
+		let mut download = String::new();
+		for (index, patch) in patches.iter().enumerate() {
+			let name = format!("T-2023-01-0{index}");
+			history.push_str(&format!(
+				" {} {} {name}\n",
+				sha256(versions[index]),
+				versions[index].len()
+			));
+			listed.push_str(&format!(" {} {} {name}\n", sha256(patch), patch.len()));
+
+			let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+			gzip.write_all(patch.as_bytes()).unwrap();
+			let gzip = gzip.finish().unwrap();
+			download.push_str(&format!(
+				" {} {} {name}.gz\n",
+				HashKind::Sha256.digest(&gzip),
+				gzip.len()
+			));
+			write(diffs.join(format!("{name}.gz")), gzip).unwrap();
+		}
+
+		let index = format!(
+			"SHA256-Current: {} {}\nSHA256-History:\n{history}SHA256-Patches:\n{listed}SHA256-Download:\n{download}",
+			sha256(versions[2]),
+			versions[2].len()
+		);
+		write(diffs.join("Index"), &index).unwrap();
+
+		let release = format!(
+			"Architectures: all\nComponents: main\nSHA256:\n {} {} main/binary-all/Packages\n {} {} main/binary-all/Packages.diff/Index\n",
+			sha256(versions[2]),
+			versions[2].len(),
+			sha256(&index),
+			index.len()
+		);
+		write(dists.join("Release"), release).unwrap();
+
+		let transport = LocalTransport::new();
+		let loader = RepositoryLoader::new(&transport);
+		let line = format!("deb file://{} stable main", root.display());
+		let entry = SourceEntry::from_line(&line).unwrap();
+		let repository = &entry.repositories("all")[0];
+
+		let release = loader.fetch_release(repository).unwrap();
+		let files = repository.index_files(&release);
+		let file = &files[0];
+
+		for version in &versions[..2] {
+			let updated = loader.update_index(repository, &release, file, version.as_bytes());
+			assert_eq!(updated.unwrap().as_deref(), Some(versions[2].as_bytes()));
+		}
+
+		// Files that aren't in the history need a full download
+		let updated = loader.update_index(repository, &release, file, b"Package: c\n");
+		assert_eq!(updated.unwrap(), None);
+
+		// A patch that doesn't match the Index is rejected
+		let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+		gzip.write_all(b"1d\n").unwrap();
+		write(diffs.join("T-2023-01-01.gz"), gzip.finish().unwrap()).unwrap();
+
+		let updated = loader.update_index(repository, &release, file, versions[1].as_bytes());
+		assert!(matches!(updated, Err(FetchError::SizeMismatch { .. })));
+
+		// Paths from the Release file can't climb out of the repository
+		let mut unsafe_file = file.clone();
+		unsafe_file.path = "../../../etc/Packages".to_string();
+		assert!(matches!(
+			loader.fetch_index(repository, &unsafe_file),
+			Err(FetchError::UnsafePath { .. })
+		));
+
+		remove_dir_all(&root).unwrap();
+	}
+}
@@ -0,0 +1,522 @@
+use crate::{
+	index::PackageIndex,
+	packages::{address, Package},
+	relation::RelationKind,
+	satisfy::applies_to,
+	solver::Plan,
+};
+use std::{
+	collections::{BTreeSet, HashMap},
+	error::Error,
+	fmt::{Display, Formatter},
+};
+
+#[derive(Debug, Clone)]
+pub enum Step<'a> {
+	Unpack(&'a Package),
+	Configure(&'a Package),
+}
+
+impl<'a> Step<'a> {
+	pub fn package(&self) -> &'a Package {
+		match self {
+			Step::Unpack(package) | Step::Configure(package) => package,
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum OrderError<'a> {
+	Malformed(&'a Package, RelationKind),
+	// Packages that Pre-Depend on each other can't be unpacked in any order
+	PreDependsLoop(Vec<&'a Package>),
+}
+
+impl<'a> Error for OrderError<'a> {}
+
+impl<'a> Display for OrderError<'a> {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			OrderError::Malformed(package, kind) => {
+				write!(formatter, "Malformed {} field in {}", kind, package.package)
+			}
+			OrderError::PreDependsLoop(packages) => {
+				let names = packages
+					.iter()
+					.map(|package| package.package.as_str())
+					.collect::<Vec<&str>>();
+
+				write!(formatter, "Pre-Depends loop between {}", names.join(", "))
+			}
+		}
+	}
+}
+
+// The order dpkg has to unpack and configure a set of packages in:
+//  - Pre-Depends are unpacked and configured before the package is unpacked
+//  - Depends are configured before the package is configured
+// Depends cycles are broken like APT does, by unpacking every package of the
+// cycle first and configuring them together afterwards. Only Pre-Depends
+// that can't be met inside a cycle are an error
+#[derive(Debug, Clone)]
+pub struct InstallOrder<'a> {
+	pub steps: Vec<Step<'a>>,
+	pub cycles: Vec<Vec<&'a Package>>,
+}
+
+impl<'a> InstallOrder<'a> {
+	// Relations satisfied by packages outside of the set are assumed to be
+	// installed and configured already
+	pub fn new(
+		packages: &[&'a Package],
+		native_architecture: &str,
+	) -> Result<InstallOrder<'a>, OrderError<'a>> {
+		let mut index = PackageIndex::new(&[]);
+		for package in packages {
+			index.insert(package);
+		}
+
+		let ids = packages
+			.iter()
+			.enumerate()
+			.map(|(id, package)| (address(package), id))
+			.collect::<HashMap<usize, usize>>();
+
+		let mut graph = Graph {
+			depends: vec![Vec::new(); packages.len()],
+			pre_depends: vec![Vec::new(); packages.len()],
+		};
+
+		for (id, package) in packages.iter().enumerate() {
+			let architecture = match package.architecture.as_str() {
+				"all" => native_architecture,
+				architecture => architecture,
+			};
+
+			for kind in [RelationKind::PreDepends, RelationKind::Depends] {
+				let relations = match package.relations(kind) {
+					Ok(relations) => relations,
+					Err(_) => return Err(OrderError::Malformed(package, kind)),
+				};
+
+				for relation in relations {
+					// Only the first alternative present in the set needs ordering
+					let target = relation
+						.alternatives
+						.iter()
+						.filter(|dependency| applies_to(dependency, architecture))
+						.flat_map(|dependency| index.satisfiers(dependency, architecture))
+						.find_map(|provider| ids.get(&address(provider.package)).copied());
+
+					let target = match target {
+						Some(target) if target != id => target,
+						_ => continue,
+					};
+
+					match kind {
+						RelationKind::PreDepends => graph.pre_depends[id].push(target),
+						_ => graph.depends[id].push(target),
+					}
+				}
+			}
+		}
+
+		let mut steps = Vec::new();
+		let mut cycles = Vec::new();
+
+		for members in graph.components() {
+			if members.len() > 1 {
+				cycles.push(members.iter().map(|id| packages[*id]).collect());
+			}
+
+			let scheduled = match graph.schedule(&members) {
+				Ok(scheduled) => scheduled,
+				Err(left) => {
+					return Err(OrderError::PreDependsLoop(
+						left.iter().map(|id| packages[*id]).collect(),
+					))
+				}
+			};
+
+			for (configure, id) in scheduled {
+				steps.push(match configure {
+					true => Step::Configure(packages[id]),
+					false => Step::Unpack(packages[id]),
+				});
+			}
+		}
+
+		Ok(InstallOrder { steps, cycles })
+	}
+
+	// Orders everything a solver plan installs, upgrades or downgrades
+	pub fn from_plan(
+		plan: &Plan<'a>,
+		native_architecture: &str,
+	) -> Result<InstallOrder<'a>, OrderError<'a>> {
+		InstallOrder::new(&plan.installs(), native_architecture)
+	}
+
+	pub fn unpack_order(&self) -> Vec<&'a Package> {
+		self.steps
+			.iter()
+			.filter(|step| matches!(step, Step::Unpack(_)))
+			.map(Step::package)
+			.collect()
+	}
+
+	pub fn configure_order(&self) -> Vec<&'a Package> {
+		self.steps
+			.iter()
+			.filter(|step| matches!(step, Step::Configure(_)))
+			.map(Step::package)
+			.collect()
+	}
+}
+
+struct Graph {
+	depends: Vec<Vec<usize>>,
+	pre_depends: Vec<Vec<usize>>,
+}
+
+impl Graph {
+	fn edges(&self, id: usize) -> impl Iterator<Item = &usize> {
+		self.pre_depends[id].iter().chain(self.depends[id].iter())
+	}
+
+	// Tarjan's algorithm, which yields every component after the components
+	// it depends on
+	fn components(&self) -> Vec<Vec<usize>> {
+		let mut tarjan = Tarjan {
+			graph: self,
+			counter: 0,
+			indices: vec![None; self.depends.len()],
+			lowlinks: vec![0; self.depends.len()],
+			stack: Vec::new(),
+			on_stack: vec![false; self.depends.len()],
+			components: Vec::new(),
+		};
+
+		for id in 0..self.depends.len() {
+			if tarjan.indices[id].is_none() {
+				tarjan.visit(id);
+			}
+		}
+
+		tarjan.components
+	}
+
+	// Orders the unpack and configure steps of a component, unpacking every
+	// member before configuring any unless a Pre-Depends needs a member
+	// configured first. Components it depends on are already configured
+	// Returns the members that can't be ordered when Pre-Depends make it
+	// impossible, like a package Pre-Depending on something that Depends on it
+	fn schedule(&self, members: &[usize]) -> Result<Vec<(bool, usize)>, Vec<usize>> {
+		let local = members
+			.iter()
+			.enumerate()
+			.map(|(index, id)| (*id, index))
+			.collect::<HashMap<usize, usize>>();
+
+		// Step 2 * index unpacks a member, and 2 * index + 1 configures it
+		let mut waiting = vec![0; members.len() * 2];
+		let mut next = vec![Vec::new(); members.len() * 2];
+		let mut edge = |before: usize, after: usize| {
+			next[before].push(after);
+			waiting[after] += 1;
+		};
+
+		for (index, id) in members.iter().enumerate() {
+			edge(2 * index, 2 * index + 1);
+
+			for target in &self.pre_depends[*id] {
+				if let Some(target) = local.get(target) {
+					edge(2 * target + 1, 2 * index);
+				}
+			}
+
+			for target in &self.depends[*id] {
+				if let Some(target) = local.get(target) {
+					edge(2 * target, 2 * index + 1);
+				}
+			}
+		}
+
+		// Unpacks come before configures, then the order packages were given in
+		let mut ready = (0..members.len())
+			.filter(|index| waiting[2 * index] == 0)
+			.map(|index| (0, index))
+			.collect::<BTreeSet<(usize, usize)>>();
+
+		let mut scheduled = Vec::new();
+		while let Some(&(kind, index)) = ready.iter().next() {
+			ready.remove(&(kind, index));
+			scheduled.push((kind == 1, members[index]));
+
+			for &step in &next[2 * index + kind] {
+				waiting[step] -= 1;
+				if waiting[step] == 0 {
+					ready.insert((step % 2, step / 2));
+				}
+			}
+		}
+
+		if scheduled.len() == members.len() * 2 {
+			return Ok(scheduled);
+		}
+
+		Err(members
+			.iter()
+			.enumerate()
+			.filter(|(index, _)| waiting[2 * index + 1] > 0)
+			.map(|(_, id)| *id)
+			.collect())
+	}
+}
+
+struct Tarjan<'g> {
+	graph: &'g Graph,
+	counter: usize,
+	indices: Vec<Option<usize>>,
+	lowlinks: Vec<usize>,
+	stack: Vec<usize>,
+	on_stack: Vec<bool>,
+	components: Vec<Vec<usize>>,
+}
+
+impl<'g> Tarjan<'g> {
+	fn visit(&mut self, id: usize) {
+		self.indices[id] = Some(self.counter);
+		self.lowlinks[id] = self.counter;
+		self.counter += 1;
+		self.stack.push(id);
+		self.on_stack[id] = true;
+
+		let graph = self.graph;
+		for &target in graph.edges(id) {
+			match self.indices[target] {
+				None => {
+					self.visit(target);
+					self.lowlinks[id] = self.lowlinks[id].min(self.lowlinks[target]);
+				}
+				Some(index) if self.on_stack[target] => {
+					self.lowlinks[id] = self.lowlinks[id].min(index);
+				}
+				Some(_) => {}
+			}
+		}
+
+		if Some(self.lowlinks[id]) != self.indices[id] {
+			return;
+		}
+
+		let mut component = Vec::new();
+		while let Some(member) = self.stack.pop() {
+			self.on_stack[member] = false;
+			component.push(member);
+
+			if member == id {
+				break;
+			}
+		}
+
+		// Keep the order the packages were given in
+		component.sort_unstable();
+		self.components.push(component);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{InstallOrder, OrderError};
+	use crate::packages::{Package, Packages};
+
+	fn names(packages: &[&Package]) -> Vec<String> {
+		packages
+			.iter()
+			.map(|package| package.package.clone())
+			.collect()
+	}
+
+	#[test]
+	fn ordering_cycles() {
+		let packages = Packages::from(
+			"Package: app
+Architecture: amd64
+Version: 1.0
+Depends: perl-base, libperl | libperl-compat
+Filename: pool/app_1.0_amd64.deb
+Size: 100
+
+Package: perl-base
+Architecture: amd64
+Version: 5.34.0-3ubuntu1
+Pre-Depends: libc6
+Depends: perl-modules
+Filename: pool/perl-base_5.34.0-3ubuntu1_amd64.deb
+Size: 100
+
+Package: perl-modules
+Architecture: all
+Version: 5.34.0-3ubuntu1
+Depends: perl-base
+Filename: pool/perl-modules_5.34.0-3ubuntu1_all.deb
+Size: 100
+
+Package: libperl
+Architecture: amd64
+Version: 5.34.0-3ubuntu1
+Depends: libc6 (>= 2.35), libdb
+Filename: pool/libperl_5.34.0-3ubuntu1_amd64.deb
+Size: 100
+
+Package: libc6
+Architecture: amd64
+Version: 2.35-0ubuntu3
+Filename: pool/libc6_2.35-0ubuntu3_amd64.deb
+Size: 100",
+		);
+
+		let set = packages.iter().collect::<Vec<&Package>>();
+		let order = match InstallOrder::new(&set, "amd64") {
+			Ok(order) => order,
+			Err(err) => panic!("Failed to order packages: {}", err),
+		};
+
+		// libdb isn't part of the set, so it's assumed to be installed
+		assert_eq!(
+			names(&order.unpack_order()),
+			vec!["libc6", "perl-base", "perl-modules", "libperl", "app"]
+		);
+
+		assert_eq!(
+			names(&order.configure_order()),
+			vec!["libc6", "perl-base", "perl-modules", "libperl", "app"]
+		);
+
+		assert_eq!(order.cycles.len(), 1);
+		assert_eq!(names(&order.cycles[0]), vec!["perl-base", "perl-modules"]);
+
+		// Both halves of the cycle are unpacked before either is configured
+		let steps = order
+			.steps
+			.iter()
+			.map(|step| step.package().package.as_str())
+			.collect::<Vec<&str>>();
+		assert_eq!(
+			&steps[..4],
+			&["libc6", "libc6", "perl-base", "perl-modules"]
+		);
+	}
+
+	#[test]
+	fn ordering_pre_depends() {
+		let packages = Packages::from(
+			"Package: dpkg
+Architecture: amd64
+Version: 1.21.1ubuntu2
+Pre-Depends: tar
+Depends: installer
+Filename: pool/dpkg_1.21.1ubuntu2_amd64.deb
+Size: 100
+
+Package: tar
+Architecture: amd64
+Version: 1.34
+Depends: libc6
+Filename: pool/tar_1.34_amd64.deb
+Size: 100
+
+Package: installer
+Architecture: amd64
+Version: 1.0
+Depends: dpkg
+Filename: pool/installer_1.0_amd64.deb
+Size: 100",
+		);
+
+		let set = packages.iter().collect::<Vec<&Package>>();
+		let order = match InstallOrder::new(&set, "amd64") {
+			Ok(order) => order,
+			Err(err) => panic!("Failed to order packages: {}", err),
+		};
+
+		// tar is configured before dpkg is unpacked, then dpkg and installer
+		// depend on each other and are unpacked together
+		let steps = order
+			.steps
+			.iter()
+			.map(|step| match step {
+				super::Step::Unpack(package) => format!("unpack {}", package.package),
+				super::Step::Configure(package) => format!("configure {}", package.package),
+			})
+			.collect::<Vec<String>>();
+
+		assert_eq!(
+			steps,
+			vec![
+				"unpack tar",
+				"configure tar",
+				"unpack dpkg",
+				"unpack installer",
+				"configure dpkg",
+				"configure installer"
+			]
+		);
+
+		// tar can't be configured without dpkg, which needs tar configured
+		let packages = Packages::from(
+			"Package: dpkg
+Architecture: amd64
+Version: 1.21.1ubuntu2
+Pre-Depends: tar
+Filename: pool/dpkg_1.21.1ubuntu2_amd64.deb
+Size: 100
+
+Package: tar
+Architecture: amd64
+Version: 1.34
+Depends: dpkg
+Filename: pool/tar_1.34_amd64.deb
+Size: 100",
+		);
+
+		let set = packages.iter().collect::<Vec<&Package>>();
+		match InstallOrder::new(&set, "amd64") {
+			Err(OrderError::PreDependsLoop(members)) => {
+				assert_eq!(names(&members), vec!["dpkg", "tar"]);
+			}
+			result => panic!(
+				"Expected a Pre-Depends loop: {:?}",
+				result.map(|x| x.steps.len())
+			),
+		}
+
+		let packages = Packages::from(
+			"Package: left
+Architecture: all
+Version: 1.0
+Pre-Depends: right
+Filename: pool/left_1.0_all.deb
+Size: 100
+
+Package: right
+Architecture: all
+Version: 1.0
+Pre-Depends: left
+Filename: pool/right_1.0_all.deb
+Size: 100",
+		);
+
+		let set = packages.iter().collect::<Vec<&Package>>();
+		match InstallOrder::new(&set, "amd64") {
+			Err(OrderError::PreDependsLoop(members)) => {
+				assert_eq!(names(&members), vec!["left", "right"]);
+			}
+			result => panic!(
+				"Expected a Pre-Depends loop: {:?}",
+				result.map(|x| x.steps.len())
+			),
+		}
+	}
+}
@@ -1,11 +1,19 @@
 use crate::{
 	case_map::CaseMap,
 	control::Control,
-	errors::{APTError, MissingKeyError},
+	errors::{APTError, ErrorContext, MissingKeyError, ParseMode, Warning, WarningKind},
+	limits::Limits,
+	normalize,
+	relation::{parse_field_relations, Relation, RelationKind},
+	release::HashKind,
+	split_stanzas,
+	translations::{Translation, Translations},
+	version::compare_versions,
 };
 use rayon::prelude::*;
-use std::ops::Index;
+use std::{cmp::Ordering, collections::HashMap, ops::Index, slice::Iter, vec::IntoIter};
 
+#[derive(Debug, Clone)]
 pub struct Package {
 	pub(crate) map: CaseMap,
 	pub package: String,
@@ -15,6 +23,7 @@ pub struct Package {
 	pub priority: Option<String>,
 	pub architecture: String,
 	pub is_essential: Option<bool>,
+	pub multi_arch: Option<String>,
 	pub depends: Option<Vec<String>>,
 	pub pre_depends: Option<Vec<String>>,
 	pub recommends: Option<Vec<String>>,
@@ -23,6 +32,7 @@ pub struct Package {
 	pub enhances: Option<Vec<String>>,
 	pub breaks: Option<Vec<String>>,
 	pub conflicts: Option<Vec<String>>,
+	pub provides: Option<Vec<String>>,
 	pub installed_size: Option<i64>,
 	pub maintainer: Option<String>,
 	pub description: Option<String>,
@@ -37,37 +47,55 @@ pub struct Package {
 	pub sha256sum: Option<String>,
 	pub sha512sum: Option<String>,
 	pub description_md5sum: Option<String>,
+	pub translation: Option<Translation>,
+	// What the parser recovered from, always empty in strict mode
+	pub warnings: Vec<Warning>,
 }
 
 impl Package {
 	pub fn from(data: &str) -> Result<Package, APTError> {
-		let control = match Control::from(data) {
-			Ok(control) => control,
-			Err(err) => return Err(err),
-		};
+		Package::from_with(data, ParseMode::Lenient)
+	}
 
-		let map = control.map;
+	pub fn from_with(data: &str, mode: ParseMode) -> Result<Package, APTError> {
+		let mut control = Control::from_with(data, mode)?;
+		let map = &control.map;
 
 		let filename = match map.get("Filename") {
 			Some(filename) => filename.to_owned(),
-			None => {
-				return Err(APTError::MissingKeyError(MissingKeyError::new(
-					"Filename", data,
-				)))
-			}
+			None => return Err(APTError::MissingKeyError(MissingKeyError::new("Filename"))),
 		};
 
 		let size = match map.get("Size") {
-			Some(size) => size.parse::<i64>().unwrap_or(-1),
-			None => {
-				return Err(APTError::MissingKeyError(MissingKeyError::new(
-					"Size", data,
-				)))
-			}
+			Some(size) => match size.parse::<i64>() {
+				Ok(size) => size,
+				Err(_) => {
+					let context = ErrorContext::field("Size", size).locate(data);
+					control
+						.warnings
+						.push(Warning::new(WarningKind::InvalidNumber, context));
+					-1
+				}
+			},
+			None => return Err(APTError::MissingKeyError(MissingKeyError::new("Size"))),
 		};
 
-		Ok(Package {
-			map: map.clone(),
+		mode.check(&control.warnings)?;
+		Ok(Package::from_control(control, filename, size))
+	}
+
+	// Builds a package from a stanza that was already validated as a control
+	// file, used for dpkg status entries that have no Filename or Size
+	pub(crate) fn from_control(control: Control, filename: String, size: i64) -> Package {
+		let map = control.map;
+		let md5sum = map.get("MD5Sum").cloned();
+		let sha1sum = map.get("SHA1").cloned();
+		let sha256sum = map.get("SHA256").cloned();
+		let sha512sum = map.get("SHA512").cloned();
+		let description_md5sum = map.get("Description-md5").cloned();
+
+		Package {
+			map,
 			package: control.package,
 			source: control.source,
 			version: control.version,
@@ -75,6 +103,7 @@ impl Package {
 			priority: control.priority,
 			architecture: control.architecture,
 			is_essential: control.is_essential,
+			multi_arch: control.multi_arch,
 			depends: control.depends,
 			pre_depends: control.pre_depends,
 			recommends: control.recommends,
@@ -83,6 +112,7 @@ impl Package {
 			enhances: control.enhances,
 			breaks: control.breaks,
 			conflicts: control.conflicts,
+			provides: control.provides,
 			installed_size: control.installed_size,
 			maintainer: control.maintainer,
 			description: control.description,
@@ -92,17 +122,46 @@ impl Package {
 			tags: control.tags,
 			filename,
 			size,
-			md5sum: map.get("MD5Sum").cloned(),
-			sha1sum: map.get("SHA1").cloned(),
-			sha256sum: map.get("SHA256").cloned(),
-			sha512sum: map.get("SHA512").cloned(),
-			description_md5sum: map.get("Description-md5").cloned(),
-		})
+			md5sum,
+			sha1sum,
+			sha256sum,
+			sha512sum,
+			description_md5sum,
+			translation: None,
+			warnings: control.warnings,
+		}
 	}
 
 	pub fn get(&self, key: &str) -> Option<&str> {
 		self.map.get(key).map(|x| &**x)
 	}
+
+	pub fn hash(&self, kind: HashKind) -> Option<&str> {
+		match kind {
+			HashKind::Md5 => self.md5sum.as_deref(),
+			HashKind::Sha1 => self.sha1sum.as_deref(),
+			HashKind::Sha256 => self.sha256sum.as_deref(),
+			HashKind::Sha512 => self.sha512sum.as_deref(),
+		}
+	}
+
+	pub fn relations(&self, kind: RelationKind) -> Result<Vec<Relation>, APTError> {
+		parse_field_relations(kind, self.map.get(kind.field()))
+	}
+
+	// Prefers the description attached by Packages::localize
+	pub fn localized_description(&self) -> Option<&str> {
+		match &self.translation {
+			Some(translation) => Some(&translation.description),
+			None => self.description.as_deref(),
+		}
+	}
+}
+
+// Packages are identified by address since the same stanza may appear in
+// several indexes
+pub(crate) fn address(package: &Package) -> usize {
+	package as *const Package as usize
 }
 
 pub struct Packages {
@@ -112,11 +171,54 @@ pub struct Packages {
 
 impl Packages {
 	pub fn from(data: &str) -> Packages {
-		let binding = data.replace("\r\n", "\n").replace('\0', "");
-		let iter = binding.trim().split("\n\n").par_bridge().into_par_iter();
+		Packages::from_with(data, ParseMode::Lenient)
+	}
+
+	// In strict mode, stanzas with warnings are listed in errors instead
+	pub fn from_with(data: &str, mode: ParseMode) -> Packages {
+		Packages::from_limited(data, mode, &Limits::default())
+	}
+
+	// An input or a stanza count over the limits is a single error and no
+	// packages, while a stanza over the limits is skipped like any other error
+	pub fn from_limited(data: &str, mode: ParseMode, limits: &Limits) -> Packages {
+		let failed = |err: APTError| Packages {
+			packages: Vec::new(),
+			errors: vec![err],
+		};
+
+		if let Err(err) = limits.check_input(data) {
+			return failed(err);
+		}
+
+		let binding = normalize(data);
+		let stanzas = split_stanzas(&binding)
+			.take(limits.stanzas.saturating_add(1))
+			.collect::<Vec<(usize, usize, &str)>>();
 
-		let values = iter
-			.map(|package| Package::from(&package))
+		if let Err(err) = limits.check_stanzas(stanzas.len()) {
+			return failed(err);
+		}
+
+		let values = stanzas
+			.into_par_iter()
+			.enumerate()
+			.map(|(index, (offset, line, stanza))| {
+				let package = limits
+					.check_stanza(stanza)
+					.and_then(|_| Package::from_with(stanza, mode));
+
+				match package {
+					Ok(mut package) => {
+						for warning in &mut package.warnings {
+							warning.context.shift(index, offset, line);
+						}
+
+						Ok(package)
+					}
+					Err(err) => Err(err.in_stanza(index, offset, line)),
+				}
+			})
 			.collect::<Vec<Result<Package, APTError>>>();
 
 		let mut packages = Vec::new();
@@ -135,13 +237,121 @@ impl Packages {
 	pub fn len(&self) -> usize {
 		self.packages.len()
 	}
+
+	pub fn is_empty(&self) -> bool {
+		self.packages.is_empty()
+	}
+
+	// Iterates in the same order as the stanzas appear in the file
+	pub fn iter(&self) -> Iter<'_, Package> {
+		self.packages.iter()
+	}
+
+	// Returns the newest version of a package when there are several
+	pub fn get(&self, name: &str) -> Option<&Package> {
+		self.versions(name).into_iter().next()
+	}
+
+	// Every stanza with this name, newest version first
+	pub fn versions(&self, name: &str) -> Vec<&Package> {
+		let mut versions = self
+			.packages
+			.iter()
+			.filter(|package| package.package == name)
+			.collect::<Vec<&Package>>();
+
+		versions.sort_by(|a, b| compare_versions(&b.version, &a.version));
+		versions
+	}
+
+	pub fn filter_architecture<'a>(
+		&'a self,
+		architecture: &'a str,
+	) -> impl Iterator<Item = &'a Package> {
+		self.packages
+			.iter()
+			.filter(move |package| package.architecture == architecture)
+	}
+
+	pub fn filter_section<'a>(&'a self, section: &'a str) -> impl Iterator<Item = &'a Package> {
+		self.packages
+			.iter()
+			.filter(move |package| match &package.section {
+				Some(value) => value.eq_ignore_ascii_case(section),
+				None => false,
+			})
+	}
+
+	pub fn filter_priority<'a>(&'a self, priority: &'a str) -> impl Iterator<Item = &'a Package> {
+		self.packages
+			.iter()
+			.filter(move |package| match &package.priority {
+				Some(value) => value.eq_ignore_ascii_case(priority),
+				None => false,
+			})
+	}
+
+	// The newest version of every package name, in order of first appearance
+	pub fn latest(&self) -> Vec<&Package> {
+		let mut positions = HashMap::<&str, usize>::new();
+		let mut latest = Vec::<&Package>::new();
+
+		for package in &self.packages {
+			match positions.get(package.package.as_str()) {
+				Some(&position) => {
+					if compare_versions(&package.version, &latest[position].version)
+						== Ordering::Greater
+					{
+						latest[position] = package;
+					}
+				}
+				None => {
+					positions.insert(&package.package, latest.len());
+					latest.push(package);
+				}
+			}
+		}
+
+		latest
+	}
+
+	// Attaches Translation-<lang> descriptions, returning how many were found
+	pub fn localize(&mut self, translations: &Translations, languages: &[&str]) -> usize {
+		let mut localized = 0;
+		for package in &mut self.packages {
+			let md5sum = match &package.description_md5sum {
+				Some(md5sum) => md5sum,
+				None => continue,
+			};
+
+			package.translation = translations
+				.find(&package.package, md5sum, languages)
+				.cloned();
+
+			if package.translation.is_some() {
+				localized += 1;
+			}
+		}
+
+		localized
+	}
 }
 
-impl Iterator for Packages {
+impl IntoIterator for Packages {
 	type Item = Package;
+	type IntoIter = IntoIter<Package>;
 
-	fn next(&mut self) -> Option<Self::Item> {
-		self.packages.pop()
+	fn into_iter(self) -> Self::IntoIter {
+		self.packages.into_iter()
+	}
+}
+
+impl<'a> IntoIterator for &'a Packages {
+	type Item = &'a Package;
+	type IntoIter = Iter<'a, Package>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.packages.iter()
 	}
 }
 
@@ -155,8 +365,12 @@ impl Index<usize> for Packages {
 
 #[cfg(test)]
 mod tests {
-	use super::Packages;
-	use std::fs::read_to_string;
+	use super::{Package, Packages};
+	use crate::{
+		errors::{APTError, ParseMode, WarningKind},
+		relation::RelationKind,
+	};
+	use std::{error::Error, fs::read_to_string};
 
 	#[test]
 	fn packages_chariz() {
@@ -181,6 +395,7 @@ mod tests {
 		assert_eq!(control.priority, None);
 		assert_eq!(control.architecture, "iphoneos-arm");
 		assert_eq!(control.is_essential, None);
+		assert_eq!(control.multi_arch, None);
 
 		assert_eq!(control.depends, Some(vec!["libnet9".to_owned()]));
 		assert_eq!(control.pre_depends, None);
@@ -190,6 +405,7 @@ mod tests {
 		assert_eq!(control.enhances, None);
 		assert_eq!(control.breaks, None);
 		assert_eq!(control.conflicts, None);
+		assert_eq!(control.provides, None);
 
 		assert_eq!(control.installed_size, Some(88));
 		assert_eq!(
@@ -349,4 +565,166 @@ mod tests {
 			Some("ubuntu-desktop-minimal, ubuntu-desktop, ubuntu-desktop-raspi, kubuntu-desktop, xubuntu-core, xubuntu-desktop, lubuntu-desktop, ubuntustudio-desktop-core, ubuntustudio-desktop, ubuntukylin-desktop, ubuntu-mate-core, ubuntu-mate-desktop, ubuntu-budgie-desktop, ubuntu-budgie-desktop-raspi")
 		);
 	}
+
+	#[test]
+	fn packages_chariz_queries() {
+		let file = "./test/chariz.packages";
+		let data = match read_to_string(file) {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let packages = Packages::from(&data);
+		let names = packages
+			.iter()
+			.take(3)
+			.map(|package| (package.package.as_str(), package.version.as_str()))
+			.collect::<Vec<(&str, &str)>>();
+
+		assert_eq!(
+			names,
+			vec![
+				("arpoison", "0.7"),
+				("arpoison", "0.7-1"),
+				("ca.kirara.voltage", "1.6.3"),
+			]
+		);
+
+		let versions = packages
+			.versions("arpoison")
+			.iter()
+			.map(|package| package.version.as_str())
+			.collect::<Vec<&str>>();
+
+		assert_eq!(versions, vec!["0.7-1", "0.7"]);
+		assert_eq!(
+			packages
+				.get("arpoison")
+				.map(|package| package.version.as_str()),
+			Some("0.7-1")
+		);
+		assert!(packages.get("invalid-package").is_none());
+
+		let latest = packages.latest();
+		assert!(latest.len() < packages.len());
+		assert_eq!(latest[0].package, "arpoison");
+		assert_eq!(latest[0].version, "0.7-1");
+
+		assert_eq!(
+			packages.filter_architecture("iphoneos-arm").count(),
+			packages.len()
+		);
+		assert!(packages
+			.filter_section("system")
+			.all(|package| package.section == Some("System".to_owned())));
+		assert_eq!(packages.filter_priority("optional").count(), 1);
+
+		let count = (&packages).into_iter().count();
+		let owned = packages.into_iter().collect::<Vec<Package>>();
+		assert_eq!(owned.len(), count);
+		assert_eq!(owned[0].version, "0.7");
+	}
+
+	#[test]
+	fn packages_errors() {
+		let data = [
+			"",
+			"Package: a\nVersion: 1\nArchitecture: all\nFilename: a.deb\nSize: 1\nDepends: b (~~ 1)",
+			"",
+			"Package: b\nVersion: 1\nArchitecture: all\nInstalled-Size: big\nFilename: b.deb\nSize: 1",
+			"",
+			"oops\nPackage: c",
+			"",
+			"Package: d\nVersion: 1\nArchitecture: all\nFilename: d.deb",
+		]
+		.join("\r\n");
+
+		let packages = Packages::from(&data);
+		assert_eq!(packages.len(), 1);
+		assert_eq!(packages.errors.len(), 3);
+
+		let err = &packages.errors[0];
+		assert!(matches!(err, APTError::ParseError(_)));
+		assert_eq!(
+			err.to_string(),
+			"Invalid installed size (stanza 1, line 12, column 1, field Installed-Size, value \"big\")"
+		);
+		assert!(err.source().is_some());
+
+		let err = &packages.errors[1];
+		assert!(matches!(err, APTError::KVError(_)));
+		assert_eq!(err.context().stanza, Some(2));
+		assert_eq!(err.context().line, Some(16));
+		assert_eq!(
+			err.context().offset,
+			data.replace("\r\n", "\n").find("oops")
+		);
+
+		let err = &packages.errors[2];
+		assert!(matches!(err, APTError::MissingKeyError(ref err) if err.key == "Size"));
+		assert_eq!(err.context().stanza, Some(3));
+
+		let err = packages[0].relations(RelationKind::Depends).unwrap_err();
+		assert_eq!(err.context().field.as_deref(), Some("Depends"));
+		assert_eq!(err.context().value.as_deref(), Some("b (~~ 1)"));
+	}
+
+	#[test]
+	fn packages_strict() {
+		let data = match read_to_string("./test/chariz.packages") {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let packages = Packages::from_with(&data, ParseMode::Strict);
+		assert!(packages.errors.is_empty());
+		assert_eq!(packages.len(), 415);
+
+		let data = [
+			"Package: a\nVersion: 1\nArchitecture: all\nFilename: a.deb\nSize: 1",
+			"",
+			"Package: b",
+			"Version: 1 ",
+			"Architecture: all",
+			"Description: Short",
+			"continued",
+			"Filename: b.deb",
+			"Size: big",
+			"Version: 2",
+		]
+		.join("\n");
+
+		let packages = Packages::from(&data);
+		assert!(packages.errors.is_empty());
+		assert!(packages[0].warnings.is_empty());
+
+		let package = &packages[1];
+		assert_eq!(package.version, "1");
+		assert_eq!(package.size, -1);
+		assert_eq!(package.description.as_deref(), Some("Short\ncontinued"));
+		assert_eq!(
+			package
+				.warnings
+				.iter()
+				.map(|warning| (warning.kind, warning.context.line))
+				.collect::<Vec<(WarningKind, Option<usize>)>>(),
+			vec![
+				(WarningKind::Whitespace, Some(8)),
+				(WarningKind::StrayContinuation, Some(11)),
+				(WarningKind::DuplicateKey, Some(14)),
+				(WarningKind::InvalidNumber, Some(13)),
+			]
+		);
+		assert_eq!(package.warnings[0].context.stanza, Some(1));
+
+		let packages = Packages::from_with(&data, ParseMode::Strict);
+		assert_eq!(packages.len(), 1);
+		match &packages.errors[..] {
+			[APTError::Warning(warning)] => {
+				assert_eq!(warning.kind, WarningKind::Whitespace);
+				assert_eq!(warning.context.line, Some(8));
+			}
+			errors => panic!("Expected a single warning: {:?}", errors),
+		}
+	}
 }
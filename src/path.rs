@@ -0,0 +1,148 @@
+use crate::{
+	errors::{APTError, ErrorContext, ParseError},
+	packages::Package,
+	release::ReleaseHash,
+};
+use std::{
+	fmt::{Display, Formatter},
+	path::Path,
+};
+
+// A relative path from an untrusted repository, like a Filename field or a
+// file listed in a Release file, that stays inside whatever it's joined to
+// Absolute paths, drive letters, backslashes, empty, "." and ".." components,
+// NUL bytes and other control characters are all rejected
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SafePath(String);
+
+impl SafePath {
+	pub fn from(path: &str) -> Result<SafePath, APTError> {
+		let error = |message: &str| Err(APTError::ParseError(ParseError::value(message, path)));
+
+		if path.is_empty() {
+			return error("Path is empty");
+		}
+
+		if path.chars().any(|x| x.is_control()) {
+			return error("Path contains control characters");
+		}
+
+		if path.contains('\\') {
+			return error("Path contains backslashes");
+		}
+
+		if path.starts_with('/') {
+			return error("Path is absolute");
+		}
+
+		let mut chars = path.chars();
+		if let (Some(drive), Some(':')) = (chars.next(), chars.next()) {
+			if drive.is_ascii_alphabetic() {
+				return error("Path starts with a drive letter");
+			}
+		}
+
+		for component in path.split('/') {
+			match component {
+				"" => return error("Path contains an empty component"),
+				"." | ".." => return error("Path contains \".\" or \"..\""),
+				_ => {}
+			}
+		}
+
+		Ok(SafePath(path.to_string()))
+	}
+
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+}
+
+impl AsRef<Path> for SafePath {
+	fn as_ref(&self) -> &Path {
+		Path::new(&self.0)
+	}
+}
+
+impl AsRef<str> for SafePath {
+	fn as_ref(&self) -> &str {
+		&self.0
+	}
+}
+
+impl Display for SafePath {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(formatter, "{}", self.0)
+	}
+}
+
+impl Package {
+	// The Filename, checked so it can be joined onto a mirror root
+	pub fn safe_filename(&self) -> Result<SafePath, APTError> {
+		SafePath::from(&self.filename).map_err(|mut err| {
+			*err.context_mut() = ErrorContext::field("Filename", &self.filename);
+			err
+		})
+	}
+}
+
+impl ReleaseHash {
+	// The filename, checked so it can be joined onto the Release file directory
+	pub fn safe_filename(&self) -> Result<SafePath, APTError> {
+		SafePath::from(&self.filename)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::SafePath;
+	use crate::packages::Package;
+	use std::path::Path;
+
+	#[test]
+	fn path_safety() {
+		for path in [
+			"pool/main/h/hello/hello_2.10-2_amd64.deb",
+			"debs/com.example.tweak_1.0_iphoneos-arm.deb",
+			"main/binary-amd64/Packages.xz",
+			"pool/main/a/apt/apt_2.4.5%3a1_amd64.deb",
+			"...",
+			"..deb",
+		] {
+			let safe = SafePath::from(path).unwrap();
+			assert_eq!(
+				Path::new("/srv/mirror").join(&safe),
+				Path::new("/srv/mirror").join(path)
+			);
+		}
+
+		for path in [
+			"",
+			"/etc/passwd",
+			"../../etc/passwd",
+			"pool/../../etc/passwd",
+			"pool/./hello.deb",
+			"pool//hello.deb",
+			"pool/",
+			"hello.deb\0.txt",
+			"hello\n.deb",
+			"..\\..\\windows",
+			"C:/Windows",
+			"c:hello.deb",
+		] {
+			assert!(SafePath::from(path).is_err(), "{:?} is unsafe", path);
+		}
+
+		let package = Package::from(
+			"Package: evil\nVersion: 1.0\nArchitecture: all\nFilename: ../../etc/cron.d/evil\nSize: 4\n",
+		)
+		.unwrap();
+
+		let err = package.safe_filename().unwrap_err();
+		assert_eq!(err.context().field.as_deref(), Some("Filename"));
+		assert_eq!(
+			err.to_string(),
+			"Path contains \".\" or \"..\" (field Filename, value \"../../etc/cron.d/evil\")"
+		);
+	}
+}
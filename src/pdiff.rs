@@ -0,0 +1,342 @@
+use crate::{
+	errors::{APTError, ErrorContext, MissingKeyError, ParseError},
+	parse_kv,
+	release::{HashKind, ReleaseHash},
+};
+
+// A Packages.diff/Index file, which lists the ed patches that move older
+// versions of an index file to the current one
+#[derive(Debug, Clone)]
+pub struct DiffIndex {
+	// The strongest hash with a Current field, e.g. SHA256
+	pub hash_kind: HashKind,
+	pub current_hash: String,
+	pub current_size: u64,
+	// Each older version of the file, along with the patch that updates it
+	pub history: Vec<ReleaseHash>,
+	// The uncompressed patches
+	pub patches: Vec<ReleaseHash>,
+	// The compressed patches as downloaded, e.g. "2023-01-01-0000.00.gz"
+	pub download: Vec<ReleaseHash>,
+	// X-Patch-Precedence: merged means every patch updates straight to the
+	// current version instead of to the next one in the history
+	pub merged: bool,
+}
+
+// A single patch to apply, along with the hashes to check it against
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchStep<'a> {
+	pub name: &'a str,
+	pub patch: &'a ReleaseHash,
+	pub download: Option<&'a ReleaseHash>,
+	pub result_hash: &'a str,
+	pub result_size: u64,
+}
+
+impl DiffIndex {
+	pub fn from(data: &str) -> Result<DiffIndex, APTError> {
+		let map = parse_kv(data)?;
+
+		let (hash_kind, current) = match HashKind::ALL.iter().find_map(|kind| {
+			map.get(&format!("{}-Current", kind.field()))
+				.map(|current| (*kind, current))
+		}) {
+			Some(current) => current,
+			None => {
+				return Err(APTError::MissingKeyError(MissingKeyError::new(
+					"SHA256-Current",
+				)))
+			}
+		};
+
+		let current_field = format!("{}-Current", hash_kind.field());
+
+		let (current_hash, current_size) = match current.split_whitespace().collect::<Vec<&str>>()[..]
+		{
+			[hash, size] => match size.parse::<u64>() {
+				Ok(size) => (hash.to_string(), size),
+				Err(err) => {
+					return Err(APTError::ParseError(
+						ParseError::field("Invalid file size", &current_field, size)
+							.with_source(err)
+							.locate(data),
+					))
+				}
+			},
+			_ => {
+				return Err(APTError::ParseError(
+					ParseError::field("Current must be \"<hash> <size>\"", &current_field, current)
+						.locate(data),
+				))
+			}
+		};
+
+		let table = |name: &str| -> Result<Vec<ReleaseHash>, APTError> {
+			let field = format!("{}-{}", hash_kind.field(), name);
+			match map.get(&field) {
+				Some(value) => parse_hashes(value).map_err(|err| {
+					APTError::ParseError(
+						err.with_context(ErrorContext::field(&field, value))
+							.locate(data),
+					)
+				}),
+				None => Ok(Vec::new()),
+			}
+		};
+
+		Ok(DiffIndex {
+			hash_kind,
+			current_hash,
+			current_size,
+			history: table("History")?,
+			patches: table("Patches")?,
+			download: table("Download")?,
+			merged: map
+				.get("X-Patch-Precedence")
+				.map(|x| x == "merged")
+				.unwrap_or(false),
+		})
+	}
+
+	// The patches that update a file with the given hash to the current version
+	// An empty chain means the file is already current, and None means it isn't
+	// in the history so the whole index has to be downloaded again
+	pub fn chain(&self, hash: &str) -> Option<Vec<PatchStep<'_>>> {
+		if self.current_hash.eq_ignore_ascii_case(hash) {
+			return Some(Vec::new());
+		}
+
+		let start = self
+			.history
+			.iter()
+			.position(|entry| entry.hash.eq_ignore_ascii_case(hash))?;
+
+		let history = if self.merged {
+			&self.history[start..=start]
+		} else {
+			&self.history[start..]
+		};
+
+		let mut steps = Vec::new();
+		for (index, entry) in history.iter().enumerate() {
+			let name = entry.filename.as_str();
+			let patch = self.patches.iter().find(|patch| patch.filename == name)?;
+			let download = self
+				.download
+				.iter()
+				.find(|download| download.filename == format!("{name}.gz"));
+
+			let (result_hash, result_size) = match history.get(index + 1) {
+				Some(next) => (next.hash.as_str(), next.size),
+				None => (self.current_hash.as_str(), self.current_size),
+			};
+
+			steps.push(PatchStep {
+				name,
+				patch,
+				download,
+				result_hash,
+				result_size,
+			});
+		}
+
+		Some(steps)
+	}
+}
+
+fn parse_hashes(value: &str) -> Result<Vec<ReleaseHash>, ParseError> {
+	let chunks = value.split_whitespace().collect::<Vec<&str>>();
+
+	let mut hashes = Vec::new();
+	for chunk in chunks.chunks(3) {
+		if chunk.len() != 3 {
+			return Err(ParseError::new(
+				"Entries must be \"<hash> <size> <filename>\"",
+			));
+		}
+
+		let size = match chunk[1].parse::<u64>() {
+			Ok(size) => size,
+			Err(err) => return Err(ParseError::new("Invalid file size").with_source(err)),
+		};
+
+		hashes.push(ReleaseHash {
+			filename: chunk[2].to_string(),
+			hash: chunk[0].to_string(),
+			size,
+		});
+	}
+
+	Ok(hashes)
+}
+
+// Applies an ed script, as written by diff --ed, to a text file
+// Only the a, c and d commands are supported, along with the "s/.//" that
+// diff uses to write lines consisting of a single dot
+pub fn apply_ed(original: &str, script: &str) -> Result<String, APTError> {
+	let mut lines = original.split_terminator('\n').collect::<Vec<&str>>();
+	let mut current = lines.len();
+	let mut script = script.split_terminator('\n').enumerate();
+
+	// Errors point at the line of the script that caused them
+	let error = |message: &str, index: usize, value: &str| {
+		APTError::ParseError(
+			ParseError::value(message, value).with_context(ErrorContext {
+				line: Some(index + 1),
+				column: Some(1),
+				value: Some(value.to_string()),
+				..Default::default()
+			}),
+		)
+	};
+
+	while let Some((index, command)) = script.next() {
+		match command {
+			"" | "w" | "q" => continue,
+			"s/.//" => {
+				match current
+					.checked_sub(1)
+					.and_then(|index| lines.get_mut(index))
+				{
+					Some(line) => *line = line.strip_prefix('.').unwrap_or(line),
+					None => return Err(error("No line to substitute", index, command)),
+				}
+				continue;
+			}
+			_ => {}
+		}
+
+		let (range, operation) = match command.len() - 1 {
+			split if command.is_char_boundary(split) => command.split_at(split),
+			_ => return Err(error("Unknown command", index, command)),
+		};
+
+		let line = |value: &str| match value.parse::<usize>() {
+			Ok(line) => Ok(line),
+			Err(_) => Err(error("Invalid line number", index, command)),
+		};
+
+		let (start, end) = match range.split_once(',') {
+			_ if range.is_empty() => (current, current),
+			Some((start, end)) => (line(start)?, line(end)?),
+			None => (line(range)?, line(range)?),
+		};
+
+		let valid = match operation {
+			"a" => start <= lines.len(),
+			"c" | "d" => start >= 1 && start <= end && end <= lines.len(),
+			_ => return Err(error("Unknown command", index, command)),
+		};
+
+		if !valid {
+			return Err(error("Line range is out of bounds", index, command));
+		}
+
+		let mut text = Vec::new();
+		if operation != "d" {
+			loop {
+				match script.next() {
+					Some((_, ".")) => break,
+					Some((_, line)) => text.push(line),
+					None => return Err(error("Text isn't terminated by \".\"", index, command)),
+				}
+			}
+		}
+
+		let count = text.len();
+		match operation {
+			"a" => {
+				lines.splice(start..start, text);
+				current = start + count;
+			}
+			"c" => {
+				lines.splice(start - 1..end, text);
+				current = start - 1 + count;
+			}
+			_ => {
+				lines.drain(start - 1..end);
+				current = start.min(lines.len());
+			}
+		}
+	}
+
+	let mut output = lines.join("\n");
+	if !output.is_empty() {
+		output.push('\n');
+	}
+
+	Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{apply_ed, DiffIndex};
+	use crate::release::HashKind;
+
+	#[test]
+	fn pdiff_ed() {
+		let original = "a\nb\nc\nd\ne\n";
+
+		// Commands are written from the end of the file backwards
+		let script = "5d\n3,4c\nC\n.\n1a\n..\n.\ns/.//\na\nafter dot\n.\n0a\nfirst\n.\n";
+		assert_eq!(
+			apply_ed(original, script).unwrap(),
+			"first\na\n.\nafter dot\nb\nC\n"
+		);
+
+		assert!(apply_ed(original, "9d\n").is_err());
+		assert!(apply_ed(original, "1a\nunterminated\n").is_err());
+		assert!(apply_ed(original, "1x\n").is_err());
+	}
+
+	#[test]
+	fn pdiff_index() {
+		let versions = ["a\nb\n", "a\nb\nc\n", "b\nc\n", "b\nC\n"];
+		let hash = |index: usize| HashKind::Sha256.digest(versions[index].as_bytes());
+
+		let data = format!(
+			"SHA1-Current: 0000 4\nSHA256-Current: {} 4\nSHA256-History:\n {} 4 one\n {} 6 two\n {} 4 three\nSHA256-Patches:\n {} 7 one\n {} 3 two\n {} 7 three\nSHA256-Download:\n {} 30 one.gz\n",
+			hash(3),
+			hash(0),
+			hash(1),
+			hash(2),
+			HashKind::Sha256.digest(b"2a\nc\n.\n"),
+			HashKind::Sha256.digest(b"1d\n"),
+			HashKind::Sha256.digest(b"2c\nC\n.\n"),
+			"ffff",
+		);
+
+		let index = DiffIndex::from(&data).unwrap();
+		assert_eq!(index.hash_kind, HashKind::Sha256);
+		assert_eq!(index.current_size, 4);
+		assert_eq!(index.history.len(), 3);
+		assert!(!index.merged);
+
+		assert_eq!(index.chain(&hash(3)), Some(Vec::new()));
+		assert_eq!(index.chain("ffff"), None);
+
+		let steps = index.chain(&hash(0)).unwrap();
+		assert_eq!(
+			steps.iter().map(|step| step.name).collect::<Vec<&str>>(),
+			vec!["one", "two", "three"]
+		);
+		assert_eq!(steps[0].download.map(|x| x.size), Some(30));
+		assert_eq!(steps[1].download, None);
+
+		// Applying every step lands on each version in turn
+		let scripts = ["2a\nc\n.\n", "1d\n", "2c\nC\n.\n"];
+		let mut file = versions[0].to_string();
+		for (step, script) in steps.iter().zip(scripts) {
+			file = apply_ed(&file, script).unwrap();
+			assert_eq!(HashKind::Sha256.digest(file.as_bytes()), step.result_hash);
+			assert_eq!(file.len() as u64, step.result_size);
+		}
+
+		// Merged patches go straight to the current version
+		let merged = DiffIndex::from(&format!("{data}X-Patch-Precedence: merged\n")).unwrap();
+		let steps = merged.chain(&hash(1)).unwrap();
+		assert_eq!(steps.len(), 1);
+		assert_eq!(steps[0].name, "two");
+		assert_eq!(steps[0].result_hash, hash(3));
+	}
+}
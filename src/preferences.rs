@@ -0,0 +1,563 @@
+use crate::{
+	blank_comments,
+	errors::{APTError, MissingKeyError, ParseError},
+	packages::Package,
+	parse_kv,
+	release::Release,
+	split_stanzas,
+	version::compare_versions,
+};
+use regex::Regex;
+use std::cmp::Ordering;
+
+// The priorities APT assigns when no pin applies
+pub const INSTALLED_PRIORITY: i32 = 100;
+pub const DEFAULT_PRIORITY: i32 = 500;
+pub const NOT_AUTOMATIC_PRIORITY: i32 = 1;
+pub const DEFAULT_RELEASE_PRIORITY: i32 = 990;
+
+// Versions at or above this priority are installed even if that means a downgrade
+pub const DOWNGRADE_PRIORITY: i32 = 1000;
+
+// A name or value in a preferences file, which can be a glob or a /regex/
+#[derive(Debug, Clone)]
+pub enum Pattern {
+	Exact(String),
+	Regex(Regex),
+}
+
+impl Pattern {
+	pub fn from(data: &str) -> Result<Pattern, APTError> {
+		let data = data.trim();
+		if data.len() > 1 && data.starts_with('/') && data.ends_with('/') {
+			return match Regex::new(&data[1..data.len() - 1]) {
+				Ok(regex) => Ok(Pattern::Regex(regex)),
+				Err(err) => Err(APTError::ParseError(
+					ParseError::value("Invalid regular expression", data).with_source(err),
+				)),
+			};
+		}
+
+		if !data.contains(['*', '?']) {
+			return Ok(Pattern::Exact(data.to_string()));
+		}
+
+		let glob = regex::escape(data).replace("\\*", ".*").replace("\\?", ".");
+		match Regex::new(&format!("^{glob}$")) {
+			Ok(regex) => Ok(Pattern::Regex(regex)),
+			Err(err) => Err(APTError::ParseError(
+				ParseError::value("Invalid glob", data).with_source(err),
+			)),
+		}
+	}
+
+	pub fn matches(&self, value: &str) -> bool {
+		match self {
+			Pattern::Exact(exact) => exact == value,
+			Pattern::Regex(regex) => regex.is_match(value),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub enum PackagePattern {
+	Name(Pattern),
+	// "src:name" matches every binary built from the source package
+	Source(Pattern),
+}
+
+impl PackagePattern {
+	pub fn matches(&self, package: &Package) -> bool {
+		match self {
+			PackagePattern::Name(pattern) => pattern.matches(&package.package),
+			PackagePattern::Source(pattern) => {
+				// Source can carry the source version, as in "glibc (2.35-0ubuntu3)"
+				let source = match &package.source {
+					Some(source) => source.split_whitespace().next().unwrap_or(source),
+					None => &package.package,
+				};
+
+				pattern.matches(source)
+			}
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub enum Pin {
+	// Pin: version 2.35*
+	Version(Pattern),
+	// Pin: release o=Ubuntu, a=jammy-updates
+	// The keys are a (archive), n (codename), v (version), o (origin),
+	// l (label), c (component) and b (architecture)
+	Release(Vec<(String, Pattern)>),
+	// Pin: origin "archive.ubuntu.com", where "" is the local system
+	Origin(String),
+}
+
+impl Pin {
+	pub fn from(data: &str) -> Result<Pin, APTError> {
+		let data = data.trim();
+		let (kind, value) = match data.split_once(char::is_whitespace) {
+			Some((kind, value)) => (kind, value.trim()),
+			None => (data, ""),
+		};
+
+		match kind {
+			"version" => Ok(Pin::Version(Pattern::from(value)?)),
+			"origin" => Ok(Pin::Origin(value.trim_matches('"').to_string())),
+			"release" => {
+				let mut conditions = Vec::new();
+				for condition in value.split(',') {
+					let condition = condition.trim();
+					if condition.is_empty() {
+						continue;
+					}
+
+					// A bare value like "release stable" names the archive
+					let (key, value) = match condition.split_once('=') {
+						Some((key, value)) => (key.trim(), value.trim()),
+						None => ("a", condition),
+					};
+
+					if !["a", "n", "v", "o", "l", "c", "b"].contains(&key) {
+						return Err(APTError::ParseError(ParseError::value(
+							"Unknown release pin key",
+							condition,
+						)));
+					}
+
+					conditions.push((key.to_string(), Pattern::from(value)?));
+				}
+
+				Ok(Pin::Release(conditions))
+			}
+			_ => Err(APTError::ParseError(ParseError::value(
+				"Pins must start with version, release or origin",
+				data,
+			))),
+		}
+	}
+
+	pub fn matches_source(&self, source: &PackageSource) -> bool {
+		match self {
+			Pin::Version(_) => false,
+			Pin::Origin(site) => source.site.as_deref().unwrap_or("") == site,
+			Pin::Release(conditions) => conditions.iter().all(|(key, pattern)| {
+				let value = match key.as_str() {
+					// APT accepts the codename for the archive too
+					"a" => {
+						return [&source.suite, &source.codename]
+							.iter()
+							.any(|x| x.as_deref().map_or(false, |x| pattern.matches(x)))
+					}
+					"n" => &source.codename,
+					"v" => &source.version,
+					"o" => &source.origin,
+					"l" => &source.label,
+					"c" => &source.component,
+					_ => &source.architecture,
+				};
+
+				value.as_deref().map_or(false, |x| pattern.matches(x))
+			}),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Preference {
+	pub packages: Vec<PackagePattern>,
+	pub pin: Pin,
+	pub priority: i32,
+	pub explanation: Option<String>,
+	// "Package: *" pins apply to whole repositories rather than to packages
+	pub is_generic: bool,
+}
+
+impl Preference {
+	pub fn from(data: &str) -> Result<Preference, APTError> {
+		let map = match parse_kv(data) {
+			Ok(map) => map,
+			Err(err) => return Err(APTError::KVError(err)),
+		};
+
+		let packages = match map.get("Package") {
+			Some(packages) => packages,
+			None => return Err(APTError::MissingKeyError(MissingKeyError::new("Package"))),
+		};
+
+		let pin = match map.get("Pin") {
+			Some(pin) => match Pin::from(pin) {
+				Ok(pin) => pin,
+				Err(mut err) => {
+					let context = err.context_mut();
+					context.field = Some("Pin".to_string());
+					*context = std::mem::take(context).locate(data);
+					return Err(err);
+				}
+			},
+			None => return Err(APTError::MissingKeyError(MissingKeyError::new("Pin"))),
+		};
+
+		let priority = match map.get("Pin-Priority") {
+			Some(priority) => match priority.trim().parse::<i32>() {
+				Ok(priority) => priority,
+				Err(err) => {
+					return Err(APTError::ParseError(
+						ParseError::field("Invalid pin priority", "Pin-Priority", priority)
+							.with_source(err)
+							.locate(data),
+					))
+				}
+			},
+			None => {
+				return Err(APTError::MissingKeyError(MissingKeyError::new(
+					"Pin-Priority",
+				)))
+			}
+		};
+
+		let is_generic = packages.trim() == "*" && !matches!(pin, Pin::Version(_));
+		let mut patterns = Vec::new();
+
+		for package in packages.split_whitespace() {
+			let pattern = match package.strip_prefix("src:") {
+				Some(source) => PackagePattern::Source(Pattern::from(source)?),
+				None => PackagePattern::Name(Pattern::from(package)?),
+			};
+
+			patterns.push(pattern);
+		}
+
+		Ok(Preference {
+			packages: patterns,
+			pin,
+			priority,
+			explanation: map.get("Explanation").cloned(),
+			is_generic,
+		})
+	}
+
+	pub fn matches_package(&self, package: &Package) -> bool {
+		self.packages.iter().any(|pattern| pattern.matches(package))
+	}
+}
+
+// Where a version of a package can be installed from, as described by the
+// Release file of the repository, or the dpkg status file for installed packages
+#[derive(Debug, Clone, Default)]
+pub struct PackageSource {
+	pub origin: Option<String>,
+	pub label: Option<String>,
+	pub suite: Option<String>,
+	pub codename: Option<String>,
+	pub version: Option<String>,
+	pub component: Option<String>,
+	pub architecture: Option<String>,
+	// The hostname of the repository, as used by "Pin: origin"
+	pub site: Option<String>,
+	pub not_automatic: bool,
+	pub but_automatic_upgrades: bool,
+	pub is_installed: bool,
+}
+
+impl PackageSource {
+	pub fn from(release: &Release, site: &str, component: &str) -> PackageSource {
+		PackageSource {
+			origin: release.origin.clone(),
+			label: release.label.clone(),
+			suite: release.suite.clone(),
+			codename: release.codename.clone(),
+			version: release.version.clone(),
+			component: Some(component.to_string()),
+			architecture: None,
+			site: Some(site.to_string()),
+			not_automatic: release.not_automatic == Some(true),
+			but_automatic_upgrades: release.but_automatic_upgrades == Some(true),
+			is_installed: false,
+		}
+	}
+
+	// The dpkg status file, which APT calls the "now" archive
+	pub fn installed() -> PackageSource {
+		PackageSource {
+			suite: Some("now".to_string()),
+			is_installed: true,
+			..PackageSource::default()
+		}
+	}
+}
+
+pub struct Preferences {
+	pub preferences: Vec<Preference>,
+	pub errors: Vec<APTError>,
+	// APT::Default-Release, which gets priority 990
+	pub default_release: Option<String>,
+}
+
+impl Preferences {
+	pub fn from(data: &str) -> Preferences {
+		// Comments are allowed anywhere in preferences files
+		let binding = blank_comments(data);
+
+		let mut preferences = Vec::new();
+		let mut errors = Vec::new();
+
+		for (index, (offset, line, stanza)) in split_stanzas(&binding).enumerate() {
+			if stanza.trim().is_empty() {
+				continue;
+			}
+
+			match Preference::from(stanza) {
+				Ok(preference) => preferences.push(preference),
+				Err(err) => errors.push(err.in_stanza(index, offset, line)),
+			}
+		}
+
+		Preferences {
+			preferences,
+			errors,
+			default_release: None,
+		}
+	}
+
+	// Files in preferences.d are read in order after /etc/apt/preferences
+	pub fn merge(&mut self, other: Preferences) {
+		self.preferences.extend(other.preferences);
+		self.errors.extend(other.errors);
+	}
+
+	pub fn len(&self) -> usize {
+		self.preferences.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.preferences.is_empty()
+	}
+
+	// The priority of everything in a repository, ignoring package pins
+	pub fn source_priority(&self, source: &PackageSource) -> i32 {
+		let pin = self
+			.preferences
+			.iter()
+			.find(|preference| preference.is_generic && preference.pin.matches_source(source));
+
+		if let Some(preference) = pin {
+			return preference.priority;
+		}
+
+		if source.is_installed {
+			return INSTALLED_PRIORITY;
+		}
+
+		if let Some(release) = &self.default_release {
+			if source.suite.as_ref() == Some(release) || source.codename.as_ref() == Some(release) {
+				return DEFAULT_RELEASE_PRIORITY;
+			}
+		}
+
+		match (source.not_automatic, source.but_automatic_upgrades) {
+			(true, true) => INSTALLED_PRIORITY,
+			(true, false) => NOT_AUTOMATIC_PRIORITY,
+			_ => DEFAULT_PRIORITY,
+		}
+	}
+
+	// The priority apt-cache policy shows for a version available from the sources
+	// The first package pin that matches wins, otherwise the best source counts
+	pub fn priority(&self, package: &Package, sources: &[&PackageSource]) -> i32 {
+		let pin = self.preferences.iter().find(|preference| {
+			if preference.is_generic || !preference.matches_package(package) {
+				return false;
+			}
+
+			match &preference.pin {
+				Pin::Version(pattern) => pattern.matches(&package.version),
+				pin => sources.iter().any(|source| pin.matches_source(source)),
+			}
+		});
+
+		if let Some(preference) = pin {
+			return preference.priority;
+		}
+
+		sources
+			.iter()
+			.map(|source| self.source_priority(source))
+			.max()
+			.unwrap_or(0)
+	}
+
+	// Picks the version APT would install out of every known version of a
+	// package, along with the sources each version is available from
+	// Versions older than the installed one need a priority of at least 1000
+	pub fn candidate<'p>(
+		&self,
+		versions: &[(&'p Package, Vec<&PackageSource>)],
+	) -> Option<&'p Package> {
+		let installed = versions
+			.iter()
+			.find(|(_, sources)| sources.iter().any(|source| source.is_installed))
+			.map(|(package, _)| *package);
+
+		let mut best: Option<(&'p Package, i32)> = None;
+		for (package, sources) in versions {
+			let priority = self.priority(package, sources);
+			if priority < 0 {
+				continue;
+			}
+
+			if let Some(installed) = installed {
+				let older =
+					compare_versions(&package.version, &installed.version) == Ordering::Less;
+				if older && priority < DOWNGRADE_PRIORITY {
+					continue;
+				}
+			}
+
+			let better = match best {
+				None => true,
+				Some((current, current_priority)) => {
+					priority > current_priority
+						|| (priority == current_priority
+							&& compare_versions(&package.version, &current.version)
+								== Ordering::Greater)
+				}
+			};
+
+			if better {
+				best = Some((package, priority));
+			}
+		}
+
+		best.map(|(package, _)| package)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{PackageSource, Pin, Preferences};
+	use crate::{packages::Package, release::Release};
+	use std::fs::read_to_string;
+
+	fn package(name: &str, version: &str) -> Package {
+		let data = format!(
+			"Package: {name}\nSource: glibc (2.35-0ubuntu3)\nVersion: {version}\nArchitecture: amd64\nFilename: pool/{name}_{version}_amd64.deb\nSize: 100"
+		);
+
+		match Package::from(&data) {
+			Ok(package) => package,
+			Err(err) => panic!("Failed to parse package: {}", err),
+		}
+	}
+
+	#[test]
+	fn preferences_priorities() {
+		let release = match read_to_string("./test/jammy.release") {
+			Ok(data) => match Release::from(&data) {
+				Ok(release) => release,
+				Err(err) => panic!("Failed to parse release: {}", err),
+			},
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let mut preferences = Preferences::from(
+			"# Keep the toolchain from the PPA
+Package: src:glibc
+Pin: version 2.35-0ubuntu3
+Pin-Priority: 1001
+
+Package: firefox* /^thunderbird/
+Pin: origin \"ppa.launchpadcontent.net\"
+Pin-Priority: 700
+
+Package: *
+Pin: release o=Ubuntu, a=jammy-backports
+Pin-Priority: 100
+
+Package: *
+Pin: release l=Experimental
+Pin-Priority: -10
+
+Package: broken
+Pin-Priority: 10",
+		);
+
+		assert_eq!(preferences.len(), 4);
+		assert_eq!(preferences.errors.len(), 1);
+		assert!(preferences.preferences[2].is_generic);
+		assert!(matches!(preferences.preferences[0].pin, Pin::Version(_)));
+
+		let jammy = PackageSource::from(&release, "archive.ubuntu.com", "main");
+		assert_eq!(jammy.codename.as_deref(), Some("jammy"));
+		assert_eq!(preferences.source_priority(&jammy), 500);
+
+		let backports = PackageSource {
+			suite: Some("jammy-backports".to_string()),
+			not_automatic: true,
+			but_automatic_upgrades: true,
+			..jammy.clone()
+		};
+		assert_eq!(preferences.source_priority(&backports), 100);
+
+		let experimental = PackageSource {
+			label: Some("Experimental".to_string()),
+			..PackageSource::default()
+		};
+		assert_eq!(preferences.source_priority(&experimental), -10);
+
+		let proposed = PackageSource {
+			suite: Some("jammy-proposed".to_string()),
+			not_automatic: true,
+			..jammy.clone()
+		};
+		assert_eq!(preferences.source_priority(&proposed), 1);
+
+		let ppa = PackageSource {
+			site: Some("ppa.launchpadcontent.net".to_string()),
+			..PackageSource::default()
+		};
+
+		let installed = PackageSource::installed();
+		assert_eq!(preferences.source_priority(&installed), 100);
+
+		let libc6 = package("libc6", "2.35-0ubuntu3.1");
+		let thunderbird = package("thunderbird-locale-de", "102.0");
+		let firefox = package("firefox", "103.0");
+		assert_eq!(preferences.priority(&libc6, &[&jammy]), 500);
+		assert_eq!(preferences.priority(&thunderbird, &[&jammy, &ppa]), 700);
+		assert_eq!(preferences.priority(&firefox, &[&jammy]), 500);
+		assert_eq!(
+			preferences.priority(&firefox, &[&proposed, &installed]),
+			100
+		);
+
+		// The installed version stays unless a newer version beats it
+		let old = package("firefox", "102.0");
+		let newer = package("firefox", "104.0");
+		let candidate = preferences.candidate(&[
+			(&old, vec![&jammy]),
+			(&firefox, vec![&installed]),
+			(&newer, vec![&proposed]),
+		]);
+		assert_eq!(candidate.map(|x| x.version.as_str()), Some("103.0"));
+
+		preferences.default_release = Some("jammy-proposed".to_string());
+		let candidate = preferences.candidate(&[
+			(&old, vec![&jammy]),
+			(&firefox, vec![&installed]),
+			(&newer, vec![&proposed]),
+		]);
+		assert_eq!(candidate.map(|x| x.version.as_str()), Some("104.0"));
+
+		let candidate = preferences.candidate(&[(&newer, vec![&experimental])]);
+		assert!(candidate.is_none());
+
+		// Pins of 1000 and above allow downgrades
+		let downgrade = package("libc6", "2.35-0ubuntu3");
+		let candidate =
+			preferences.candidate(&[(&libc6, vec![&installed]), (&downgrade, vec![&jammy])]);
+		assert_eq!(candidate.map(|x| x.version.as_str()), Some("2.35-0ubuntu3"));
+	}
+}
@@ -0,0 +1,389 @@
+use crate::{
+	errors::{APTError, ParseError},
+	make_array,
+	version::compare_versions,
+};
+use std::{
+	cmp::Ordering,
+	fmt::{Display, Formatter, Result},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RelationKind {
+	Depends,
+	PreDepends,
+	Recommends,
+	Suggests,
+	Enhances,
+	Breaks,
+	Conflicts,
+	Replaces,
+	Provides,
+}
+
+impl RelationKind {
+	pub const ALL: [RelationKind; 9] = [
+		RelationKind::Depends,
+		RelationKind::PreDepends,
+		RelationKind::Recommends,
+		RelationKind::Suggests,
+		RelationKind::Enhances,
+		RelationKind::Breaks,
+		RelationKind::Conflicts,
+		RelationKind::Replaces,
+		RelationKind::Provides,
+	];
+
+	pub fn field(&self) -> &'static str {
+		match self {
+			RelationKind::Depends => "Depends",
+			RelationKind::PreDepends => "Pre-Depends",
+			RelationKind::Recommends => "Recommends",
+			RelationKind::Suggests => "Suggests",
+			RelationKind::Enhances => "Enhances",
+			RelationKind::Breaks => "Breaks",
+			RelationKind::Conflicts => "Conflicts",
+			RelationKind::Replaces => "Replaces",
+			RelationKind::Provides => "Provides",
+		}
+	}
+}
+
+impl Display for RelationKind {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
+		write!(formatter, "{}", self.field())
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VersionOperator {
+	Earlier,
+	EarlierOrEqual,
+	Equal,
+	LaterOrEqual,
+	Later,
+}
+
+impl VersionOperator {
+	pub fn from(data: &str) -> std::result::Result<VersionOperator, APTError> {
+		match data {
+			"<<" => Ok(VersionOperator::Earlier),
+			// The single character forms are deprecated but still mean "or equal"
+			"<=" | "<" => Ok(VersionOperator::EarlierOrEqual),
+			"=" => Ok(VersionOperator::Equal),
+			">=" | ">" => Ok(VersionOperator::LaterOrEqual),
+			">>" => Ok(VersionOperator::Later),
+			_ => Err(APTError::ParseError(ParseError::value(
+				"Invalid version operator",
+				data,
+			))),
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			VersionOperator::Earlier => "<<",
+			VersionOperator::EarlierOrEqual => "<=",
+			VersionOperator::Equal => "=",
+			VersionOperator::LaterOrEqual => ">=",
+			VersionOperator::Later => ">>",
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VersionConstraint {
+	pub operator: VersionOperator,
+	pub version: String,
+}
+
+impl VersionConstraint {
+	pub fn matches(&self, version: &str) -> bool {
+		let ordering = compare_versions(version, &self.version);
+		match self.operator {
+			VersionOperator::Earlier => ordering == Ordering::Less,
+			VersionOperator::EarlierOrEqual => ordering != Ordering::Greater,
+			VersionOperator::Equal => ordering == Ordering::Equal,
+			VersionOperator::LaterOrEqual => ordering != Ordering::Less,
+			VersionOperator::Later => ordering == Ordering::Greater,
+		}
+	}
+}
+
+impl Display for VersionConstraint {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
+		write!(formatter, "{} {}", self.operator.as_str(), self.version)
+	}
+}
+
+// A single package reference such as "libc6:any (>= 2.34) [amd64]"
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Dependency {
+	pub name: String,
+	pub architecture: Option<String>,
+	pub constraint: Option<VersionConstraint>,
+	pub architectures: Vec<String>,
+	pub profiles: Vec<String>,
+}
+
+impl Dependency {
+	pub fn from(data: &str) -> std::result::Result<Dependency, APTError> {
+		let data = data.trim();
+		let end = data
+			.find(|x: char| x.is_whitespace() || "([<".contains(x))
+			.unwrap_or(data.len());
+
+		let (name, architecture) = match data[..end].split_once(':') {
+			Some((name, architecture)) => (name, Some(architecture.to_string())),
+			None => (&data[..end], None),
+		};
+
+		if name.is_empty() || architecture.as_deref() == Some("") {
+			return Err(APTError::ParseError(ParseError::value(
+				"Invalid package name",
+				data,
+			)));
+		}
+
+		let mut dependency = Dependency {
+			name: name.to_string(),
+			architecture,
+			constraint: None,
+			architectures: Vec::new(),
+			profiles: Vec::new(),
+		};
+
+		let mut remainder = data[end..].trim_start();
+		while !remainder.is_empty() {
+			let (open, close) = match remainder.chars().next() {
+				Some('(') => ('(', ')'),
+				Some('[') => ('[', ']'),
+				Some('<') => ('<', '>'),
+				_ => {
+					return Err(APTError::ParseError(ParseError::value(
+						"Unexpected text after the package name",
+						data,
+					)))
+				}
+			};
+
+			let position = match remainder.find(close) {
+				Some(position) => position,
+				None => {
+					return Err(APTError::ParseError(ParseError::value(
+						&format!("Missing closing \"{close}\""),
+						data,
+					)))
+				}
+			};
+
+			let inner = remainder[open.len_utf8()..position].trim();
+			match open {
+				'(' => {
+					if dependency.constraint.is_some() {
+						return Err(APTError::ParseError(ParseError::value(
+							"More than one version constraint",
+							data,
+						)));
+					}
+
+					let split = inner
+						.find(|x: char| !"<=>".contains(x))
+						.unwrap_or(inner.len());
+
+					let operator = match VersionOperator::from(&inner[..split]) {
+						Ok(operator) => operator,
+						Err(_) => {
+							return Err(APTError::ParseError(ParseError::value(
+								"Invalid version operator",
+								data,
+							)))
+						}
+					};
+					let version = inner[split..].trim();
+					if version.is_empty() {
+						return Err(APTError::ParseError(ParseError::value(
+							"Missing version in constraint",
+							data,
+						)));
+					}
+
+					dependency.constraint = Some(VersionConstraint {
+						operator,
+						version: version.to_string(),
+					});
+				}
+				'[' => {
+					dependency
+						.architectures
+						.extend(inner.split_whitespace().map(|x| x.to_string()));
+				}
+				_ => {
+					dependency
+						.profiles
+						.extend(inner.split_whitespace().map(|x| x.to_string()));
+				}
+			}
+
+			remainder = remainder[position + close.len_utf8()..].trim_start();
+		}
+
+		Ok(dependency)
+	}
+
+	pub fn matches_version(&self, version: &str) -> bool {
+		match &self.constraint {
+			Some(constraint) => constraint.matches(version),
+			None => true,
+		}
+	}
+}
+
+impl Display for Dependency {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
+		write!(formatter, "{}", self.name)?;
+		if let Some(architecture) = &self.architecture {
+			write!(formatter, ":{}", architecture)?;
+		}
+
+		if let Some(constraint) = &self.constraint {
+			write!(formatter, " ({})", constraint)?;
+		}
+
+		if !self.architectures.is_empty() {
+			write!(formatter, " [{}]", self.architectures.join(" "))?;
+		}
+
+		if !self.profiles.is_empty() {
+			write!(formatter, " <{}>", self.profiles.join(" "))?;
+		}
+
+		Ok(())
+	}
+}
+
+// One comma-separated entry of a relationship field, made of "|" alternatives
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Relation {
+	pub alternatives: Vec<Dependency>,
+}
+
+impl Relation {
+	pub fn from(data: &str) -> std::result::Result<Relation, APTError> {
+		let mut alternatives = Vec::new();
+		for alternative in data.split('|') {
+			alternatives.push(Dependency::from(alternative)?);
+		}
+
+		Ok(Relation { alternatives })
+	}
+}
+
+impl Display for Relation {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
+		let alternatives = self
+			.alternatives
+			.iter()
+			.map(|x| x.to_string())
+			.collect::<Vec<String>>();
+
+		write!(formatter, "{}", alternatives.join(" | "))
+	}
+}
+
+// Parses every entry of a field such as the one returned by make_array
+pub fn parse_relations(
+	raw_data: Option<&Vec<String>>,
+) -> std::result::Result<Vec<Relation>, APTError> {
+	let mut relations = Vec::new();
+	if let Some(raw_data) = raw_data {
+		for entry in raw_data {
+			if entry.is_empty() {
+				continue;
+			}
+
+			relations.push(Relation::from(entry)?);
+		}
+	}
+
+	Ok(relations)
+}
+
+// Parses the relation field of a kind from its raw value, marking errors
+// with the field. Shared by everything with a relations() method
+pub(crate) fn parse_field_relations(
+	kind: RelationKind,
+	value: Option<&String>,
+) -> std::result::Result<Vec<Relation>, APTError> {
+	parse_relations(make_array(value).as_ref()).map_err(|mut err| {
+		err.context_mut().field = Some(kind.field().to_string());
+		err
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Dependency, Relation, VersionConstraint, VersionOperator};
+
+	#[test]
+	fn relation_alternatives() {
+		let relation = match Relation::from("firmware (>= 12.2) | org.swift.libswift") {
+			Ok(relation) => relation,
+			Err(err) => panic!("Failed to parse relation: {}", err),
+		};
+
+		assert_eq!(
+			relation.alternatives,
+			vec![
+				Dependency {
+					name: "firmware".to_owned(),
+					architecture: None,
+					constraint: Some(VersionConstraint {
+						operator: VersionOperator::LaterOrEqual,
+						version: "12.2".to_owned(),
+					}),
+					architectures: Vec::new(),
+					profiles: Vec::new(),
+				},
+				Dependency {
+					name: "org.swift.libswift".to_owned(),
+					architecture: None,
+					constraint: None,
+					architectures: Vec::new(),
+					profiles: Vec::new(),
+				},
+			]
+		);
+
+		assert_eq!(
+			relation.to_string(),
+			"firmware (>= 12.2) | org.swift.libswift"
+		);
+	}
+
+	#[test]
+	fn relation_qualifiers() {
+		let dependency = match Dependency::from("python3:any (>>3.10~) [amd64 !i386] <!nocheck>") {
+			Ok(dependency) => dependency,
+			Err(err) => panic!("Failed to parse dependency: {}", err),
+		};
+
+		assert_eq!(dependency.name, "python3");
+		assert_eq!(dependency.architecture, Some("any".to_owned()));
+		assert_eq!(
+			dependency.constraint.as_ref().map(|x| x.operator),
+			Some(VersionOperator::Later)
+		);
+		assert_eq!(dependency.architectures, vec!["amd64", "!i386"]);
+		assert_eq!(dependency.profiles, vec!["!nocheck"]);
+
+		assert!(dependency.matches_version("3.10.4-0ubuntu1"));
+		assert!(!dependency.matches_version("3.9.7-1"));
+
+		assert!(Dependency::from("clang (<< 1:3.4.2-7~exp1)").is_ok());
+		assert!(Dependency::from("broken (>= )").is_err());
+		assert!(Dependency::from("broken (== 1.0)").is_err());
+		assert!(Dependency::from("broken (>= 1.0").is_err());
+		assert!(Dependency::from("").is_err());
+	}
+}
@@ -1,8 +1,13 @@
 use crate::{
 	case_map::CaseMap,
-	errors::{APTError, MissingKeyError, ParseError},
-	parse_kv,
+	deb822::Paragraph,
+	errors::{APTError, MissingKeyError, ParseError, ParseMode, Warning},
+	limits::Limits,
+	normalize,
 };
+use md5::{Digest, Md5};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -12,6 +17,44 @@ pub struct ReleaseHash {
 	pub size: u64,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashKind {
+	Md5,
+	Sha1,
+	Sha256,
+	Sha512,
+}
+
+impl HashKind {
+	// Strongest first
+	pub const ALL: [HashKind; 4] = [
+		HashKind::Sha512,
+		HashKind::Sha256,
+		HashKind::Sha1,
+		HashKind::Md5,
+	];
+
+	// The name of the field in the Release file and of the by-hash directory
+	pub fn field(&self) -> &'static str {
+		match self {
+			HashKind::Md5 => "MD5Sum",
+			HashKind::Sha1 => "SHA1",
+			HashKind::Sha256 => "SHA256",
+			HashKind::Sha512 => "SHA512",
+		}
+	}
+
+	// Hashes the data, returning the lowercase hex digest Release files use
+	pub fn digest(&self, data: &[u8]) -> String {
+		match self {
+			HashKind::Md5 => format!("{:x}", Md5::digest(data)),
+			HashKind::Sha1 => format!("{:x}", Sha1::digest(data)),
+			HashKind::Sha256 => format!("{:x}", Sha256::digest(data)),
+			HashKind::Sha512 => format!("{:x}", Sha512::digest(data)),
+		}
+	}
+}
+
 pub struct Release {
 	pub(crate) map: CaseMap,
 	pub architectures: Vec<String>,
@@ -34,14 +77,27 @@ pub struct Release {
 	pub acquire_by_hash: Option<bool>,
 	pub signed_by: Option<String>,
 	pub packages_require_authorization: Option<bool>,
+	// What the parser recovered from, always empty in strict mode
+	pub warnings: Vec<Warning>,
 }
 
 impl Release {
 	pub fn from(data: &str) -> Result<Release, APTError> {
-		let map = match parse_kv(data) {
-			Ok(map) => map,
-			Err(err) => return Err(APTError::KVError(err)),
-		};
+		Release::from_with(data, ParseMode::Lenient)
+	}
+
+	pub fn from_with(data: &str, mode: ParseMode) -> Result<Release, APTError> {
+		Release::from_limited(data, mode, &Limits::default())
+	}
+
+	pub fn from_limited(data: &str, mode: ParseMode, limits: &Limits) -> Result<Release, APTError> {
+		limits.check_input(data)?;
+		let binding = normalize(data);
+		limits.check_stanza(&binding)?;
+
+		let paragraph = Paragraph::from_with(&binding, mode)?;
+		let map = paragraph.to_map();
+		let warnings = paragraph.warnings;
 
 		let architectures = match map.get("Architectures") {
 			Some(architectures) => architectures
@@ -51,7 +107,6 @@ impl Release {
 			None => {
 				return Err(APTError::MissingKeyError(MissingKeyError::new(
 					"Architectures",
-					data,
 				)))
 			}
 		};
@@ -64,7 +119,6 @@ impl Release {
 			None => {
 				return Err(APTError::MissingKeyError(MissingKeyError::new(
 					"Components",
-					data,
 				)))
 			}
 		};
@@ -92,12 +146,25 @@ impl Release {
 			for chunk in chunks {
 				let chunk = chunk.to_vec();
 				if chunk.len() != 3 {
-					return Err(APTError::ParseError(ParseError));
+					return Err(APTError::ParseError(
+						ParseError::field(
+							"Hash entries must be \"<hash> <size> <filename>\"",
+							key,
+							&chunk.join(" "),
+						)
+						.locate(data),
+					));
 				}
 
 				let size = match chunk[1].parse::<u64>() {
 					Ok(size) => size,
-					Err(_) => return Err(APTError::ParseError(ParseError)),
+					Err(err) => {
+						return Err(APTError::ParseError(
+							ParseError::field("Invalid file size", key, chunk[1])
+								.with_source(err)
+								.locate(data),
+						))
+					}
 				};
 
 				hashes.push(ReleaseHash {
@@ -136,12 +203,33 @@ impl Release {
 			packages_require_authorization: map
 				.get("Packages-Require-Authorization")
 				.map(|x| x == "yes"),
+			warnings,
 		})
 	}
 
 	pub fn get(&self, key: &str) -> Option<&str> {
 		self.map.get(key).map(|x| &**x)
 	}
+
+	pub fn hashes(&self, kind: HashKind) -> Option<&Vec<ReleaseHash>> {
+		match kind {
+			HashKind::Md5 => self.md5sum.as_ref(),
+			HashKind::Sha1 => self.sha1sum.as_ref(),
+			HashKind::Sha256 => self.sha256sum.as_ref(),
+			HashKind::Sha512 => self.sha512sum.as_ref(),
+		}
+	}
+
+	// Looks up a file by its path relative to the Release file, using the
+	// strongest hash it is listed with
+	pub fn find(&self, filename: &str) -> Option<(HashKind, &ReleaseHash)> {
+		HashKind::ALL.iter().find_map(|kind| {
+			self.hashes(*kind)?
+				.iter()
+				.find(|hash| hash.filename == filename)
+				.map(|hash| (*kind, hash))
+		})
+	}
 }
 
 #[cfg(test)]
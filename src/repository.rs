@@ -0,0 +1,462 @@
+use crate::{
+	release::{HashKind, Release, ReleaseHash},
+	sources::{SourceEntry, SourceType},
+};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Compression {
+	None,
+	Gzip,
+	Bzip2,
+	Lzma,
+	Xz,
+	Zstd,
+	Lz4,
+}
+
+impl Compression {
+	// Smallest downloads first
+	pub const ORDER: [Compression; 7] = [
+		Compression::Xz,
+		Compression::Zstd,
+		Compression::Lzma,
+		Compression::Bzip2,
+		Compression::Gzip,
+		Compression::Lz4,
+		Compression::None,
+	];
+
+	pub fn extension(&self) -> &'static str {
+		match self {
+			Compression::None => "",
+			Compression::Gzip => ".gz",
+			Compression::Bzip2 => ".bz2",
+			Compression::Lzma => ".lzma",
+			Compression::Xz => ".xz",
+			Compression::Zstd => ".zst",
+			Compression::Lz4 => ".lz4",
+		}
+	}
+
+	pub fn from_path(path: &str) -> Compression {
+		Compression::ORDER
+			.iter()
+			.copied()
+			.find(|x| *x != Compression::None && path.ends_with(x.extension()))
+			.unwrap_or(Compression::None)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKind {
+	Packages,
+	Sources,
+	Translation,
+	Contents,
+}
+
+impl Display for IndexKind {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		let name = match self {
+			IndexKind::Packages => "Packages",
+			IndexKind::Sources => "Sources",
+			IndexKind::Translation => "Translation",
+			IndexKind::Contents => "Contents",
+		};
+
+		write!(formatter, "{name}")
+	}
+}
+
+// An index file listed in a Release file, along with where to download it
+#[derive(Debug, Clone)]
+pub struct IndexFile {
+	pub kind: IndexKind,
+	pub component: Option<String>,
+	pub architecture: Option<String>,
+	pub language: Option<String>,
+	// The path relative to the Release file, like "main/binary-amd64/Packages.xz"
+	pub path: String,
+	pub compression: Compression,
+	pub url: String,
+	pub hash_kind: HashKind,
+	pub hash: ReleaseHash,
+	// The entry of the decompressed file, when the Release file lists it
+	pub uncompressed: Option<ReleaseHash>,
+}
+
+impl IndexFile {
+	// A file in the PDiff directory of the index, e.g. diff_path("Index") is
+	// "main/binary-amd64/Packages.diff/Index"
+	pub fn diff_path(&self, name: &str) -> String {
+		format!("{}.diff/{}", path_stem(&self.path), name)
+	}
+}
+
+// A single URI and suite of a SourceEntry, which has its own Release file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repository {
+	pub source_type: SourceType,
+	pub uri: String,
+	pub suite: String,
+	pub components: Vec<String>,
+	pub architectures: Vec<String>,
+	pub languages: Vec<String>,
+	// The By-Hash option of the entry: "yes", "no" or "force"
+	pub by_hash: Option<String>,
+}
+
+impl SourceEntry {
+	// Entries without an architecture list use the native architecture, and
+	// entries without a language list fetch the English translations
+	pub fn repositories(&self, native_architecture: &str) -> Vec<Repository> {
+		let architectures = match self.architectures.is_empty() {
+			true => vec![native_architecture.to_string()],
+			false => self.architectures.clone(),
+		};
+
+		let languages = match self.get("Languages") {
+			Some(languages) => languages
+				.split_whitespace()
+				.map(|x| x.to_string())
+				.collect(),
+			None => vec!["en".to_string()],
+		};
+
+		let mut repositories = Vec::new();
+		for source_type in &self.types {
+			for uri in &self.uris {
+				for suite in &self.suites {
+					repositories.push(Repository {
+						source_type: *source_type,
+						uri: uri.clone(),
+						suite: suite.clone(),
+						components: self.components.clone(),
+						architectures: architectures.clone(),
+						languages: languages.clone(),
+						by_hash: self.get("By-Hash").map(|x| x.to_string()),
+					});
+				}
+			}
+		}
+
+		repositories
+	}
+}
+
+impl Repository {
+	pub fn is_flat(&self) -> bool {
+		self.components.is_empty()
+	}
+
+	// The directory holding the Release file, always ending in "/"
+	// That's dists/<suite>/ for regular repositories, and the suite path
+	// itself for flat repositories like "./"
+	pub fn base_url(&self) -> String {
+		let uri = self.uri.trim_end_matches('/');
+		if !self.is_flat() {
+			return format!("{}/dists/{}/", uri, self.suite.trim_matches('/'));
+		}
+
+		let path = self.suite.trim_start_matches("./").trim_matches('/');
+		match path.is_empty() {
+			true => format!("{uri}/"),
+			false => format!("{uri}/{path}/"),
+		}
+	}
+
+	pub fn url(&self, path: &str) -> String {
+		format!("{}{}", self.base_url(), path.trim_start_matches('/'))
+	}
+
+	// InRelease first, then Release along with its detached signature
+	pub fn release_urls(&self) -> Vec<String> {
+		["InRelease", "Release", "Release.gpg"]
+			.iter()
+			.map(|file| self.url(file))
+			.collect()
+	}
+
+	fn uses_by_hash(&self, release: &Release) -> bool {
+		match self.by_hash.as_deref() {
+			Some("force") => true,
+			Some("no") => false,
+			_ => release.acquire_by_hash == Some(true),
+		}
+	}
+
+	// Every index file to fetch, picking the best compression the Release
+	// file lists for each one
+	// Files the Release file doesn't list can't be verified, so they're left out
+	pub fn index_files(&self, release: &Release) -> Vec<IndexFile> {
+		self.index_files_with(release, &Compression::ORDER)
+	}
+
+	// Like index_files, limited to the compressions the caller can handle,
+	// in order of preference
+	pub fn index_files_with(
+		&self,
+		release: &Release,
+		compressions: &[Compression],
+	) -> Vec<IndexFile> {
+		let mut architectures = self.architectures.clone();
+		if release.architectures.iter().any(|x| x == "all")
+			&& !architectures.iter().any(|x| x == "all")
+		{
+			architectures.push("all".to_string());
+		}
+
+		let mut targets = Vec::new();
+		if self.is_flat() {
+			let kind = match self.source_type {
+				SourceType::Binary => IndexKind::Packages,
+				SourceType::Source => IndexKind::Sources,
+			};
+
+			targets.push((kind, None, None, None, kind.to_string()));
+		} else {
+			for component in &self.components {
+				match self.source_type {
+					SourceType::Binary => {
+						for architecture in &architectures {
+							let path = format!("{}/binary-{}/Packages", component, architecture);
+
+							targets.push((
+								IndexKind::Packages,
+								Some(component.as_str()),
+								Some(architecture.as_str()),
+								None,
+								path,
+							));
+						}
+					}
+					SourceType::Source => targets.push((
+						IndexKind::Sources,
+						Some(component.as_str()),
+						None,
+						None,
+						format!("{}/source/Sources", component),
+					)),
+				}
+
+				for language in &self.languages {
+					if language == "none" {
+						continue;
+					}
+
+					targets.push((
+						IndexKind::Translation,
+						Some(component.as_str()),
+						None,
+						Some(language.as_str()),
+						format!("{}/i18n/Translation-{}", component, language),
+					));
+				}
+			}
+
+			// Debian keeps Contents in each component, Ubuntu at the top level
+			if self.source_type == SourceType::Binary {
+				for architecture in &architectures {
+					let mut found = false;
+					for component in &self.components {
+						let path = format!("{component}/Contents-{architecture}");
+						if has_any(release, &path) {
+							found = true;
+							targets.push((
+								IndexKind::Contents,
+								Some(component.as_str()),
+								Some(architecture.as_str()),
+								None,
+								path,
+							));
+						}
+					}
+
+					if !found {
+						targets.push((
+							IndexKind::Contents,
+							None,
+							Some(architecture.as_str()),
+							None,
+							format!("Contents-{architecture}"),
+						));
+					}
+				}
+			}
+		}
+
+		let by_hash = self.uses_by_hash(release);
+		let mut files = Vec::new();
+
+		for (kind, component, architecture, language, path) in targets {
+			let found = compressions.iter().find_map(|compression| {
+				let path = format!("{}{}", path, compression.extension());
+				release
+					.find(&path)
+					.map(|(hash_kind, hash)| (*compression, path, hash_kind, hash))
+			});
+
+			let (compression, path, hash_kind, hash) = match found {
+				Some(found) => found,
+				None => continue,
+			};
+
+			let url = match by_hash {
+				true => {
+					let directory = match path.rsplit_once('/') {
+						Some((directory, _)) => format!("{directory}/"),
+						None => String::new(),
+					};
+
+					self.url(&format!(
+						"{}by-hash/{}/{}",
+						directory,
+						hash_kind.field(),
+						hash.hash
+					))
+				}
+				false => self.url(&path),
+			};
+
+			let uncompressed = match compression {
+				Compression::None => None,
+				_ => release
+					.hashes(hash_kind)
+					.and_then(|hashes| hashes.iter().find(|x| x.filename == path_stem(&path)))
+					.cloned(),
+			};
+
+			files.push(IndexFile {
+				kind,
+				component: component.map(|x| x.to_string()),
+				architecture: architecture.map(|x| x.to_string()),
+				language: language.map(|x| x.to_string()),
+				path,
+				compression,
+				url,
+				hash_kind,
+				hash: hash.clone(),
+				uncompressed,
+			});
+		}
+
+		files
+	}
+}
+
+fn has_any(release: &Release, path: &str) -> bool {
+	Compression::ORDER.iter().any(|compression| {
+		release
+			.find(&format!("{}{}", path, compression.extension()))
+			.is_some()
+	})
+}
+
+pub(crate) fn path_stem(path: &str) -> &str {
+	let compression = Compression::from_path(path);
+	path.strip_suffix(compression.extension()).unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Compression, IndexKind};
+	use crate::{release::Release, sources::SourceEntry};
+	use std::fs::read_to_string;
+
+	fn release(file: &str) -> Release {
+		let data = match read_to_string(file) {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		match Release::from(&data) {
+			Ok(release) => release,
+			Err(err) => panic!("Failed to parse release: {}", err),
+		}
+	}
+
+	#[test]
+	fn repository_jammy() {
+		let release = release("./test/jammy.release");
+		let entry =
+			SourceEntry::from_line("deb http://archive.ubuntu.com/ubuntu jammy main universe")
+				.unwrap();
+
+		let repositories = entry.repositories("amd64");
+		assert_eq!(repositories.len(), 1);
+
+		let jammy = &repositories[0];
+		assert_eq!(
+			jammy.release_urls()[0],
+			"http://archive.ubuntu.com/ubuntu/dists/jammy/InRelease"
+		);
+
+		let files = jammy.index_files(&release);
+		let packages = &files[0];
+		assert_eq!(packages.kind, IndexKind::Packages);
+		assert_eq!(packages.path, "main/binary-amd64/Packages.xz");
+		assert_eq!(packages.compression, Compression::Xz);
+		assert_eq!(packages.hash.size, 1407072);
+		assert_eq!(packages.hash_kind.field(), "SHA256");
+		assert_eq!(
+			packages.url,
+			"http://archive.ubuntu.com/ubuntu/dists/jammy/main/binary-amd64/by-hash/SHA256/97d674c77cc65a54e4ca751bf0653c3f635d08a40807cf9489739d4363746c7f"
+		);
+		assert_eq!(
+			packages.uncompressed.as_ref().map(|x| x.size),
+			Some(6831072)
+		);
+
+		let translation = &files[1];
+		assert_eq!(translation.kind, IndexKind::Translation);
+		assert_eq!(translation.language.as_deref(), Some("en"));
+		assert_eq!(translation.path, "main/i18n/Translation-en.xz");
+
+		let contents = files
+			.iter()
+			.find(|file| file.kind == IndexKind::Contents)
+			.unwrap();
+		assert_eq!(contents.path, "Contents-amd64.gz");
+		assert_eq!(contents.component, None);
+
+		assert_eq!(files.len(), 5);
+
+		// Without by-hash the plain path is used
+		let entry = SourceEntry::from_line(
+			"deb [arch=arm64 by-hash=no lang=none] http://ports.ubuntu.com/ubuntu-ports/ jammy main",
+		)
+		.unwrap();
+
+		let files = entry.repositories("amd64")[0].index_files(&release);
+		assert_eq!(files.len(), 2);
+		assert_eq!(
+			files[0].url,
+			"http://ports.ubuntu.com/ubuntu-ports/dists/jammy/main/binary-arm64/Packages.xz"
+		);
+	}
+
+	#[test]
+	fn repository_flat() {
+		let release = release("./test/chariz.release");
+		let entry = SourceEntry::from_line("deb https://repo.chariz.com/ ./").unwrap();
+
+		let chariz = &entry.repositories("iphoneos-arm")[0];
+		assert!(chariz.is_flat());
+		assert_eq!(chariz.release_urls()[1], "https://repo.chariz.com/Release");
+
+		let files = chariz.index_files(&release);
+		assert_eq!(files.len(), 1);
+		assert_eq!(files[0].url, "https://repo.chariz.com/Packages.xz");
+		assert_eq!(files[0].hash_kind.field(), "SHA512");
+		assert_eq!(files[0].uncompressed.as_ref().map(|x| x.size), Some(368031));
+
+		let entry =
+			SourceEntry::from_line("deb http://apt.thebigboss.org/repofiles/cydia/ stable/")
+				.unwrap();
+		assert_eq!(
+			entry.repositories("iphoneos-arm")[0].url("Packages.bz2"),
+			"http://apt.thebigboss.org/repofiles/cydia/stable/Packages.bz2"
+		);
+	}
+}
@@ -0,0 +1,332 @@
+use crate::{
+	errors::APTError,
+	index::{PackageIndex, Provider},
+	packages::Package,
+	relation::{Dependency, Relation, RelationKind},
+};
+
+// The result of checking one comma-separated entry of a relationship field
+#[derive(Debug, Clone)]
+pub struct Satisfaction<'a> {
+	pub relation: Relation,
+	pub candidates: Vec<Provider<'a>>,
+	pub applies: bool,
+}
+
+impl<'a> Satisfaction<'a> {
+	// Relations restricted to other architectures are trivially satisfied
+	pub fn is_satisfied(&self) -> bool {
+		!self.applies || !self.candidates.is_empty()
+	}
+
+	pub fn packages(&self) -> Vec<&'a Package> {
+		let mut packages = Vec::<&'a Package>::new();
+		for candidate in &self.candidates {
+			if !packages.iter().any(|x| std::ptr::eq(*x, candidate.package)) {
+				packages.push(candidate.package);
+			}
+		}
+
+		packages
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SatisfactionReport<'a> {
+	pub relations: Vec<Satisfaction<'a>>,
+}
+
+impl<'a> SatisfactionReport<'a> {
+	pub fn is_satisfied(&self) -> bool {
+		self.relations.iter().all(Satisfaction::is_satisfied)
+	}
+
+	pub fn unsatisfied(&self) -> Vec<&Relation> {
+		self.relations
+			.iter()
+			.filter(|x| !x.is_satisfied())
+			.map(|x| &x.relation)
+			.collect()
+	}
+}
+
+impl<'a> PackageIndex<'a> {
+	// Candidates for a dependency declared by a package of the given
+	// architecture, following the Multi-Arch rules for qualifiers:
+	//  - "foo" is satisfied by the same architecture, "all" or Multi-Arch: foreign
+	//  - "foo:any" is only satisfied by Multi-Arch: allowed
+	//  - "foo:<arch>" is only satisfied by that architecture
+	pub fn satisfiers(&self, dependency: &Dependency, architecture: &str) -> Vec<&Provider<'a>> {
+		self.candidates(dependency)
+			.into_iter()
+			.filter(|provider| architecture_matches(dependency, provider.package, architecture))
+			.collect()
+	}
+
+	// Architecture-independent packages depend as if they were native, so
+	// pass the native architecture when the depending package is "all"
+	pub fn check(&self, relations: &[Relation], architecture: &str) -> SatisfactionReport<'a> {
+		let relations = relations
+			.iter()
+			.map(|relation| {
+				let mut applies = false;
+				let mut candidates = Vec::new();
+
+				for dependency in &relation.alternatives {
+					if !applies_to(dependency, architecture) {
+						continue;
+					}
+
+					applies = true;
+					for provider in self.satisfiers(dependency, architecture) {
+						candidates.push(provider.clone());
+					}
+				}
+
+				Satisfaction {
+					relation: relation.clone(),
+					candidates,
+					applies,
+				}
+			})
+			.collect();
+
+		SatisfactionReport { relations }
+	}
+
+	// Checks a raw field value such as "libc6 (>= 2.34), default-logind | logind"
+	pub fn check_field(
+		&self,
+		data: &str,
+		architecture: &str,
+	) -> Result<SatisfactionReport<'a>, APTError> {
+		let mut relations = Vec::new();
+		for entry in data.split(',') {
+			if entry.trim().is_empty() {
+				continue;
+			}
+
+			relations.push(Relation::from(entry)?);
+		}
+
+		Ok(self.check(&relations, architecture))
+	}
+
+	pub fn check_package(
+		&self,
+		package: &Package,
+		kind: RelationKind,
+		native_architecture: &str,
+	) -> Result<SatisfactionReport<'a>, APTError> {
+		let relations = package.relations(kind)?;
+		let architecture = match package.architecture.as_str() {
+			"all" => native_architecture,
+			architecture => architecture,
+		};
+
+		Ok(self.check(&relations, architecture))
+	}
+}
+
+pub(crate) fn architecture_matches(
+	dependency: &Dependency,
+	package: &Package,
+	architecture: &str,
+) -> bool {
+	let multi_arch = package.multi_arch.as_deref().unwrap_or("no");
+	match dependency.architecture.as_deref() {
+		// Only Multi-Arch: allowed satisfies :any, and foreign packages only
+		// stand in for other architectures when no qualifier was written
+		Some("any") => multi_arch == "allowed",
+		Some("native") => package.architecture == architecture || package.architecture == "all",
+		Some(qualifier) => package.architecture == qualifier,
+		None => {
+			package.architecture == architecture
+				|| package.architecture == "all"
+				|| multi_arch == "foreign"
+		}
+	}
+}
+
+// Handles the "[amd64 !i386]" restriction lists used by source packages
+pub(crate) fn applies_to(dependency: &Dependency, architecture: &str) -> bool {
+	if dependency.architectures.is_empty() {
+		return true;
+	}
+
+	let negated = dependency.architectures.iter().all(|x| x.starts_with('!'));
+	let listed = dependency
+		.architectures
+		.iter()
+		.any(|x| matches_wildcard(x.trim_start_matches('!'), architecture));
+
+	if negated {
+		!listed
+	} else {
+		listed
+	}
+}
+
+// Architecture wildcards like "any", "linux-any" and "any-amd64"
+fn matches_wildcard(wildcard: &str, architecture: &str) -> bool {
+	if wildcard == architecture || wildcard == "any" {
+		return true;
+	}
+
+	if let Some(os) = wildcard.strip_suffix("-any") {
+		return architecture.starts_with(&format!("{os}-"))
+			|| (os == "linux" && !architecture.contains('-'));
+	}
+
+	if let Some(cpu) = wildcard.strip_prefix("any-") {
+		return architecture == cpu || architecture.ends_with(&format!("-{cpu}"));
+	}
+
+	false
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		index::PackageIndex,
+		packages::Packages,
+		relation::{Relation, RelationKind},
+	};
+	use std::fs::read_to_string;
+
+	fn multiarch_packages() -> Packages {
+		Packages::from(
+			"Package: python3
+Architecture: amd64
+Version: 3.10.4-0ubuntu2
+Multi-Arch: allowed
+Filename: pool/main/p/python3-defaults/python3_3.10.4-0ubuntu2_amd64.deb
+Size: 22842
+
+Package: libc6
+Architecture: i386
+Version: 2.35-0ubuntu3
+Multi-Arch: same
+Filename: pool/main/g/glibc/libc6_2.35-0ubuntu3_i386.deb
+Size: 3000000
+
+Package: libc6
+Architecture: amd64
+Version: 2.35-0ubuntu3
+Multi-Arch: same
+Filename: pool/main/g/glibc/libc6_2.35-0ubuntu3_amd64.deb
+Size: 3000000
+
+Package: make
+Architecture: amd64
+Version: 4.3-4.1build1
+Multi-Arch: foreign
+Filename: pool/main/m/make-dfsg/make_4.3-4.1build1_amd64.deb
+Size: 180000
+
+Package: tzdata
+Architecture: all
+Version: 2022a-0ubuntu1
+Provides: tzdata-bookworm
+Filename: pool/main/t/tzdata/tzdata_2022a-0ubuntu1_all.deb
+Size: 340000",
+		)
+	}
+
+	#[test]
+	fn satisfy_multiarch() {
+		let packages = multiarch_packages();
+		let index = PackageIndex::new(&[&packages]);
+
+		let check = |data: &str, architecture: &str| match index.check_field(data, architecture) {
+			Ok(report) => report.is_satisfied(),
+			Err(err) => panic!("Failed to parse relations: {}", err),
+		};
+
+		assert!(check("python3:any (>= 3.10)", "i386"));
+		assert!(!check("python3", "i386"));
+		assert!(!check("python3:any (>= 3.11)", "amd64"));
+
+		assert!(check("libc6 (>= 2.34)", "i386"));
+		assert!(!check("libc6:any", "amd64"));
+		assert!(check("libc6:i386", "amd64"));
+		assert!(!check("libc6:armhf", "amd64"));
+
+		assert!(check("make", "i386"));
+		// Foreign packages only satisfy dependencies without a qualifier
+		assert!(!check("make:any", "i386"));
+		assert!(!check("make:i386", "i386"));
+		assert!(check("make:amd64", "i386"));
+		assert!(!check("make:armhf (>= 4.3)", "amd64"));
+		assert!(check("tzdata, tzdata-bookworm", "arm64"));
+		assert!(check("make [amd64] | missing-package", "amd64"));
+
+		// Restricted to other architectures, so nothing needs to exist
+		assert!(check("missing-package [!amd64]", "amd64"));
+		assert!(!check("missing-package [linux-any]", "amd64"));
+
+		let report = match index.check_field("libc6, missing-package | make", "amd64") {
+			Ok(report) => report,
+			Err(err) => panic!("Failed to parse relations: {}", err),
+		};
+
+		assert!(report.is_satisfied());
+		let packages = report.relations[0].packages();
+		assert_eq!(packages.len(), 1);
+		assert_eq!(packages[0].architecture, "amd64");
+		assert_eq!(report.relations[1].packages()[0].package, "make");
+
+		let report = index.check(
+			&[
+				Relation::from("python3").unwrap(),
+				Relation::from("missing-package").unwrap(),
+			],
+			"armhf",
+		);
+
+		let unsatisfied = report
+			.unsatisfied()
+			.iter()
+			.map(|x| x.to_string())
+			.collect::<Vec<String>>();
+		assert_eq!(unsatisfied, vec!["python3", "missing-package"]);
+	}
+
+	#[test]
+	fn satisfy_chariz() {
+		let file = "./test/chariz.packages";
+		let data = match read_to_string(file) {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let packages = Packages::from(&data);
+		let index = PackageIndex::new(&[&packages]);
+
+		let arpoison = &packages[0];
+		let report = match index.check_package(arpoison, RelationKind::Depends, "iphoneos-arm") {
+			Ok(report) => report,
+			Err(err) => panic!("Failed to check package: {}", err),
+		};
+
+		assert!(report.is_satisfied());
+		assert_eq!(report.relations[0].packages()[0].package, "libnet9");
+
+		// Jailbreak repos rely on packages from the bootstrap like mobilesubstrate
+		let voltage = match packages.get("ca.kirara.voltage") {
+			Some(package) => package,
+			None => panic!("Missing package ca.kirara.voltage"),
+		};
+
+		let report = match index.check_package(voltage, RelationKind::Depends, "iphoneos-arm") {
+			Ok(report) => report,
+			Err(err) => panic!("Failed to check package: {}", err),
+		};
+
+		assert!(!report.is_satisfied());
+		assert!(report
+			.unsatisfied()
+			.iter()
+			.any(|x| x.to_string() == "mobilesubstrate"));
+	}
+}
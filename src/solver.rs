@@ -0,0 +1,992 @@
+use crate::{
+	errors::APTError,
+	index::PackageIndex,
+	installability::Reason,
+	packages::{address, Package, Packages},
+	preferences::{DEFAULT_PRIORITY, INSTALLED_PRIORITY},
+	relation::{Relation, RelationKind},
+	status::DpkgStatus,
+	universe::Universe,
+	version::compare_versions,
+};
+use std::{
+	cmp::Ordering,
+	collections::{HashMap, HashSet},
+	error::Error,
+	fmt::{Display, Formatter},
+};
+
+const STEP_LIMIT: usize = 100_000;
+const REASON_LIMIT: usize = 8;
+
+#[derive(Debug, Clone)]
+pub enum Action<'a> {
+	Install(&'a Package),
+	Upgrade { from: &'a Package, to: &'a Package },
+	Downgrade { from: &'a Package, to: &'a Package },
+	// Removed because a newly installed package Replaces it
+	Replace { from: &'a Package, to: &'a Package },
+	Remove(&'a Package),
+}
+
+#[derive(Debug, Clone)]
+pub struct Plan<'a> {
+	pub actions: Vec<Action<'a>>,
+}
+
+impl<'a> Plan<'a> {
+	pub fn is_empty(&self) -> bool {
+		self.actions.is_empty()
+	}
+
+	// Every package that has to be downloaded and unpacked
+	pub fn installs(&self) -> Vec<&'a Package> {
+		self.actions
+			.iter()
+			.filter_map(|action| match action {
+				Action::Install(package) => Some(*package),
+				Action::Upgrade { to, .. } | Action::Downgrade { to, .. } => Some(*to),
+				_ => None,
+			})
+			.collect()
+	}
+
+	pub fn removals(&self) -> Vec<&'a Package> {
+		self.actions
+			.iter()
+			.filter_map(|action| match action {
+				Action::Remove(package) => Some(*package),
+				Action::Replace { from, .. } => Some(*from),
+				_ => None,
+			})
+			.collect()
+	}
+}
+
+#[derive(Debug)]
+pub enum SolverError<'a> {
+	UnknownPackage(String),
+	Essential(&'a Package),
+	Unsatisfiable(Vec<Reason<'a>>),
+	StepLimit,
+}
+
+impl<'a> Error for SolverError<'a> {}
+
+impl<'a> Display for SolverError<'a> {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			SolverError::UnknownPackage(name) => {
+				write!(formatter, "Unable to locate package {}", name)
+			}
+			SolverError::Essential(package) => {
+				write!(
+					formatter,
+					"Refusing to remove essential package {}",
+					package.package
+				)
+			}
+			SolverError::Unsatisfiable(reasons) => {
+				write!(
+					formatter,
+					"Unable to satisfy dependencies ({} problems)",
+					reasons.len()
+				)
+			}
+			SolverError::StepLimit => write!(formatter, "Gave up resolving dependencies"),
+		}
+	}
+}
+
+pub struct Solver<'a> {
+	index: PackageIndex<'a>,
+	installed: HashSet<usize>,
+	local: HashSet<usize>,
+	pins: HashMap<(String, String, String), i32>,
+	architecture: String,
+	installs: Vec<Relation>,
+	removals: Vec<String>,
+	upgrade: bool,
+}
+
+impl<'a> Solver<'a> {
+	pub fn new(
+		available: &[&'a Packages],
+		status: &'a DpkgStatus,
+		native_architecture: &str,
+	) -> Solver<'a> {
+		let mut solver = Solver {
+			index: PackageIndex::new(available),
+			installed: HashSet::new(),
+			local: HashSet::new(),
+			pins: HashMap::new(),
+			architecture: native_architecture.to_string(),
+			installs: Vec::new(),
+			removals: Vec::new(),
+			upgrade: false,
+		};
+
+		for entry in status.installed() {
+			let installed = &entry.package;
+
+			// Installed versions that are still available are the same package
+			let existing = solver
+				.index
+				.providers(&installed.package)
+				.iter()
+				.find(|provider| {
+					!provider.is_virtual
+						&& provider.package.version == installed.version
+						&& provider.package.architecture == installed.architecture
+				})
+				.map(|provider| provider.package);
+
+			match existing {
+				Some(package) => {
+					solver.installed.insert(address(package));
+				}
+				None => {
+					solver.index.insert(installed);
+					solver.installed.insert(address(installed));
+					solver.local.insert(address(installed));
+				}
+			}
+		}
+
+		solver
+	}
+
+	pub fn pin(&mut self, package: &Package, priority: i32) {
+		self.pins.insert(key(package), priority);
+	}
+
+	pub fn priority(&self, package: &Package) -> i32 {
+		if let Some(priority) = self.pins.get(&key(package)) {
+			return *priority;
+		}
+
+		if self.local.contains(&address(package)) {
+			INSTALLED_PRIORITY
+		} else {
+			DEFAULT_PRIORITY
+		}
+	}
+
+	// Accepts a relation such as "foo", "foo (>= 1.0)" or "foo:i386"
+	pub fn install(&mut self, data: &str) -> Result<(), APTError> {
+		self.installs.push(Relation::from(data)?);
+		Ok(())
+	}
+
+	pub fn remove(&mut self, name: &str) {
+		self.removals.push(name.to_string());
+	}
+
+	// Upgrades installed packages where possible, keeping back the rest
+	pub fn upgrade(&mut self) {
+		self.upgrade = true;
+	}
+
+	pub fn solve(&self) -> Result<Plan<'a>, SolverError<'a>> {
+		let order = |a: &Package, b: &Package| self.rank(a, b);
+		let universe = Universe::new(
+			&self.index,
+			&self.architecture,
+			&[RelationKind::PreDepends, RelationKind::Depends],
+			&order,
+		);
+
+		let mut transaction = Transaction::new(&universe);
+		for id in 0..universe.len() {
+			let package = universe.packages[id];
+			transaction.priorities[id] = self.priority(package);
+			transaction.essential[id] = package.is_essential == Some(true);
+
+			if self.installed.contains(&address(package)) {
+				transaction.installed[id] = true;
+				transaction.selected[id] = true;
+				transaction.slots.insert(universe.slot(id), id);
+			}
+		}
+
+		for id in 0..universe.len() {
+			transaction
+				.versions
+				.entry(universe.slot(id))
+				.or_default()
+				.push(id);
+		}
+
+		for versions in transaction.versions.values_mut() {
+			versions.sort_by(|a, b| self.rank(universe.packages[*a], universe.packages[*b]));
+		}
+
+		for name in &self.removals {
+			for id in 0..universe.len() {
+				if universe.packages[id].package != *name {
+					continue;
+				}
+
+				transaction.forbidden[id] = true;
+				if !transaction.selected[id] {
+					continue;
+				}
+
+				if transaction.essential[id] {
+					return Err(SolverError::Essential(universe.packages[id]));
+				}
+
+				transaction.evict(id);
+			}
+		}
+
+		for relation in &self.installs {
+			let mut candidates = Vec::new();
+			for dependency in &relation.alternatives {
+				let mut satisfiers = self
+					.index
+					.satisfiers(dependency, &self.architecture)
+					.iter()
+					.filter_map(|provider| universe.id(provider.package))
+					.collect::<Vec<usize>>();
+
+				satisfiers.sort_by(|a, b| self.rank(universe.packages[*a], universe.packages[*b]));
+				for satisfier in satisfiers {
+					if !candidates.contains(&satisfier) {
+						candidates.push(satisfier);
+					}
+				}
+			}
+
+			if candidates.is_empty() {
+				return Err(SolverError::UnknownPackage(relation.to_string()));
+			}
+
+			transaction.request(candidates, false, true);
+		}
+
+		if self.upgrade {
+			for id in 0..universe.len() {
+				if !transaction.selected[id] {
+					continue;
+				}
+
+				let versions = transaction.versions[&universe.slot(id)].clone();
+				if versions.first() != Some(&id) {
+					transaction.request(versions, true, false);
+				}
+			}
+		}
+
+		match transaction.solve(0) {
+			Some(true) => Ok(transaction.plan()),
+			Some(false) => Err(SolverError::Unsatisfiable(transaction.reasons)),
+			None => Err(SolverError::StepLimit),
+		}
+	}
+
+	// Higher pin priorities first, then newer versions
+	fn rank(&self, a: &Package, b: &Package) -> Ordering {
+		self.priority(b)
+			.cmp(&self.priority(a))
+			.then_with(|| compare_versions(&b.version, &a.version))
+	}
+}
+
+fn key(package: &Package) -> (String, String, String) {
+	(
+		package.package.clone(),
+		package.version.clone(),
+		package.architecture.clone(),
+	)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Item {
+	Requirement(usize, usize),
+	Request(usize),
+}
+
+#[derive(Debug, Clone)]
+struct Request {
+	candidates: Vec<usize>,
+	// Optional requests may be dropped, like held back upgrades
+	optional: bool,
+	protect: bool,
+}
+
+// Every change is logged so a failed branch can be rolled back
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+	Select(usize, Option<usize>),
+	Evict(usize),
+	Protect(usize),
+}
+
+struct Transaction<'u, 'a> {
+	universe: &'u Universe<'a>,
+	priorities: Vec<i32>,
+	installed: Vec<bool>,
+	essential: Vec<bool>,
+	forbidden: Vec<bool>,
+	selected: Vec<bool>,
+	requested: Vec<bool>,
+	// Packages evicted in the current branch can't come back, which keeps
+	// the search from swapping two versions back and forth
+	evicted: Vec<bool>,
+	parents: Vec<Option<usize>>,
+	slots: HashMap<(&'a str, &'a str), usize>,
+	versions: HashMap<(&'a str, &'a str), Vec<usize>>,
+	log: Vec<Operation>,
+	agenda: Vec<Item>,
+	requests: Vec<Request>,
+	steps: usize,
+	reasons: Vec<Reason<'a>>,
+	seen: HashSet<String>,
+}
+
+impl<'u, 'a> Transaction<'u, 'a> {
+	fn new(universe: &'u Universe<'a>) -> Transaction<'u, 'a> {
+		let length = universe.len();
+		Transaction {
+			universe,
+			priorities: vec![DEFAULT_PRIORITY; length],
+			installed: vec![false; length],
+			essential: vec![false; length],
+			forbidden: vec![false; length],
+			selected: vec![false; length],
+			requested: vec![false; length],
+			evicted: vec![false; length],
+			parents: vec![None; length],
+			slots: HashMap::new(),
+			versions: HashMap::new(),
+			log: Vec::new(),
+			agenda: Vec::new(),
+			requests: Vec::new(),
+			steps: 0,
+			reasons: Vec::new(),
+			seen: HashSet::new(),
+		}
+	}
+
+	fn request(&mut self, candidates: Vec<usize>, optional: bool, protect: bool) {
+		self.agenda.push(Item::Request(self.requests.len()));
+		self.requests.push(Request {
+			candidates,
+			optional,
+			protect,
+		});
+	}
+
+	fn allowed(&self, id: usize) -> bool {
+		!self.forbidden[id]
+			&& !self.evicted[id]
+			&& self.universe.malformed[id].is_none()
+			&& (self.priorities[id] >= 0 || self.installed[id])
+	}
+
+	fn removable(&self, id: usize) -> bool {
+		!self.requested[id] && !self.essential[id]
+	}
+
+	fn mark(&self) -> (usize, usize, usize) {
+		(self.log.len(), self.agenda.len(), self.requests.len())
+	}
+
+	fn undo(&mut self, mark: (usize, usize, usize)) {
+		while self.log.len() > mark.0 {
+			match self.log.pop() {
+				Some(Operation::Select(id, parent)) => {
+					self.selected[id] = false;
+					self.parents[id] = parent;
+					let slot = self.universe.slot(id);
+					if self.slots.get(&slot) == Some(&id) {
+						self.slots.remove(&slot);
+					}
+				}
+				Some(Operation::Evict(id)) => {
+					self.selected[id] = true;
+					self.evicted[id] = false;
+					self.slots.insert(self.universe.slot(id), id);
+				}
+				Some(Operation::Protect(id)) => self.requested[id] = false,
+				None => break,
+			}
+		}
+
+		self.agenda.truncate(mark.1);
+		self.requests.truncate(mark.2);
+	}
+
+	fn evict(&mut self, id: usize) {
+		self.selected[id] = false;
+		self.evicted[id] = true;
+
+		let slot = self.universe.slot(id);
+		if self.slots.get(&slot) == Some(&id) {
+			self.slots.remove(&slot);
+		}
+
+		self.log.push(Operation::Evict(id));
+		for (owner, requirement) in &self.universe.dependents[id] {
+			if self.selected[*owner] {
+				self.agenda.push(Item::Requirement(*owner, *requirement));
+			}
+		}
+	}
+
+	// Gives a removed package the chance to come back at another version
+	fn reinstate(&mut self, id: usize) {
+		let versions = self.versions[&self.universe.slot(id)]
+			.iter()
+			.copied()
+			.filter(|x| *x != id)
+			.collect::<Vec<usize>>();
+
+		if !versions.is_empty() {
+			self.request(versions, true, false);
+		}
+	}
+
+	fn protect(&mut self, id: usize) {
+		if !self.requested[id] {
+			self.requested[id] = true;
+			self.log.push(Operation::Protect(id));
+		}
+	}
+
+	fn chain(&self, id: usize) -> Vec<&'a Package> {
+		let mut chain = vec![self.universe.packages[id]];
+		let mut current = id;
+
+		while let Some(parent) = self.parents[current] {
+			if chain.len() > self.universe.len() {
+				break;
+			}
+
+			chain.push(self.universe.packages[parent]);
+			current = parent;
+		}
+
+		chain.reverse();
+		chain
+	}
+
+	fn record(&mut self, key: String, reason: Reason<'a>) {
+		if self.reasons.len() < REASON_LIMIT && self.seen.insert(key) {
+			self.reasons.push(reason);
+		}
+	}
+
+	fn record_conflict(
+		&mut self,
+		owner: Option<usize>,
+		candidate: usize,
+		conflicting: usize,
+		kind: Option<RelationKind>,
+	) {
+		let chain = match owner {
+			Some(owner) => self.chain(owner),
+			None => Vec::new(),
+		};
+
+		let relation = match owner {
+			Some(owner) => self.universe.requirements[owner]
+				.iter()
+				.find(|x| x.candidates.contains(&candidate))
+				.map(|x| x.relation.clone()),
+			None => None,
+		};
+
+		let candidate = self.universe.packages[candidate];
+		let relation = match relation {
+			Some(relation) => relation,
+			None => match Relation::from(&candidate.package) {
+				Ok(relation) => relation,
+				Err(_) => return,
+			},
+		};
+
+		let conflicting = self.universe.packages[conflicting];
+		let key = format!(
+			"conflict:{}:{}:{}:{}",
+			relation, candidate.package, candidate.version, conflicting.package
+		);
+
+		self.record(
+			key,
+			Reason::Conflict {
+				chain,
+				relation,
+				candidate,
+				conflicting,
+				kind,
+			},
+		);
+	}
+
+	// Selects a package, evicting whatever it replaces or clashes with
+	// Returns false if a protected package is in the way
+	fn select(&mut self, candidate: usize, parent: Option<usize>, protect: bool) -> bool {
+		let slot = self.universe.slot(candidate);
+		if let Some(&existing) = self.slots.get(&slot) {
+			if self.requested[existing] {
+				self.record_conflict(parent, candidate, existing, None);
+				return false;
+			}
+
+			self.evict(existing);
+		}
+
+		let mut clashing = Vec::new();
+		for restriction in &self.universe.restrictions[candidate] {
+			if self.selected[restriction.target] {
+				clashing.push((restriction.target, restriction.kind));
+			}
+		}
+
+		for (owner, kind) in &self.universe.restricted_by[candidate] {
+			if self.selected[*owner] {
+				clashing.push((*owner, *kind));
+			}
+		}
+
+		for (other, kind) in clashing {
+			if !self.selected[other] {
+				continue;
+			}
+
+			if !self.removable(other) {
+				self.record_conflict(parent, candidate, other, Some(kind));
+				return false;
+			}
+
+			self.evict(other);
+			self.reinstate(other);
+		}
+
+		self.selected[candidate] = true;
+		self.log
+			.push(Operation::Select(candidate, self.parents[candidate]));
+		self.parents[candidate] = parent;
+		self.slots.insert(slot, candidate);
+
+		if protect {
+			self.protect(candidate);
+		}
+
+		for requirement in 0..self.universe.requirements[candidate].len() {
+			self.agenda.push(Item::Requirement(candidate, requirement));
+		}
+
+		true
+	}
+
+	// Tries one choice and continues with the rest of the agenda
+	fn attempt(
+		&mut self,
+		candidate: usize,
+		parent: Option<usize>,
+		protect: bool,
+		position: usize,
+	) -> Option<bool> {
+		self.steps += 1;
+		if self.steps > STEP_LIMIT {
+			return None;
+		}
+
+		let mark = self.mark();
+		if self.select(candidate, parent, protect) {
+			match self.solve(position + 1) {
+				Some(true) => return Some(true),
+				Some(false) => {}
+				None => return None,
+			}
+		}
+
+		self.undo(mark);
+		Some(false)
+	}
+
+	fn solve(&mut self, position: usize) -> Option<bool> {
+		let mut position = position;
+		while position < self.agenda.len() {
+			match self.agenda[position] {
+				Item::Requirement(owner, index) => {
+					let requirement = &self.universe.requirements[owner][index];
+					if !self.selected[owner]
+						|| requirement.candidates.iter().any(|x| self.selected[*x])
+					{
+						position += 1;
+						continue;
+					}
+
+					let candidates = requirement
+						.candidates
+						.iter()
+						.copied()
+						.filter(|x| self.allowed(*x))
+						.collect::<Vec<usize>>();
+
+					if candidates.is_empty() {
+						let reason = Reason::Missing {
+							chain: self.chain(owner),
+							relation: requirement.relation.clone(),
+						};
+
+						let key = format!(
+							"missing:{}:{}",
+							self.universe.packages[owner].package, requirement.relation
+						);
+						self.record(key, reason);
+					}
+
+					for candidate in candidates {
+						match self.attempt(candidate, Some(owner), false, position) {
+							Some(true) => return Some(true),
+							Some(false) => {}
+							None => return None,
+						}
+					}
+
+					// As a last resort the package with the broken dependency goes
+					if self.removable(owner) {
+						let mark = self.mark();
+						self.evict(owner);
+						self.reinstate(owner);
+
+						match self.solve(position + 1) {
+							Some(true) => return Some(true),
+							Some(false) => {}
+							None => return None,
+						}
+
+						self.undo(mark);
+					}
+
+					return Some(false);
+				}
+				Item::Request(index) => {
+					let request = self.requests[index].clone();
+					let satisfied = request.candidates.first().map(|x| self.selected[*x]);
+
+					if satisfied == Some(true) {
+						if request.protect {
+							self.protect(request.candidates[0]);
+						}
+
+						position += 1;
+						continue;
+					}
+
+					for candidate in request.candidates.iter().copied() {
+						if self.selected[candidate] {
+							// Keeping the version that's already selected
+							let mark = self.mark();
+							if request.protect {
+								self.protect(candidate);
+							}
+
+							match self.solve(position + 1) {
+								Some(true) => return Some(true),
+								Some(false) => {}
+								None => return None,
+							}
+
+							self.undo(mark);
+							continue;
+						}
+
+						if !self.allowed(candidate) {
+							continue;
+						}
+
+						match self.attempt(candidate, None, request.protect, position) {
+							Some(true) => return Some(true),
+							Some(false) => {}
+							None => return None,
+						}
+					}
+
+					if request.optional {
+						position += 1;
+						continue;
+					}
+
+					return Some(false);
+				}
+			}
+		}
+
+		Some(true)
+	}
+
+	fn plan(&self) -> Plan<'a> {
+		let mut actions = Vec::new();
+		let packages = &self.universe.packages;
+
+		let mut selected = self
+			.log
+			.iter()
+			.filter_map(|operation| match operation {
+				Operation::Select(id, _) if self.selected[*id] => Some(*id),
+				_ => None,
+			})
+			.collect::<Vec<usize>>();
+		selected.dedup();
+
+		let mut replaced = HashSet::new();
+		for id in &selected {
+			let slot = self.universe.slot(*id);
+			let previous = (0..packages.len()).find(|x| {
+				self.installed[*x] && !self.selected[*x] && self.universe.slot(*x) == slot
+			});
+
+			match previous {
+				Some(previous) => {
+					replaced.insert(previous);
+					let (from, to) = (packages[previous], packages[*id]);
+					match compare_versions(&to.version, &from.version) {
+						Ordering::Less => actions.push(Action::Downgrade { from, to }),
+						_ => actions.push(Action::Upgrade { from, to }),
+					}
+				}
+				None if !self.installed[*id] => actions.push(Action::Install(packages[*id])),
+				None => {}
+			}
+		}
+
+		for id in 0..packages.len() {
+			if !self.installed[id] || self.selected[id] || replaced.contains(&id) {
+				continue;
+			}
+
+			let from = packages[id];
+			let replacement = selected
+				.iter()
+				.map(|x| packages[*x])
+				.find(|to| replaces(to, from));
+
+			match replacement {
+				Some(to) => actions.push(Action::Replace { from, to }),
+				None => actions.push(Action::Remove(from)),
+			}
+		}
+
+		Plan { actions }
+	}
+}
+
+fn replaces(package: &Package, other: &Package) -> bool {
+	let relations = match package.relations(RelationKind::Replaces) {
+		Ok(relations) => relations,
+		Err(_) => return false,
+	};
+
+	relations.iter().any(|relation| {
+		relation.alternatives.iter().any(|dependency| {
+			dependency.name == other.package && dependency.matches_version(&other.version)
+		})
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Action, Solver, SolverError};
+	use crate::{installability::Reason, packages::Packages, status::DpkgStatus};
+
+	fn available() -> Packages {
+		Packages::from(
+			"Package: app
+Architecture: amd64
+Version: 2.0
+Depends: libfoo (>= 2.0)
+Conflicts: oldtool
+Filename: pool/app_2.0_amd64.deb
+Size: 100
+
+Package: libfoo
+Architecture: amd64
+Version: 2.0
+Filename: pool/libfoo_2.0_amd64.deb
+Size: 100
+
+Package: libfoo
+Architecture: amd64
+Version: 1.0
+Filename: pool/libfoo_1.0_amd64.deb
+Size: 100
+
+Package: consumer
+Architecture: amd64
+Version: 1.1
+Depends: libfoo (>= 1.0)
+Filename: pool/consumer_1.1_amd64.deb
+Size: 100
+
+Package: newtool
+Architecture: amd64
+Version: 1.0
+Conflicts: oldtool
+Replaces: oldtool
+Provides: tool
+Filename: pool/newtool_1.0_amd64.deb
+Size: 100
+
+Package: broken
+Architecture: amd64
+Version: 1.0
+Depends: missing-library
+Filename: pool/broken_1.0_amd64.deb
+Size: 100",
+		)
+	}
+
+	fn status() -> DpkgStatus {
+		DpkgStatus::from(
+			"Package: libfoo
+Status: install ok installed
+Architecture: amd64
+Version: 1.0
+
+Package: consumer
+Status: install ok installed
+Architecture: amd64
+Version: 1.0
+Depends: libfoo (<< 2.0)
+
+Package: oldtool
+Status: install ok installed
+Architecture: amd64
+Version: 0.9
+
+Package: base-files
+Status: install ok installed
+Essential: yes
+Architecture: amd64
+Version: 12ubuntu4",
+		)
+	}
+
+	fn describe(action: &Action) -> String {
+		match action {
+			Action::Install(package) => format!("install {} {}", package.package, package.version),
+			Action::Upgrade { from, to } => format!(
+				"upgrade {} {} -> {}",
+				from.package, from.version, to.version
+			),
+			Action::Downgrade { from, to } => {
+				format!(
+					"downgrade {} {} -> {}",
+					from.package, from.version, to.version
+				)
+			}
+			Action::Replace { from, to } => format!("replace {} with {}", from.package, to.package),
+			Action::Remove(package) => format!("remove {}", package.package),
+		}
+	}
+
+	#[test]
+	fn solver_plan() {
+		let packages = available();
+		let status = status();
+		let mut solver = Solver::new(&[&packages], &status, "amd64");
+
+		if let Err(err) = solver.install("app") {
+			panic!("Failed to parse request: {}", err);
+		}
+
+		if let Err(err) = solver.install("tool") {
+			panic!("Failed to parse request: {}", err);
+		}
+
+		let plan = match solver.solve() {
+			Ok(plan) => plan,
+			Err(err) => panic!("Failed to solve: {}", err),
+		};
+
+		let mut actions = plan.actions.iter().map(describe).collect::<Vec<String>>();
+		actions.sort();
+
+		assert_eq!(
+			actions,
+			vec![
+				"install app 2.0",
+				"install newtool 1.0",
+				"replace oldtool with newtool",
+				"upgrade consumer 1.0 -> 1.1",
+				"upgrade libfoo 1.0 -> 2.0",
+			]
+		);
+
+		assert_eq!(plan.installs().len(), 4);
+		assert_eq!(plan.removals()[0].package, "oldtool");
+	}
+
+	#[test]
+	fn solver_pins_and_errors() {
+		let packages = available();
+		let status = status();
+
+		let mut solver = Solver::new(&[&packages], &status, "amd64");
+		solver.remove("base-files");
+		assert!(matches!(solver.solve(), Err(SolverError::Essential(_))));
+
+		let mut solver = Solver::new(&[&packages], &status, "amd64");
+		solver.install("missing-package").unwrap();
+		assert!(matches!(
+			solver.solve(),
+			Err(SolverError::UnknownPackage(_))
+		));
+
+		let mut solver = Solver::new(&[&packages], &status, "amd64");
+		solver.install("broken").unwrap();
+		match solver.solve() {
+			Err(SolverError::Unsatisfiable(reasons)) => {
+				assert!(reasons.iter().any(|reason| match reason {
+					Reason::Missing { relation, .. } => relation.to_string() == "missing-library",
+					_ => false,
+				}));
+			}
+			result => panic!(
+				"Expected an unsatisfiable request: {:?}",
+				result.map(|x| x.actions.len())
+			),
+		}
+
+		// consumer 1.1 accepts the newer libfoo, so both upgrade together
+		let mut solver = Solver::new(&[&packages], &status, "amd64");
+		solver.upgrade();
+		let plan = match solver.solve() {
+			Ok(plan) => plan,
+			Err(err) => panic!("Failed to solve: {}", err),
+		};
+
+		let mut actions = plan.actions.iter().map(describe).collect::<Vec<String>>();
+		actions.sort();
+		assert_eq!(
+			actions,
+			vec!["upgrade consumer 1.0 -> 1.1", "upgrade libfoo 1.0 -> 2.0"]
+		);
+
+		// A negative pin keeps libfoo 2.0 out, so app can't be installed anymore
+		let mut solver = Solver::new(&[&packages], &status, "amd64");
+		for package in packages.versions("libfoo") {
+			if package.version == "2.0" {
+				solver.pin(package, -1);
+			}
+		}
+
+		solver.upgrade();
+		let plan = match solver.solve() {
+			Ok(plan) => plan,
+			Err(err) => panic!("Failed to solve: {}", err),
+		};
+
+		let actions = plan.actions.iter().map(describe).collect::<Vec<String>>();
+		assert_eq!(actions, vec!["upgrade consumer 1.0 -> 1.1"]);
+
+		solver.install("app").unwrap();
+		assert!(matches!(solver.solve(), Err(SolverError::Unsatisfiable(_))));
+	}
+}
@@ -0,0 +1,706 @@
+use crate::{
+	blank_comments,
+	deb822::Paragraph,
+	errors::{APTError, MissingKeyError, ParseError},
+	parse_kv, split_stanzas,
+};
+use std::fmt::{Display, Formatter};
+
+// Options of the one-line format next to their deb822 field names
+const OPTIONS: [(&str, &str); 17] = [
+	("arch", "Architectures"),
+	("lang", "Languages"),
+	("target", "Targets"),
+	("pdiffs", "PDiffs"),
+	("by-hash", "By-Hash"),
+	("allow-insecure", "Allow-Insecure"),
+	("allow-weak", "Allow-Weak"),
+	("allow-downgrade-to-insecure", "Allow-Downgrade-To-Insecure"),
+	("trusted", "Trusted"),
+	("signed-by", "Signed-By"),
+	("check-valid-until", "Check-Valid-Until"),
+	("valid-until-min", "Valid-Until-Min"),
+	("valid-until-max", "Valid-Until-Max"),
+	("check-date", "Check-Date"),
+	("date-max-future", "Date-Max-Future"),
+	("inrelease-path", "InRelease-Path"),
+	("snapshot", "Snapshot"),
+];
+
+// Options holding several values, separated by commas on one line and by
+// whitespace in deb822
+const LIST_OPTIONS: [&str; 4] = ["Architectures", "Languages", "Targets", "Signed-By"];
+
+// Fields that have their own member in SourceEntry
+const FIELDS: [&str; 7] = [
+	"Types",
+	"URIs",
+	"Suites",
+	"Components",
+	"Enabled",
+	"Architectures",
+	"Signed-By",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceType {
+	Binary,
+	Source,
+}
+
+impl SourceType {
+	pub fn from(data: &str) -> Result<SourceType, APTError> {
+		match data {
+			"deb" => Ok(SourceType::Binary),
+			"deb-src" => Ok(SourceType::Source),
+			_ => Err(APTError::ParseError(ParseError::value(
+				"Types must be deb or deb-src",
+				data,
+			))),
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			SourceType::Binary => "deb",
+			SourceType::Source => "deb-src",
+		}
+	}
+}
+
+impl Display for SourceType {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(formatter, "{}", self.as_str())
+	}
+}
+
+// One repository entry of /etc/apt/sources.list or /etc/apt/sources.list.d
+// A deb822 stanza can describe several types, URIs and suites at once,
+// while a line of the one-line format always holds exactly one of each
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceEntry {
+	pub types: Vec<SourceType>,
+	pub uris: Vec<String>,
+	pub suites: Vec<String>,
+	pub components: Vec<String>,
+	pub enabled: bool,
+	pub architectures: Vec<String>,
+	pub signed_by: Option<String>,
+	// Every other option by its deb822 field name, in deb822 form
+	pub options: Vec<(String, String)>,
+}
+
+impl SourceEntry {
+	// Parses a stanza of a deb822 .sources file
+	pub fn from(data: &str) -> Result<SourceEntry, APTError> {
+		let map = match parse_kv(data) {
+			Ok(map) => map,
+			Err(err) => return Err(APTError::KVError(err)),
+		};
+
+		let field = |key: &str| -> Result<Vec<String>, APTError> {
+			match map.get(key) {
+				Some(value) => Ok(value.split_whitespace().map(|x| x.to_string()).collect()),
+				None => Err(APTError::MissingKeyError(MissingKeyError::new(key))),
+			}
+		};
+
+		let types = field("Types")?
+			.iter()
+			.map(|x| SourceType::from(x))
+			.collect::<Result<Vec<SourceType>, APTError>>()
+			.map_err(|mut err| {
+				let context = err.context_mut();
+				context.field = Some("Types".to_string());
+				*context = std::mem::take(context).locate(data);
+				err
+			})?;
+
+		let uris = field("URIs")?;
+		let suites = field("Suites")?;
+		let components = field("Components").unwrap_or_default();
+
+		let enabled = match map.get("Enabled") {
+			Some(value) => match parse_bool(value) {
+				Some(enabled) => enabled,
+				None => {
+					return Err(APTError::ParseError(
+						ParseError::field("Enabled must be a boolean", "Enabled", value)
+							.locate(data),
+					))
+				}
+			},
+			None => true,
+		};
+
+		let architectures = field("Architectures").unwrap_or_default();
+
+		// An embedded key keeps its lines, while paths and fingerprints may be
+		// folded over several
+		let signed_by = Paragraph::from(data)?
+			.get("Signed-By")
+			.map(|field| match is_embedded_key(&field.value) {
+				true => field.lines().join("\n").trim().to_string(),
+				false => field.folded(),
+			});
+
+		// Walk the stanza itself since the map doesn't keep the field order
+		let mut options = Vec::new();
+		for line in data.lines() {
+			if line.starts_with(char::is_whitespace) {
+				continue;
+			}
+
+			let key = match line.split_once(':') {
+				Some((key, _)) => key.trim(),
+				None => continue,
+			};
+
+			if FIELDS.iter().any(|x| x.eq_ignore_ascii_case(key)) {
+				continue;
+			}
+
+			if let Some(value) = map.get(key) {
+				options.push((deb822_name(key), value.trim().to_string()));
+			}
+		}
+
+		let entry = SourceEntry {
+			types,
+			uris,
+			suites,
+			components,
+			enabled,
+			architectures,
+			signed_by,
+			options,
+		};
+
+		entry.validate()?;
+		Ok(entry)
+	}
+
+	// Parses a line like "deb [arch=amd64 signed-by=/usr/share/keyrings/x.gpg] http://... jammy main"
+	// Lines commented out with a leading "#" come back as disabled entries
+	pub fn from_line(line: &str) -> Result<SourceEntry, APTError> {
+		let line = line.trim();
+		let (enabled, line) = match line.strip_prefix('#') {
+			Some(line) => (false, line.trim_start()),
+			None => (true, line),
+		};
+
+		// Trailing comments are allowed after the components
+		let line = match line.split_once('#') {
+			Some((line, _)) => line.trim_end(),
+			None => line,
+		};
+
+		let (kind, rest) = match line.split_once(char::is_whitespace) {
+			Some((kind, rest)) => (SourceType::from(kind)?, rest.trim_start()),
+			None => {
+				return Err(APTError::ParseError(ParseError::value(
+					"Missing URI and suite",
+					line,
+				)))
+			}
+		};
+
+		let mut entry = SourceEntry {
+			types: vec![kind],
+			uris: Vec::new(),
+			suites: Vec::new(),
+			components: Vec::new(),
+			enabled,
+			architectures: Vec::new(),
+			signed_by: None,
+			options: Vec::new(),
+		};
+
+		let rest = match rest.strip_prefix('[') {
+			Some(rest) => {
+				let (options, rest) = match rest.split_once(']') {
+					Some(split) => split,
+					None => {
+						return Err(APTError::ParseError(ParseError::value(
+							"Missing closing \"]\" after the options",
+							line,
+						)))
+					}
+				};
+
+				for option in options.split_whitespace() {
+					let (key, value) = match option.split_once('=') {
+						Some(split) => split,
+						None => {
+							return Err(APTError::ParseError(ParseError::value(
+								"Options must be key=value",
+								option,
+							)))
+						}
+					};
+
+					let key = deb822_name(key);
+					let value = if LIST_OPTIONS.contains(&key.as_str()) {
+						value.split(',').collect::<Vec<&str>>().join(" ")
+					} else {
+						value.to_string()
+					};
+
+					match key.as_str() {
+						"Architectures" => {
+							entry.architectures =
+								value.split_whitespace().map(|x| x.to_string()).collect()
+						}
+						"Signed-By" => entry.signed_by = Some(value),
+						_ => entry.options.push((key, value)),
+					}
+				}
+
+				rest.trim_start()
+			}
+			None => rest,
+		};
+
+		let mut words = rest.split_whitespace();
+		match (words.next(), words.next()) {
+			(Some(uri), Some(suite)) => {
+				entry.uris.push(uri.to_string());
+				entry.suites.push(suite.to_string());
+			}
+			_ => {
+				return Err(APTError::ParseError(ParseError::value(
+					"Missing URI and suite",
+					line,
+				)))
+			}
+		}
+
+		entry.components = words.map(|x| x.to_string()).collect();
+		entry.validate()?;
+		Ok(entry)
+	}
+
+	// Flat repositories use an exact path ending in "/" and no components,
+	// every other suite needs at least one component
+	fn validate(&self) -> Result<(), APTError> {
+		if self.types.is_empty() || self.uris.is_empty() || self.suites.is_empty() {
+			return Err(APTError::ParseError(ParseError::new(
+				"Types, URIs and Suites can't be empty",
+			)));
+		}
+
+		for suite in &self.suites {
+			if suite.ends_with('/') != self.components.is_empty() {
+				let message = if suite.ends_with('/') {
+					"Flat repositories can't have components"
+				} else {
+					"Suites need at least one component unless they end with \"/\""
+				};
+
+				return Err(APTError::ParseError(ParseError::field(
+					message, "Suites", suite,
+				)));
+			}
+		}
+
+		Ok(())
+	}
+
+	pub fn is_flat(&self) -> bool {
+		self.components.is_empty()
+	}
+
+	pub fn get(&self, key: &str) -> Option<&str> {
+		self.options
+			.iter()
+			.find(|(name, _)| name.eq_ignore_ascii_case(key))
+			.map(|(_, value)| value.as_str())
+	}
+
+	pub fn to_deb822(&self) -> String {
+		let types = self
+			.types
+			.iter()
+			.map(SourceType::as_str)
+			.collect::<Vec<&str>>();
+
+		let mut lines = vec![
+			format!("Types: {}", types.join(" ")),
+			format!("URIs: {}", self.uris.join(" ")),
+			format!("Suites: {}", self.suites.join(" ")),
+		];
+
+		if !self.components.is_empty() {
+			lines.push(format!("Components: {}", self.components.join(" ")));
+		}
+
+		if !self.enabled {
+			lines.push("Enabled: no".to_string());
+		}
+
+		if !self.architectures.is_empty() {
+			lines.push(format!("Architectures: {}", self.architectures.join(" ")));
+		}
+
+		if let Some(signed_by) = &self.signed_by {
+			lines.push(deb822_field("Signed-By", signed_by));
+		}
+
+		for (key, value) in &self.options {
+			lines.push(deb822_field(key, value));
+		}
+
+		lines.join("\n")
+	}
+
+	// One line for every combination of type, URI and suite
+	// A Signed-By with an embedded key can't be written on one line
+	pub fn to_lines(&self) -> Result<Vec<String>, APTError> {
+		let mut options = Vec::new();
+		if !self.architectures.is_empty() {
+			options.push(format!("arch={}", self.architectures.join(",")));
+		}
+
+		if let Some(signed_by) = &self.signed_by {
+			if is_embedded_key(signed_by) {
+				return Err(APTError::ParseError(ParseError::field(
+					"An embedded key can only be written in deb822",
+					"Signed-By",
+					signed_by,
+				)));
+			}
+
+			options.push(format!("signed-by={}", list_value("Signed-By", signed_by)));
+		}
+
+		for (key, value) in &self.options {
+			options.push(format!("{}={}", line_name(key), list_value(key, value)));
+		}
+
+		let mut prefix = String::new();
+		if !self.enabled {
+			prefix.push_str("# ");
+		}
+
+		let options = match options.is_empty() {
+			true => String::new(),
+			false => format!(" [{}]", options.join(" ")),
+		};
+
+		let mut lines = Vec::new();
+		for kind in &self.types {
+			for uri in &self.uris {
+				for suite in &self.suites {
+					let mut line = format!("{prefix}{kind}{options} {uri} {suite}");
+					for component in &self.components {
+						line.push(' ');
+						line.push_str(component);
+					}
+
+					lines.push(line);
+				}
+			}
+		}
+
+		Ok(lines)
+	}
+}
+
+// Booleans are read like APT's StringToBool, so "true", "on" or "1" work too
+fn parse_bool(value: &str) -> Option<bool> {
+	match value.trim().to_lowercase().as_str() {
+		"yes" | "true" | "with" | "on" | "enable" | "1" => Some(true),
+		"no" | "false" | "without" | "off" | "disable" | "0" => Some(false),
+		_ => None,
+	}
+}
+
+fn is_embedded_key(value: &str) -> bool {
+	value.contains("-----BEGIN PGP PUBLIC KEY BLOCK-----")
+}
+
+// Values with several lines are written as continuation lines, with "." for
+// the empty ones so they don't end the stanza
+fn deb822_field(key: &str, value: &str) -> String {
+	if !value.contains('\n') {
+		return format!("{key}: {value}");
+	}
+
+	let mut field = format!("{key}:");
+	for line in value.split('\n') {
+		match line.trim().is_empty() {
+			true => field.push_str("\n ."),
+			false => field.push_str(&format!("\n {line}")),
+		}
+	}
+
+	field
+}
+
+fn deb822_name(option: &str) -> String {
+	match OPTIONS.iter().find(|(line, deb822)| {
+		line.eq_ignore_ascii_case(option) || deb822.eq_ignore_ascii_case(option)
+	}) {
+		Some((_, deb822)) => deb822.to_string(),
+		None => option.to_string(),
+	}
+}
+
+fn line_name(field: &str) -> String {
+	match OPTIONS.iter().find(|(_, deb822)| *deb822 == field) {
+		Some((line, _)) => line.to_string(),
+		None => field.to_lowercase(),
+	}
+}
+
+fn list_value(field: &str, value: &str) -> String {
+	if LIST_OPTIONS.contains(&field) {
+		value.split_whitespace().collect::<Vec<&str>>().join(",")
+	} else {
+		value.to_string()
+	}
+}
+
+pub struct Sources {
+	pub entries: Vec<SourceEntry>,
+	pub errors: Vec<APTError>,
+}
+
+impl Sources {
+	// Parses a deb822 .sources file
+	pub fn from(data: &str) -> Sources {
+		let binding = blank_comments(data);
+
+		let mut entries = Vec::new();
+		let mut errors = Vec::new();
+
+		for (index, (offset, line, stanza)) in split_stanzas(&binding).enumerate() {
+			if stanza.trim().is_empty() {
+				continue;
+			}
+
+			match SourceEntry::from(stanza) {
+				Ok(entry) => entries.push(entry),
+				Err(err) => errors.push(err.in_stanza(index, offset, line)),
+			}
+		}
+
+		Sources { entries, errors }
+	}
+
+	// Parses a one-line sources.list file
+	// Comments that aren't commented out entries are skipped
+	pub fn from_list(data: &str) -> Sources {
+		let mut entries = Vec::new();
+		let mut errors = Vec::new();
+
+		for (index, line) in data.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() {
+				continue;
+			}
+
+			match SourceEntry::from_line(line) {
+				Ok(entry) => entries.push(entry),
+				Err(mut err) if !line.starts_with('#') => {
+					err.context_mut().line = Some(index + 1);
+					errors.push(err);
+				}
+				Err(_) => {}
+			}
+		}
+
+		Sources { entries, errors }
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	pub fn enabled(&self) -> impl Iterator<Item = &SourceEntry> {
+		self.entries.iter().filter(|entry| entry.enabled)
+	}
+
+	pub fn to_deb822(&self) -> String {
+		let stanzas = self
+			.entries
+			.iter()
+			.map(SourceEntry::to_deb822)
+			.collect::<Vec<String>>();
+
+		format!("{}\n", stanzas.join("\n\n"))
+	}
+
+	pub fn to_list(&self) -> Result<String, APTError> {
+		let mut data = String::new();
+		for entry in &self.entries {
+			for line in entry.to_lines()? {
+				data.push_str(&line);
+				data.push('\n');
+			}
+		}
+
+		Ok(data)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{SourceEntry, SourceType, Sources};
+
+	#[test]
+	fn sources_list() {
+		let sources = Sources::from_list(
+			"# See http://help.ubuntu.com/community/UpgradeNotes for how to upgrade
+deb http://archive.ubuntu.com/ubuntu/ jammy main restricted
+deb [arch=amd64,arm64 signed-by=/usr/share/keyrings/docker.gpg] https://download.docker.com/linux/ubuntu jammy stable # Docker
+# deb-src http://archive.ubuntu.com/ubuntu/ jammy main restricted
+deb [trusted=yes] https://repo.chariz.com/ ./
+
+deb http://broken.example.com/",
+		);
+
+		assert_eq!(sources.len(), 4);
+		assert_eq!(sources.errors.len(), 1);
+		assert_eq!(sources.enabled().count(), 3);
+
+		let docker = &sources.entries[1];
+		assert_eq!(docker.types, vec![SourceType::Binary]);
+		assert_eq!(docker.architectures, vec!["amd64", "arm64"]);
+		assert_eq!(
+			docker.signed_by.as_deref(),
+			Some("/usr/share/keyrings/docker.gpg")
+		);
+		assert_eq!(docker.components, vec!["stable"]);
+
+		let disabled = &sources.entries[2];
+		assert!(!disabled.enabled);
+		assert_eq!(disabled.types, vec![SourceType::Source]);
+
+		let chariz = &sources.entries[3];
+		assert!(chariz.is_flat());
+		assert_eq!(chariz.get("trusted"), Some("yes"));
+
+		assert_eq!(
+			sources.to_deb822(),
+			"Types: deb
+URIs: http://archive.ubuntu.com/ubuntu/
+Suites: jammy
+Components: main restricted
+
+Types: deb
+URIs: https://download.docker.com/linux/ubuntu
+Suites: jammy
+Components: stable
+Architectures: amd64 arm64
+Signed-By: /usr/share/keyrings/docker.gpg
+
+Types: deb-src
+URIs: http://archive.ubuntu.com/ubuntu/
+Suites: jammy
+Components: main restricted
+Enabled: no
+
+Types: deb
+URIs: https://repo.chariz.com/
+Suites: ./
+Trusted: yes
+"
+		);
+
+		// Converting back and forth keeps every entry
+		let converted = Sources::from(&sources.to_deb822());
+		assert!(converted.errors.is_empty());
+		assert_eq!(converted.entries, sources.entries);
+		assert_eq!(
+			Sources::from_list(&converted.to_list().unwrap()).entries,
+			sources.entries
+		);
+	}
+
+	#[test]
+	fn sources_deb822() {
+		let sources = Sources::from(
+			"# Ubuntu sources have moved to /etc/apt/sources.list.d/ubuntu.sources
+Types: deb deb-src
+URIs: http://archive.ubuntu.com/ubuntu/
+Suites: noble noble-updates
+Components: main universe
+Signed-By: /usr/share/keyrings/ubuntu-archive-keyring.gpg
+PDiffs: no
+Languages: en de
+
+Types: deb
+URIs: http://example.com/
+Suites: stable",
+		);
+
+		assert_eq!(sources.len(), 1);
+		assert_eq!(sources.errors.len(), 1);
+
+		let ubuntu = &sources.entries[0];
+		assert_eq!(ubuntu.types, vec![SourceType::Binary, SourceType::Source]);
+		assert_eq!(ubuntu.get("PDiffs"), Some("no"));
+		assert_eq!(ubuntu.get("languages"), Some("en de"));
+
+		let lines = ubuntu.to_lines().unwrap();
+		assert_eq!(lines.len(), 4);
+		assert_eq!(
+			lines[0],
+			"deb [signed-by=/usr/share/keyrings/ubuntu-archive-keyring.gpg pdiffs=no lang=en,de] http://archive.ubuntu.com/ubuntu/ noble main universe"
+		);
+		assert_eq!(
+			lines[3],
+			"deb-src [signed-by=/usr/share/keyrings/ubuntu-archive-keyring.gpg pdiffs=no lang=en,de] http://archive.ubuntu.com/ubuntu/ noble-updates main universe"
+		);
+
+		let entry = match SourceEntry::from_line(&lines[1]) {
+			Ok(entry) => entry,
+			Err(err) => panic!("Failed to parse line: {}", err),
+		};
+
+		assert_eq!(entry.suites, vec!["noble-updates"]);
+		assert_eq!(entry.options, ubuntu.options);
+	}
+
+	#[test]
+	fn sources_embedded_key() {
+		let data = "Types: deb
+URIs: https://example.com/debian
+Suites: stable
+Components: main
+Enabled: false
+Signed-By:
+ -----BEGIN PGP PUBLIC KEY BLOCK-----
+ .
+ mQINBGRlbXBsZQEQAKxhbXBsZQ==
+ =abcd
+ -----END PGP PUBLIC KEY BLOCK-----
+";
+
+		let sources = Sources::from(data);
+		assert!(sources.errors.is_empty());
+
+		let entry = &sources.entries[0];
+		assert!(!entry.enabled);
+		assert_eq!(
+			entry.signed_by.as_deref(),
+			Some("-----BEGIN PGP PUBLIC KEY BLOCK-----\n\nmQINBGRlbXBsZQEQAKxhbXBsZQ==\n=abcd\n-----END PGP PUBLIC KEY BLOCK-----")
+		);
+
+		// The key's empty line is escaped so the stanza stays whole
+		assert_eq!(
+			sources.to_deb822(),
+			data.replace("Enabled: false", "Enabled: no")
+		);
+		assert_eq!(Sources::from(&sources.to_deb822()).entries, sources.entries);
+
+		let err = entry.to_lines().unwrap_err();
+		assert_eq!(err.context().field.as_deref(), Some("Signed-By"));
+		assert!(sources.to_list().is_err());
+	}
+}
@@ -0,0 +1,212 @@
+use crate::{
+	deb822::{Deb822, Paragraph},
+	errors::{APTError, ErrorContext, ParseError},
+	make_array,
+	version::Version,
+};
+use std::error::Error;
+
+// A struct read from the fields of a stanza, usually through
+// #[derive(FromStanza)] with the derive feature
+pub trait FromStanza: Sized {
+	fn from_paragraph(paragraph: &Paragraph) -> Result<Self, APTError>;
+
+	fn from_stanza(data: &str) -> Result<Self, APTError> {
+		Self::from_paragraph(&Paragraph::from(data)?)
+	}
+}
+
+// A value that a single field can be parsed into
+pub trait FromField: Sized {
+	fn from_field(value: &str) -> Result<Self, ParseError>;
+}
+
+impl FromField for String {
+	fn from_field(value: &str) -> Result<String, ParseError> {
+		Ok(value.to_string())
+	}
+}
+
+// Boolean fields are "yes" or "no", like Essential
+impl FromField for bool {
+	fn from_field(value: &str) -> Result<bool, ParseError> {
+		match value {
+			"yes" => Ok(true),
+			"no" => Ok(false),
+			_ => Err(ParseError::value("Expected \"yes\" or \"no\"", value)),
+		}
+	}
+}
+
+// Comma separated lists, like Tag or Depends
+impl FromField for Vec<String> {
+	fn from_field(value: &str) -> Result<Vec<String>, ParseError> {
+		Ok(make_array(Some(&value.to_string())).unwrap_or_default())
+	}
+}
+
+impl FromField for Version {
+	fn from_field(value: &str) -> Result<Version, ParseError> {
+		Version::from(value)
+			.map_err(|err| ParseError::value("Invalid version", value).with_source(err))
+	}
+}
+
+macro_rules! from_field_number {
+	($($type:ty),*) => {
+		$(
+			impl FromField for $type {
+				fn from_field(value: &str) -> Result<$type, ParseError> {
+					value
+						.parse::<$type>()
+						.map_err(|err| ParseError::value("Invalid number", value).with_source(err))
+				}
+			}
+		)*
+	};
+}
+
+from_field_number!(i32, i64, u32, u64, usize);
+
+// Reads a field with a parser, returning None when the stanza doesn't have it
+// Parse errors point at the field, with whatever the parser returned as their
+// source
+pub fn parse_field<T, E, F>(
+	paragraph: &Paragraph,
+	key: &str,
+	parser: F,
+) -> Result<Option<T>, APTError>
+where
+	E: Error + Send + Sync + 'static,
+	F: Fn(&str) -> Result<T, E>,
+{
+	let field = match paragraph.get(key) {
+		Some(field) => field,
+		None => return Ok(None),
+	};
+
+	let text = field.text();
+	match parser(&text) {
+		Ok(value) => Ok(Some(value)),
+		Err(err) => Err(APTError::ParseError(
+			ParseError::new(&format!("Invalid {}", field.name))
+				.with_context(ErrorContext {
+					offset: Some(field.offset),
+					line: Some(field.line),
+					column: Some(1),
+					field: Some(field.name.clone()),
+					value: Some(text),
+					..Default::default()
+				})
+				.with_source(err),
+		)),
+	}
+}
+
+impl Deb822 {
+	// Reads every paragraph as a T, marking errors with their stanza
+	pub fn parse_as<T: FromStanza>(&self) -> Vec<Result<T, APTError>> {
+		self.paragraphs
+			.iter()
+			.enumerate()
+			.map(|(index, paragraph)| {
+				T::from_paragraph(paragraph).map_err(|mut err| {
+					err.context_mut().stanza = Some(index);
+					err
+				})
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::FromStanza;
+	use crate::{deb822::Deb822, errors::APTError, version::Version};
+	use apt_parser_derive::FromStanza;
+	use std::fs::read_to_string;
+
+	#[derive(FromStanza)]
+	struct Tweak {
+		package: String,
+		version: Version,
+		name: Option<String>,
+		author: Option<String>,
+		icon: Option<String>,
+		depiction: Option<String>,
+		#[stanza(rename = "SileoDepiction")]
+		sileo_depiction: Option<String>,
+		header: Option<String>,
+		#[stanza(default)]
+		tag: Vec<String>,
+		installed_size: Option<u64>,
+		#[stanza(with = "parse_size")]
+		size: u64,
+	}
+
+	fn parse_size(value: &str) -> Result<u64, std::num::ParseIntError> {
+		value.trim().parse()
+	}
+
+	#[test]
+	fn stanza_derive() {
+		let data = match read_to_string("./test/chariz.packages") {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let document = Deb822::from(&data).unwrap();
+		let tweaks = document
+			.parse_as::<Tweak>()
+			.into_iter()
+			.collect::<Result<Vec<Tweak>, APTError>>()
+			.unwrap();
+
+		assert_eq!(tweaks.len(), 415);
+		assert!(tweaks.iter().all(|tweak| tweak.sileo_depiction.is_some()));
+		assert_eq!(
+			tweaks.iter().filter(|tweak| tweak.name.is_some()).count(),
+			document
+				.iter()
+				.filter(|paragraph| paragraph.get("name").is_some())
+				.count()
+		);
+
+		let tweak = &tweaks[0];
+		assert_eq!(tweak.package, "arpoison");
+		assert_eq!(tweak.version.to_string(), "0.7");
+		assert_eq!(tweak.name, None);
+		assert_eq!(
+			tweak.author.as_deref(),
+			Some("MidnightChips <midnightchips@gmail.com>")
+		);
+		assert_eq!(tweak.icon, None);
+		assert_eq!(
+			tweak.depiction.as_deref(),
+			Some("https://chariz.com/get/arpoison")
+		);
+		assert_eq!(tweak.header, None);
+		assert_eq!(
+			tweak.tag,
+			vec!["role::developer", "compatible_min::ios14.0"]
+		);
+		assert_eq!(tweak.installed_size, Some(88));
+		assert_eq!(tweak.size, 9618);
+		assert_eq!(tweaks[2].name.as_deref(), Some("Eyeplugs"));
+
+		// Missing keys and values that don't parse are errors
+		match Tweak::from_stanza("Package: a\nVersion: 1.0\n") {
+			Err(APTError::MissingKeyError(err)) => assert_eq!(err.key, "Size"),
+			_ => panic!("Expected a missing Size"),
+		}
+
+		match Tweak::from_stanza("Package: a\nVersion: 1.0\nSize: 1\nInstalled-Size: big\n") {
+			Err(err) => {
+				assert_eq!(err.context().field.as_deref(), Some("Installed-Size"));
+				assert_eq!(err.context().line, Some(4));
+				assert!(std::error::Error::source(&err).is_some());
+			}
+			_ => panic!("Expected an invalid Installed-Size"),
+		}
+	}
+}
@@ -0,0 +1,197 @@
+use crate::{
+	control::Control,
+	errors::{APTError, MissingKeyError, ParseError},
+	limits::Limits,
+	normalize,
+	packages::Package,
+	split_stanzas,
+};
+use rayon::prelude::*;
+use std::slice::Iter;
+
+// An entry of /var/lib/dpkg/status
+#[derive(Debug, Clone)]
+pub struct InstalledPackage {
+	pub package: Package,
+	pub want: String,
+	pub flag: String,
+	pub state: String,
+}
+
+impl InstalledPackage {
+	pub fn from(data: &str) -> Result<InstalledPackage, APTError> {
+		let control = Control::from(data)?;
+
+		// Status is always "<want> <flag> <state>", e.g. "install ok installed"
+		let status = match control.map.get("Status") {
+			Some(status) => status.split_whitespace().collect::<Vec<&str>>(),
+			None => return Err(APTError::MissingKeyError(MissingKeyError::new("Status"))),
+		};
+
+		if status.len() != 3 {
+			return Err(APTError::ParseError(
+				ParseError::field(
+					"Status must be \"<want> <flag> <state>\"",
+					"Status",
+					&status.join(" "),
+				)
+				.locate(data),
+			));
+		}
+
+		let (want, flag, state) = (
+			status[0].to_string(),
+			status[1].to_string(),
+			status[2].to_string(),
+		);
+
+		Ok(InstalledPackage {
+			package: Package::from_control(control, String::new(), 0),
+			want,
+			flag,
+			state,
+		})
+	}
+
+	// Removed packages that left their configuration files behind are still
+	// listed, but they don't satisfy anything
+	pub fn is_installed(&self) -> bool {
+		self.state != "not-installed" && self.state != "config-files"
+	}
+
+	pub fn get(&self, key: &str) -> Option<&str> {
+		self.package.get(key)
+	}
+}
+
+pub struct DpkgStatus {
+	pub(crate) packages: Vec<InstalledPackage>,
+	pub errors: Vec<APTError>,
+}
+
+impl DpkgStatus {
+	pub fn from(data: &str) -> DpkgStatus {
+		DpkgStatus::from_limited(data, &Limits::default())
+	}
+
+	// Limits are applied the same way as Packages::from_limited
+	pub fn from_limited(data: &str, limits: &Limits) -> DpkgStatus {
+		let failed = |err: APTError| DpkgStatus {
+			packages: Vec::new(),
+			errors: vec![err],
+		};
+
+		if let Err(err) = limits.check_input(data) {
+			return failed(err);
+		}
+
+		let binding = normalize(data);
+		let stanzas = split_stanzas(&binding)
+			.take(limits.stanzas.saturating_add(1))
+			.collect::<Vec<(usize, usize, &str)>>();
+
+		if let Err(err) = limits.check_stanzas(stanzas.len()) {
+			return failed(err);
+		}
+
+		let values = stanzas
+			.into_par_iter()
+			.enumerate()
+			.filter(|(_, (_, _, stanza))| !stanza.trim().is_empty())
+			.map(|(index, (offset, line, stanza))| {
+				limits
+					.check_stanza(stanza)
+					.and_then(|_| InstalledPackage::from(stanza))
+					.map_err(|err| err.in_stanza(index, offset, line))
+			})
+			.collect::<Vec<Result<InstalledPackage, APTError>>>();
+
+		let mut packages = Vec::new();
+		let mut errors = Vec::new();
+
+		for value in values {
+			match value {
+				Ok(package) => packages.push(package),
+				Err(err) => errors.push(err),
+			}
+		}
+
+		DpkgStatus { packages, errors }
+	}
+
+	pub fn len(&self) -> usize {
+		self.packages.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.packages.is_empty()
+	}
+
+	pub fn iter(&self) -> Iter<'_, InstalledPackage> {
+		self.packages.iter()
+	}
+
+	// Only the packages that are actually on the system
+	pub fn installed(&self) -> impl Iterator<Item = &InstalledPackage> {
+		self.packages
+			.iter()
+			.filter(|package| package.is_installed())
+	}
+
+	pub fn get(&self, name: &str) -> Option<&InstalledPackage> {
+		self.installed()
+			.find(|installed| installed.package.package == name)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::DpkgStatus;
+
+	#[test]
+	fn status_entries() {
+		let status = DpkgStatus::from(
+			"Package: base-files
+Essential: yes
+Status: install ok installed
+Priority: required
+Section: admin
+Installed-Size: 394
+Maintainer: Ubuntu Developers <ubuntu-devel-discuss@lists.ubuntu.com>
+Architecture: amd64
+Version: 12ubuntu4
+Conffiles:
+ /etc/debian_version 0bb2e5a6b2b8c9f1e5e8f6f4e6c0e1f8
+Description: Debian base system miscellaneous files
+
+Package: oldtool
+Status: deinstall ok config-files
+Architecture: amd64
+Version: 0.9
+Description: A tool that was removed",
+		);
+
+		assert!(status.errors.is_empty());
+		assert_eq!(status.len(), 2);
+		assert_eq!(status.installed().count(), 1);
+
+		let base_files = match status.get("base-files") {
+			Some(package) => package,
+			None => panic!("Missing base-files"),
+		};
+
+		assert_eq!(base_files.want, "install");
+		assert_eq!(base_files.flag, "ok");
+		assert_eq!(base_files.state, "installed");
+		assert_eq!(base_files.package.is_essential, Some(true));
+		assert_eq!(base_files.package.filename, "");
+		assert!(base_files.get("Conffiles").is_some());
+
+		assert!(status.get("oldtool").is_none());
+		assert!(
+			DpkgStatus::from("Package: broken\nVersion: 1.0\nArchitecture: all")
+				.errors
+				.len() == 1
+		);
+	}
+}
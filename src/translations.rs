@@ -0,0 +1,284 @@
+use crate::{
+	errors::{APTError, MissingKeyError},
+	limits::Limits,
+	normalize, parse_kv, split_stanzas,
+};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Translation {
+	pub package: String,
+	pub description_md5sum: String,
+	pub language: String,
+	pub description: String,
+}
+
+impl Translation {
+	pub fn from(data: &str) -> Result<Translation, APTError> {
+		let map = match parse_kv(data) {
+			Ok(map) => map,
+			Err(err) => return Err(APTError::KVError(err)),
+		};
+
+		let package = match map.get("Package") {
+			Some(package) => package,
+			None => return Err(APTError::MissingKeyError(MissingKeyError::new("Package"))),
+		};
+
+		let description_md5sum = match map.get("Description-md5") {
+			Some(md5sum) => md5sum,
+			None => {
+				return Err(APTError::MissingKeyError(MissingKeyError::new(
+					"Description-md5",
+				)))
+			}
+		};
+
+		// Translation files carry a single Description-<lang> field per stanza
+		let description = map.iter().find(|(key, _)| {
+			let key = key.to_lowercase();
+			key.starts_with("description-") && key != "description-md5"
+		});
+
+		let (language, description) = match description {
+			Some((key, value)) => (key["Description-".len()..].to_string(), value),
+			None => {
+				return Err(APTError::MissingKeyError(MissingKeyError::new(
+					"Description",
+				)))
+			}
+		};
+
+		Ok(Translation {
+			package: package.to_string(),
+			description_md5sum: description_md5sum.to_string(),
+			language,
+			description: description.to_string(),
+		})
+	}
+}
+
+pub struct Translations {
+	// Keyed by Description-md5 and then by package, so lookups can borrow
+	pub(crate) translations: HashMap<String, HashMap<String, Vec<Translation>>>,
+	pub errors: Vec<APTError>,
+}
+
+impl Translations {
+	pub fn from(data: &str) -> Translations {
+		Translations::from_limited(data, &Limits::default())
+	}
+
+	// Limits are applied the same way as Packages::from_limited
+	pub fn from_limited(data: &str, limits: &Limits) -> Translations {
+		let failed = |err: APTError| Translations {
+			translations: HashMap::new(),
+			errors: vec![err],
+		};
+
+		if let Err(err) = limits.check_input(data) {
+			return failed(err);
+		}
+
+		let binding = normalize(data);
+		let stanzas = split_stanzas(&binding)
+			.take(limits.stanzas.saturating_add(1))
+			.collect::<Vec<(usize, usize, &str)>>();
+
+		if let Err(err) = limits.check_stanzas(stanzas.len()) {
+			return failed(err);
+		}
+
+		let values = stanzas
+			.into_par_iter()
+			.enumerate()
+			.map(|(index, (offset, line, stanza))| {
+				limits
+					.check_stanza(stanza)
+					.and_then(|_| Translation::from(stanza))
+					.map_err(|err| err.in_stanza(index, offset, line))
+			})
+			.collect::<Vec<Result<Translation, APTError>>>();
+
+		let mut translations = Translations {
+			translations: HashMap::new(),
+			errors: Vec::new(),
+		};
+
+		for value in values {
+			match value {
+				Ok(translation) => translations.insert(translation),
+				Err(err) => translations.errors.push(err),
+			}
+		}
+
+		translations
+	}
+
+	fn insert(&mut self, translation: Translation) {
+		let entries = self
+			.translations
+			.entry(translation.description_md5sum.clone())
+			.or_default()
+			.entry(translation.package.clone())
+			.or_default();
+		match entries
+			.iter_mut()
+			.find(|entry| entry.language == translation.language)
+		{
+			Some(entry) => *entry = translation,
+			None => entries.push(translation),
+		}
+	}
+
+	// Combines the contents of another Translation-<lang> file into this one
+	pub fn merge(&mut self, other: Translations) {
+		for (_, packages) in other.translations {
+			for (_, entries) in packages {
+				for translation in entries {
+					self.insert(translation);
+				}
+			}
+		}
+
+		self.errors.extend(other.errors);
+	}
+
+	pub fn get(&self, package: &str, md5sum: &str, language: &str) -> Option<&Translation> {
+		match self
+			.translations
+			.get(md5sum)
+			.and_then(|packages| packages.get(package))
+		{
+			Some(entries) => entries.iter().find(|entry| entry.language == language),
+			None => None,
+		}
+	}
+
+	// Walks the language chain in order, trying "de" after "de_DE"
+	pub fn find(&self, package: &str, md5sum: &str, languages: &[&str]) -> Option<&Translation> {
+		for language in languages {
+			if let Some(translation) = self.get(package, md5sum, language) {
+				return Some(translation);
+			}
+
+			if let Some((base, _)) = language.split_once('_') {
+				if let Some(translation) = self.get(package, md5sum, base) {
+					return Some(translation);
+				}
+			}
+		}
+
+		None
+	}
+
+	pub fn len(&self) -> usize {
+		self.translations
+			.values()
+			.flat_map(HashMap::values)
+			.map(Vec::len)
+			.sum()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.translations.is_empty()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Translations;
+	use crate::Packages;
+	use std::fs::read_to_string;
+
+	fn read_translations(file: &str) -> Translations {
+		let data = match read_to_string(file) {
+			Ok(data) => data,
+			Err(err) => panic!("Failed to read file: {}", err),
+		};
+
+		let translations = Translations::from(&data);
+		if !translations.errors.is_empty() {
+			panic!("Failed to parse translations: {:?}", translations.errors);
+		}
+
+		translations
+	}
+
+	#[test]
+	fn translations_jammy() {
+		let translations = read_translations("./test/jammy.translation-en");
+		assert_eq!(translations.len(), 3);
+
+		let translation =
+			match translations.get("accountsservice", "8aeed0a03c7cd494f0c4b8d977483d7e", "en") {
+				Some(translation) => translation,
+				None => panic!("Missing translation for accountsservice"),
+			};
+
+		assert_eq!(translation.language, "en");
+		assert_eq!(translation.description, "query and manipulate user account information\nThe AccountService project provides a set of D-Bus interfaces for querying and manipulating user account information and an implementation of these interfaces, based on the useradd, usermod and userdel commands.");
+		assert_eq!(
+			translations.get("accountsservice", "8aeed0a03c7cd494f0c4b8d977483d7e", "de"),
+			None
+		);
+	}
+
+	#[test]
+	fn translations_localize() {
+		let mut translations = read_translations("./test/jammy.translation-en");
+		translations.merge(read_translations("./test/jammy.translation-de"));
+		assert_eq!(translations.len(), 5);
+
+		let mut packages = Packages::from(
+			"Package: accountsservice
+Architecture: amd64
+Version: 0.6.55-3ubuntu2
+Filename: pool/main/a/accountsservice/accountsservice_0.6.55-3ubuntu2_amd64.deb
+Size: 66304
+Description: query and manipulate user account information
+Description-md5: 8aeed0a03c7cd494f0c4b8d977483d7e
+
+Package: acl
+Architecture: amd64
+Version: 2.3.1-1
+Filename: pool/main/a/acl/acl_2.3.1-1_amd64.deb
+Size: 38682
+Description: access control list - utilities
+Description-md5: b83fc53f6059dbeb88f11752e70968e7
+
+Package: arpoison
+Architecture: iphoneos-arm
+Version: 0.7
+Filename: debs/arpoison_0.7_iphoneos-arm.deb
+Size: 9618
+Description: Generates user-defined ARP packets",
+		);
+
+		assert_eq!(packages.localize(&translations, &["de_DE", "en"]), 2);
+
+		let accountsservice = &packages[0];
+		let translation = accountsservice
+			.translation
+			.as_ref()
+			.map(|x| x.language.as_str());
+		assert_eq!(translation, Some("de"));
+		assert_eq!(
+			accountsservice.localized_description(),
+			Some("Benutzerkonteninformationen abfragen und bearbeiten\nDas AccountService-Projekt stellt D-Bus-Schnittstellen zum Abfragen und Bearbeiten von Benutzerkonteninformationen bereit.")
+		);
+
+		// The German file has no entry for this checksum, so English is used
+		let acl = &packages[1];
+		let translation = acl.translation.as_ref().map(|x| x.language.as_str());
+		assert_eq!(translation, Some("en"));
+
+		let arpoison = &packages[2];
+		assert!(arpoison.translation.is_none());
+		assert_eq!(
+			arpoison.localized_description(),
+			Some("Generates user-defined ARP packets")
+		);
+	}
+}
@@ -0,0 +1,171 @@
+use crate::{errors::LimitError, release::HashKind};
+use std::{
+	error::Error,
+	fmt::{Display, Formatter},
+	fs::read,
+	io::ErrorKind,
+	path::{Component, Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum FetchError {
+	// The transport failed, e.g. a connection error or an HTTP 500
+	Transport {
+		url: String,
+		message: String,
+	},
+	NotFound(String),
+	SizeMismatch {
+		url: String,
+		expected: u64,
+		actual: u64,
+	},
+	HashMismatch {
+		url: String,
+		kind: HashKind,
+		expected: String,
+		actual: String,
+	},
+	Decompress {
+		url: String,
+		message: String,
+	},
+	Encoding(String),
+	Parse {
+		url: String,
+		message: String,
+	},
+	// A path from the Release file or a PDiff Index that could escape the
+	// repository, like "../../etc/passwd"
+	UnsafePath {
+		path: String,
+		message: String,
+	},
+	// A file that decompressed to more than the loader's Limits allow
+	Limit {
+		url: String,
+		error: LimitError,
+	},
+}
+
+impl Error for FetchError {}
+
+impl Display for FetchError {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			FetchError::Transport { url, message } => {
+				write!(formatter, "Failed to fetch {}: {}", url, message)
+			}
+			FetchError::NotFound(url) => write!(formatter, "{} does not exist", url),
+			FetchError::SizeMismatch {
+				url,
+				expected,
+				actual,
+			} => write!(
+				formatter,
+				"Size mismatch for {}: expected {} bytes, got {}",
+				url, expected, actual
+			),
+			FetchError::HashMismatch {
+				url,
+				kind,
+				expected,
+				actual,
+			} => write!(
+				formatter,
+				"{} mismatch for {}: expected {}, got {}",
+				kind.field(),
+				url,
+				expected,
+				actual
+			),
+			FetchError::Decompress { url, message } => {
+				write!(formatter, "Failed to decompress {}: {}", url, message)
+			}
+			FetchError::Encoding(url) => write!(formatter, "{} is not valid UTF-8", url),
+			FetchError::Parse { url, message } => {
+				write!(formatter, "Failed to parse {}: {}", url, message)
+			}
+			FetchError::UnsafePath { path, message } => {
+				write!(formatter, "Refusing to fetch {:?}: {}", path, message)
+			}
+			FetchError::Limit { url, error } => {
+				write!(formatter, "Failed to load {}: {}", url, error)
+			}
+		}
+	}
+}
+
+// Anything that can download a URL, like an HTTP client or a local mirror
+// Files that don't exist are Ok(None), since InRelease and by-hash paths
+// are allowed to be missing
+pub trait Transport {
+	fn fetch(&self, url: &str) -> Result<Option<Vec<u8>>, FetchError>;
+}
+
+// Reads file:// URLs, and optionally maps every other URL into a mirror
+// directory laid out as <root>/<host>/<path>, like apt-mirror does
+#[derive(Debug, Clone, Default)]
+pub struct LocalTransport {
+	pub root: Option<PathBuf>,
+}
+
+impl LocalTransport {
+	pub fn new() -> LocalTransport {
+		LocalTransport { root: None }
+	}
+
+	pub fn mirror<P: Into<PathBuf>>(root: P) -> LocalTransport {
+		LocalTransport {
+			root: Some(root.into()),
+		}
+	}
+
+	pub fn path(&self, url: &str) -> Result<PathBuf, FetchError> {
+		let invalid = |message: &str| FetchError::Transport {
+			url: url.to_string(),
+			message: message.to_string(),
+		};
+
+		let path = match url.strip_prefix("file://") {
+			Some(path) => PathBuf::from(path),
+			None => {
+				let root = match &self.root {
+					Some(root) => root,
+					None => return Err(invalid("Only file:// URLs are supported")),
+				};
+
+				let location = match url.split_once("://") {
+					Some((_, location)) => location,
+					None => return Err(invalid("Missing URL scheme")),
+				};
+
+				root.join(location.trim_start_matches('/'))
+			}
+		};
+
+		// Mirror paths must not escape their root
+		if Path::new(&path)
+			.components()
+			.any(|component| component == Component::ParentDir)
+		{
+			return Err(invalid("Paths can't contain \"..\""));
+		}
+
+		Ok(path)
+	}
+}
+
+impl Transport for LocalTransport {
+	fn fetch(&self, url: &str) -> Result<Option<Vec<u8>>, FetchError> {
+		let path = self.path(url)?;
+		match read(&path) {
+			Ok(data) => Ok(Some(data)),
+			Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+			Err(err) => Err(FetchError::Transport {
+				url: url.to_string(),
+				message: err.to_string(),
+			}),
+		}
+	}
+}
@@ -0,0 +1,194 @@
+use crate::{
+	index::PackageIndex,
+	packages::{address, Package},
+	relation::{Relation, RelationKind},
+	satisfy::{applies_to, architecture_matches},
+};
+use std::{cmp::Ordering, collections::HashMap};
+
+// A relation of a package resolved to the ids of the packages satisfying it
+pub(crate) struct Requirement {
+	pub relation: Relation,
+	pub candidates: Vec<usize>,
+}
+
+// A package that can't be installed alongside the owner of the restriction
+pub(crate) struct Restriction {
+	pub kind: RelationKind,
+	pub target: usize,
+}
+
+// The packages of a PackageIndex numbered by position, with their
+// relationships resolved once so searches only deal with ids
+pub(crate) struct Universe<'a> {
+	pub packages: Vec<&'a Package>,
+	pub requirements: Vec<Vec<Requirement>>,
+	pub restrictions: Vec<Vec<Restriction>>,
+	pub malformed: Vec<Option<RelationKind>>,
+	// The (owner, requirement) pairs each package is a candidate for
+	pub dependents: Vec<Vec<(usize, usize)>>,
+	// The packages whose Conflicts or Breaks name each package
+	pub restricted_by: Vec<Vec<(usize, RelationKind)>>,
+	ids: HashMap<usize, usize>,
+}
+
+impl<'a> Universe<'a> {
+	pub fn new(
+		index: &PackageIndex<'a>,
+		native_architecture: &str,
+		kinds: &[RelationKind],
+		order: &dyn Fn(&Package, &Package) -> Ordering,
+	) -> Universe<'a> {
+		let packages = index.packages().to_vec();
+		let ids = packages
+			.iter()
+			.enumerate()
+			.map(|(id, package)| (address(package), id))
+			.collect::<HashMap<usize, usize>>();
+
+		let mut universe = Universe {
+			requirements: Vec::with_capacity(packages.len()),
+			restrictions: Vec::with_capacity(packages.len()),
+			malformed: vec![None; packages.len()],
+			dependents: vec![Vec::new(); packages.len()],
+			restricted_by: vec![Vec::new(); packages.len()],
+			packages,
+			ids,
+		};
+
+		for id in 0..universe.packages.len() {
+			let package = universe.packages[id];
+			let architecture = match package.architecture.as_str() {
+				"all" => native_architecture,
+				architecture => architecture,
+			};
+
+			let mut requirements = Vec::new();
+			for kind in kinds {
+				let relations = match package.relations(*kind) {
+					Ok(relations) => relations,
+					Err(_) => {
+						universe.malformed[id] = Some(*kind);
+						continue;
+					}
+				};
+
+				for relation in relations {
+					let mut applies = false;
+					let mut candidates = Vec::new();
+
+					for dependency in &relation.alternatives {
+						if !applies_to(dependency, architecture) {
+							continue;
+						}
+
+						applies = true;
+						let mut satisfiers = index
+							.satisfiers(dependency, architecture)
+							.iter()
+							.filter_map(|provider| universe.id(provider.package))
+							.collect::<Vec<usize>>();
+
+						// The order only applies within each alternative
+						satisfiers
+							.sort_by(|a, b| order(universe.packages[*a], universe.packages[*b]));
+
+						for satisfier in satisfiers {
+							if !candidates.contains(&satisfier) {
+								candidates.push(satisfier);
+							}
+						}
+					}
+
+					if applies {
+						requirements.push(Requirement {
+							relation,
+							candidates,
+						});
+					}
+				}
+			}
+
+			let mut restrictions = Vec::new();
+			for kind in [RelationKind::Conflicts, RelationKind::Breaks] {
+				let relations = match package.relations(kind) {
+					Ok(relations) => relations,
+					Err(_) => {
+						universe.malformed[id] = Some(kind);
+						continue;
+					}
+				};
+
+				for relation in relations {
+					for dependency in &relation.alternatives {
+						for provider in index.candidates(dependency) {
+							// Unqualified conflicts apply to every architecture
+							if dependency.architecture.is_some()
+								&& !architecture_matches(dependency, provider.package, architecture)
+							{
+								continue;
+							}
+
+							let target = match universe.id(provider.package) {
+								Some(target) => target,
+								None => continue,
+							};
+
+							// Packages routinely conflict with what they provide
+							if target == id {
+								continue;
+							}
+
+							restrictions.push(Restriction { kind, target });
+						}
+					}
+				}
+			}
+
+			universe.requirements.push(requirements);
+			universe.restrictions.push(restrictions);
+		}
+
+		for owner in 0..universe.requirements.len() {
+			for (requirement, entry) in universe.requirements[owner].iter().enumerate() {
+				for candidate in &entry.candidates {
+					universe.dependents[*candidate].push((owner, requirement));
+				}
+			}
+
+			for restriction in &universe.restrictions[owner] {
+				universe.restricted_by[restriction.target].push((owner, restriction.kind));
+			}
+		}
+
+		universe
+	}
+
+	pub fn id(&self, package: &Package) -> Option<usize> {
+		self.ids.get(&address(package)).copied()
+	}
+
+	pub fn len(&self) -> usize {
+		self.packages.len()
+	}
+
+	// Returns the restriction preventing the two packages from being installed
+	// together, checking both directions
+	pub fn clash(&self, left: usize, right: usize) -> Option<(usize, &Restriction)> {
+		if let Some(restriction) = self.restrictions[left].iter().find(|x| x.target == right) {
+			return Some((left, restriction));
+		}
+
+		if let Some(restriction) = self.restrictions[right].iter().find(|x| x.target == left) {
+			return Some((right, restriction));
+		}
+
+		None
+	}
+
+	// Only one version of a package name can be installed per architecture
+	pub fn slot(&self, id: usize) -> (&'a str, &'a str) {
+		let package = self.packages[id];
+		(&package.package, &package.architecture)
+	}
+}
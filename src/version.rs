@@ -0,0 +1,264 @@
+use crate::errors::{APTError, ParseError};
+use std::{
+	cmp::Ordering,
+	fmt::{Display, Formatter, Result},
+};
+
+#[derive(Debug, Clone)]
+pub struct Version {
+	pub epoch: u64,
+	pub upstream_version: String,
+	pub debian_revision: Option<String>,
+}
+
+impl Version {
+	pub fn from(data: &str) -> std::result::Result<Version, APTError> {
+		let data = data.trim();
+
+		let (epoch, remainder) = match data.split_once(':') {
+			Some((epoch, remainder)) => match epoch.parse::<u64>() {
+				Ok(epoch) => (epoch, remainder),
+				Err(err) => {
+					return Err(APTError::ParseError(
+						ParseError::value("Invalid epoch", data).with_source(err),
+					))
+				}
+			},
+			None => (0, data),
+		};
+
+		let (upstream_version, debian_revision) = match remainder.rsplit_once('-') {
+			Some((upstream, revision)) => (upstream, Some(revision)),
+			None => (remainder, None),
+		};
+
+		// dpkg only warns about these, but they can never compare sensibly
+		if upstream_version.is_empty()
+			|| !upstream_version
+				.chars()
+				.all(|x| x.is_ascii_alphanumeric() || ".+~-:".contains(x))
+		{
+			return Err(APTError::ParseError(ParseError::value(
+				"Invalid upstream version",
+				data,
+			)));
+		}
+
+		if let Some(revision) = debian_revision {
+			if revision.is_empty()
+				|| !revision
+					.chars()
+					.all(|x| x.is_ascii_alphanumeric() || ".+~".contains(x))
+			{
+				return Err(APTError::ParseError(ParseError::value(
+					"Invalid Debian revision",
+					data,
+				)));
+			}
+		}
+
+		Ok(Version {
+			epoch,
+			upstream_version: upstream_version.to_string(),
+			debian_revision: debian_revision.map(|x| x.to_string()),
+		})
+	}
+}
+
+impl Display for Version {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
+		if self.epoch > 0 {
+			write!(formatter, "{}:", self.epoch)?;
+		}
+
+		write!(formatter, "{}", self.upstream_version)?;
+		if let Some(revision) = &self.debian_revision {
+			write!(formatter, "-{}", revision)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl Ord for Version {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.epoch
+			.cmp(&other.epoch)
+			.then_with(|| compare_fragment(&self.upstream_version, &other.upstream_version))
+			.then_with(|| {
+				compare_fragment(
+					self.debian_revision.as_deref().unwrap_or(""),
+					other.debian_revision.as_deref().unwrap_or(""),
+				)
+			})
+	}
+}
+
+impl PartialOrd for Version {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl PartialEq for Version {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl Eq for Version {}
+
+// Compares two raw version strings. Invalid versions sort before every valid
+// one and by plain string comparison among themselves, so the order stays
+// total for sorting even when a repository has broken versions
+pub fn compare_versions(left: &str, right: &str) -> Ordering {
+	match (Version::from(left), Version::from(right)) {
+		(Ok(left), Ok(right)) => left.cmp(&right),
+		(Ok(_), Err(_)) => Ordering::Greater,
+		(Err(_), Ok(_)) => Ordering::Less,
+		(Err(_), Err(_)) => left.cmp(right),
+	}
+}
+
+// The sort weight of a non-digit character, as defined by dpkg
+// '~' sorts before everything (even the end of the string), letters before
+// the remaining symbols
+fn order(character: Option<u8>) -> i32 {
+	match character {
+		None => 0,
+		Some(b'~') => -1,
+		Some(x) if x.is_ascii_digit() => 0,
+		Some(x) if x.is_ascii_alphabetic() => i32::from(x),
+		Some(x) => i32::from(x) + 256,
+	}
+}
+
+fn compare_fragment(left: &str, right: &str) -> Ordering {
+	let left = left.as_bytes();
+	let right = right.as_bytes();
+	let (mut x, mut y) = (0, 0);
+
+	while x < left.len() || y < right.len() {
+		while (x < left.len() && !left[x].is_ascii_digit())
+			|| (y < right.len() && !right[y].is_ascii_digit())
+		{
+			let left_order = order(left.get(x).copied());
+			let right_order = order(right.get(y).copied());
+
+			if left_order != right_order {
+				return left_order.cmp(&right_order);
+			}
+
+			x += 1;
+			y += 1;
+		}
+
+		while x < left.len() && left[x] == b'0' {
+			x += 1;
+		}
+
+		while y < right.len() && right[y] == b'0' {
+			y += 1;
+		}
+
+		let mut first_difference = Ordering::Equal;
+		while x < left.len()
+			&& y < right.len()
+			&& left[x].is_ascii_digit()
+			&& right[y].is_ascii_digit()
+		{
+			if first_difference == Ordering::Equal {
+				first_difference = left[x].cmp(&right[y]);
+			}
+
+			x += 1;
+			y += 1;
+		}
+
+		if x < left.len() && left[x].is_ascii_digit() {
+			return Ordering::Greater;
+		}
+
+		if y < right.len() && right[y].is_ascii_digit() {
+			return Ordering::Less;
+		}
+
+		if first_difference != Ordering::Equal {
+			return first_difference;
+		}
+	}
+
+	Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{compare_versions, Version};
+	use std::cmp::Ordering;
+
+	#[test]
+	fn version_parse() {
+		let version = match Version::from("1:13.0-54") {
+			Ok(version) => version,
+			Err(err) => panic!("Failed to parse version: {}", err),
+		};
+
+		assert_eq!(version.epoch, 1);
+		assert_eq!(version.upstream_version, "13.0");
+		assert_eq!(version.debian_revision, Some("54".to_owned()));
+		assert_eq!(version.to_string(), "1:13.0-54");
+
+		let version = match Version::from("1:3.5~+rc1-3~exp1") {
+			Ok(version) => version,
+			Err(err) => panic!("Failed to parse version: {}", err),
+		};
+
+		assert_eq!(version.upstream_version, "3.5~+rc1");
+		assert_eq!(version.debian_revision, Some("3~exp1".to_owned()));
+
+		assert!(Version::from("a:1.0").is_err());
+		assert!(Version::from("1.0-").is_err());
+		assert!(Version::from("").is_err());
+	}
+
+	#[test]
+	fn version_compare() {
+		assert_eq!(compare_versions("0.7", "0.7-1"), Ordering::Less);
+		assert_eq!(compare_versions("1.0", "0:1.0"), Ordering::Equal);
+		assert_eq!(compare_versions("1.0~rc1", "1.0"), Ordering::Less);
+		assert_eq!(compare_versions("1.0", "1.0+b1"), Ordering::Less);
+		assert_eq!(compare_versions("1.0a", "1.0+"), Ordering::Less);
+		assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+		assert_eq!(compare_versions("1.001", "1.1"), Ordering::Equal);
+		assert_eq!(compare_versions("1:0.1", "9.9"), Ordering::Greater);
+		assert_eq!(compare_versions("2.34-0ubuntu3", "2.34"), Ordering::Greater);
+		assert_eq!(compare_versions("1.6.2-3", "99.0"), Ordering::Less);
+
+		// Invalid versions sort first, so mixed lists still have a total order
+		let mut versions = vec!["10", "a:1.0", "2.0", "1.0-", "1.0", "", "1:0.1"];
+		for (a, b, c) in versions
+			.iter()
+			.flat_map(|a| versions.iter().map(move |b| (a, b)))
+			.flat_map(|(a, b)| versions.iter().map(move |c| (a, b, c)))
+		{
+			if compare_versions(a, b) != Ordering::Greater
+				&& compare_versions(b, c) != Ordering::Greater
+			{
+				assert_ne!(
+					compare_versions(a, c),
+					Ordering::Greater,
+					"{} {} {}",
+					a,
+					b,
+					c
+				);
+			}
+		}
+
+		versions.sort_by(|a, b| compare_versions(a, b));
+		assert_eq!(
+			versions,
+			vec!["", "1.0-", "a:1.0", "1.0", "2.0", "10", "1:0.1"]
+		);
+	}
+}